@@ -10,6 +10,7 @@ use tokio::fs;
 use tracing::{debug, info};
 
 use crate::registry::{ServiceCapability, ServiceCommand, ServiceProvider, ServiceResult};
+use super::is_within;
 
 pub struct FileSystemAdapter {
     name: String,
@@ -158,7 +159,7 @@ impl FileSystemAdapter {
         let full_path = self.base_path.join(path);
         
         // Security check - ensure path is within base_path
-        if !full_path.starts_with(&self.base_path) {
+        if !is_within(&full_path, &self.base_path) {
             return Err(anyhow!("Path traversal detected"));
         }
         
@@ -184,7 +185,7 @@ impl FileSystemAdapter {
         let full_path = self.base_path.join(path);
         
         // Security check
-        if !full_path.starts_with(&self.base_path) {
+        if !is_within(&full_path, &self.base_path) {
             return Err(anyhow!("Path traversal detected"));
         }
         
@@ -211,7 +212,7 @@ impl FileSystemAdapter {
         let full_path = self.base_path.join(path);
         
         // Security check
-        if !full_path.starts_with(&self.base_path) {
+        if !is_within(&full_path, &self.base_path) {
             return Err(anyhow!("Path traversal detected"));
         }
         
@@ -240,7 +241,7 @@ impl FileSystemAdapter {
         let full_path = self.base_path.join(path);
         
         // Security check
-        if !full_path.starts_with(&self.base_path) {
+        if !is_within(&full_path, &self.base_path) {
             return Err(anyhow!("Path traversal detected"));
         }
         
@@ -303,4 +304,25 @@ mod tests {
             "Hello, World!"
         );
     }
+    
+    #[tokio::test]
+    async fn test_paths_cannot_leave_base_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut adapter = FileSystemAdapter::new(temp_dir.path().join("base"));
+        adapter.initialize().await.unwrap();
+        
+        let write_cmd = ServiceCommand {
+            tool: "writeFile".to_string(),
+            args: json!({
+                "path": "../escaped.txt",
+                "content": "Hello, World!"
+            }),
+            project_name: None,
+            role_id: None,
+            context: None,
+            store_result: None,
+        };
+        assert!(adapter.execute(write_cmd).await.is_err());
+        assert!(!temp_dir.path().join("escaped.txt").exists());
+    }
 }
@@ -6,4 +6,15 @@ pub mod terminal;
 
 pub use filesystem::FileSystemAdapter;
 pub use git::GitAdapter;
-pub use terminal::TerminalAdapter;
\ No newline at end of file
+pub use terminal::TerminalAdapter;
+
+use std::path::{Component, Path};
+
+/// Whether `path` stays inside `base`
+///
+/// `..` is refused rather than resolved, since the directories it would
+/// step out of may not exist yet.
+pub(crate) fn is_within(path: &Path, base: &Path) -> bool {
+    path.strip_prefix(base)
+        .is_ok_and(|rest| !rest.components().any(|c| c == Component::ParentDir))
+}
\ No newline at end of file
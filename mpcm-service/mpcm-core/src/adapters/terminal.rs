@@ -5,7 +5,7 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
 use tokio::sync::RwLock;
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
@@ -13,6 +13,7 @@ use serde_json::{json, Value as JsonValue};
 use tracing::{debug, info, warn};
 
 use crate::registry::{ServiceCapability, ServiceCommand, ServiceProvider, ServiceResult};
+use super::is_within;
 
 /// Running process information
 #[derive(Debug, Clone)]
@@ -22,12 +23,45 @@ struct ProcessInfo {
     working_dir: PathBuf,
 }
 
+/// Commands the terminal adapter runs unless configured otherwise
+pub const DEFAULT_ALLOWED_COMMANDS: &[&str] = &[
+    // Safe commands
+    "ls", "pwd", "echo", "cat", "grep", "find", "which",
+    "npm", "yarn", "cargo", "python", "node", "git", "make",
+];
+
+/// Whitelist shared between a terminal adapter and whoever configures it
+///
+/// Changes apply to the next command the adapter is asked to run.
+#[derive(Debug, Clone, Default)]
+pub struct AllowedCommands(Arc<std::sync::RwLock<Vec<String>>>);
+
+impl AllowedCommands {
+    /// Replace every allowed command
+    pub fn set(&self, commands: Vec<String>) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = commands;
+    }
+    
+    /// The commands currently allowed
+    pub fn get(&self) -> Vec<String> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+    
+    fn push(&self, command: String) {
+        self.0.write().unwrap_or_else(PoisonError::into_inner).push(command);
+    }
+    
+    fn contains(&self, command: &str) -> bool {
+        self.0.read().unwrap_or_else(PoisonError::into_inner).iter().any(|allowed| allowed == command)
+    }
+}
+
 pub struct TerminalAdapter {
     name: String,
     base_path: PathBuf,
     initialized: bool,
     /// Whitelist of allowed commands
-    allowed_commands: Vec<String>,
+    allowed_commands: AllowedCommands,
     /// Running processes
     processes: Arc<RwLock<HashMap<u32, ProcessInfo>>>,
 }
@@ -38,23 +72,9 @@ impl TerminalAdapter {
             name: "terminal".to_string(),
             base_path: base_path.into(),
             initialized: false,
-            allowed_commands: vec![
-                // Safe commands
-                "ls".to_string(),
-                "pwd".to_string(),
-                "echo".to_string(),
-                "cat".to_string(),
-                "grep".to_string(),
-                "find".to_string(),
-                "which".to_string(),
-                "npm".to_string(),
-                "yarn".to_string(),
-                "cargo".to_string(),
-                "python".to_string(),
-                "node".to_string(),
-                "git".to_string(),
-                "make".to_string(),
-            ],
+            allowed_commands: AllowedCommands(Arc::new(std::sync::RwLock::new(
+                DEFAULT_ALLOWED_COMMANDS.iter().map(|c| c.to_string()).collect(),
+            ))),
             processes: Arc::new(RwLock::new(HashMap::new())),
         }
     }
@@ -64,6 +84,16 @@ impl TerminalAdapter {
         self.allowed_commands.push(command.into());
     }
     
+    /// Replace the whitelist of allowed commands
+    pub fn set_allowed_commands(&mut self, commands: Vec<String>) {
+        self.allowed_commands.set(commands);
+    }
+    
+    /// Handle for changing the whitelist after the adapter is registered
+    pub fn allowed_commands(&self) -> AllowedCommands {
+        self.allowed_commands.clone()
+    }
+    
     /// Check if command is allowed
     fn is_command_allowed(&self, command: &str) -> bool {
         // Extract the base command (first word)
@@ -71,8 +101,7 @@ impl TerminalAdapter {
             .next()
             .unwrap_or("");
         
-        self.allowed_commands.iter()
-            .any(|allowed| allowed == base_command)
+        self.allowed_commands.contains(base_command)
     }
 }
 
@@ -225,16 +254,15 @@ impl TerminalAdapter {
         
         // Determine working directory
         let cwd = if let Some(cwd_str) = args.get("cwd").and_then(|v| v.as_str()) {
-            let cwd_path = PathBuf::from(cwd_str);
-            if !cwd_path.starts_with(&self.base_path) {
-                return Err(anyhow!("Working directory must be within base path"));
-            }
-            cwd_path
+            self.base_path.join(cwd_str)
         } else if let Some(project) = project_name {
             self.base_path.join(project)
         } else {
             self.base_path.clone()
         };
+        if !is_within(&cwd, &self.base_path) {
+            return Err(anyhow!("Working directory must be within base path"));
+        }
         
         // Parse environment variables
         let env_vars: HashMap<String, String> = args.get("env")
@@ -249,13 +277,15 @@ impl TerminalAdapter {
         // Execute command
         debug!("Executing: {} in {:?}", command_str, cwd);
         
-        let mut cmd = Command::new("sh");
+        // Killed if the request is cancelled or runs out of time
+        let mut cmd = tokio::process::Command::new("sh");
         cmd.arg("-c")
             .arg(command_str)
             .current_dir(&cwd)
-            .envs(env_vars);
+            .envs(env_vars)
+            .kill_on_drop(true);
         
-        let output = cmd.output()?;
+        let output = cmd.output().await?;
         
         Ok(ServiceResult {
             success: output.status.success(),
@@ -285,16 +315,15 @@ impl TerminalAdapter {
         
         // Determine working directory
         let cwd = if let Some(cwd_str) = args.get("cwd").and_then(|v| v.as_str()) {
-            let cwd_path = PathBuf::from(cwd_str);
-            if !cwd_path.starts_with(&self.base_path) {
-                return Err(anyhow!("Working directory must be within base path"));
-            }
-            cwd_path
+            self.base_path.join(cwd_str)
         } else if let Some(project) = project_name {
             self.base_path.join(project)
         } else {
             self.base_path.clone()
         };
+        if !is_within(&cwd, &self.base_path) {
+            return Err(anyhow!("Working directory must be within base path"));
+        }
         
         // Spawn process
         let child = Command::new("sh")
@@ -411,4 +440,49 @@ mod tests {
             .unwrap()
             .contains("Hello, Terminal!"));
     }
+    
+    #[tokio::test]
+    async fn test_whitelist_changes_after_registration() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut adapter = TerminalAdapter::new(temp_dir.path());
+        adapter.initialize().await.unwrap();
+        let allowed = adapter.allowed_commands();
+        
+        let echo = || ServiceCommand {
+            tool: "execute".to_string(),
+            args: json!({ "command": "echo hi" }),
+            project_name: None,
+            role_id: None,
+            context: None,
+            store_result: None,
+        };
+        assert!(adapter.execute(echo()).await.is_ok());
+        
+        allowed.set(vec!["ls".to_string()]);
+        assert!(adapter.execute(echo()).await.is_err());
+        assert_eq!(adapter.allowed_commands().get(), ["ls"]);
+    }
+    
+    #[tokio::test]
+    async fn test_working_directory_cannot_leave_base_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut adapter = TerminalAdapter::new(temp_dir.path().join("base"));
+        adapter.initialize().await.unwrap();
+        
+        let pwd = |cwd: Option<&str>, project_name: Option<&str>| ServiceCommand {
+            tool: "execute".to_string(),
+            args: json!({ "command": "pwd", "cwd": cwd }),
+            project_name: project_name.map(str::to_string),
+            role_id: None,
+            context: None,
+            store_result: None,
+        };
+        assert!(adapter.execute(pwd(Some("../.."), None)).await.is_err());
+        assert!(adapter.execute(pwd(Some("/"), None)).await.is_err());
+        assert!(adapter.execute(pwd(None, Some(".."))).await.is_err());
+        
+        std::fs::create_dir(temp_dir.path().join("base").join("sub")).unwrap();
+        let result = adapter.execute(pwd(Some("sub"), None)).await.unwrap();
+        assert!(result.data.unwrap()["stdout"].as_str().unwrap().trim_end().ends_with("base/sub"));
+    }
 }
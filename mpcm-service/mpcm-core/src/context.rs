@@ -2,14 +2,192 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
 use uuid::Uuid;
 
+use crate::error::MpcmError;
+
+/// Kind of a context entry
+///
+/// Serialized as its name, so stored rows and JSON keep the plain string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ContextType {
+    Decision,
+    Code,
+    Standard,
+    Status,
+    Todo,
+    Note,
+    Config,
+    Issue,
+    Reference,
+    /// A type registered at runtime
+    Custom(String),
+}
+
+impl ContextType {
+    /// Every built-in type
+    pub const BUILTIN: [ContextType; 9] = [
+        ContextType::Decision,
+        ContextType::Code,
+        ContextType::Standard,
+        ContextType::Status,
+        ContextType::Todo,
+        ContextType::Note,
+        ContextType::Config,
+        ContextType::Issue,
+        ContextType::Reference,
+    ];
+
+    /// Built-in type with this name, if any
+    pub fn builtin(name: &str) -> Option<Self> {
+        Self::BUILTIN.into_iter().find(|t| t.as_str() == name)
+    }
+
+    /// Built-in type with this name, otherwise a custom one
+    ///
+    /// Does not check that a custom type is registered; use `parse` to
+    /// accept built-ins only.
+    pub fn from_name(name: &str) -> Self {
+        Self::builtin(name).unwrap_or_else(|| ContextType::Custom(name.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContextType::Decision => "decision",
+            ContextType::Code => "code",
+            ContextType::Standard => "standard",
+            ContextType::Status => "status",
+            ContextType::Todo => "todo",
+            ContextType::Note => "note",
+            ContextType::Config => "config",
+            ContextType::Issue => "issue",
+            ContextType::Reference => "reference",
+            ContextType::Custom(name) => name,
+        }
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        !matches!(self, ContextType::Custom(_))
+    }
+}
+
+impl fmt::Display for ContextType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ContextType {
+    type Err = MpcmError;
+
+    /// Accepts built-in type names only
+    fn from_str(s: &str) -> Result<Self, MpcmError> {
+        Self::builtin(s).ok_or_else(|| {
+            MpcmError::InvalidContextType(format!(
+                "unknown type '{}', expected one of: {}",
+                s,
+                Self::BUILTIN.map(|t| t.as_str().to_string()).join(", ")
+            ))
+        })
+    }
+}
+
+impl From<String> for ContextType {
+    fn from(name: String) -> Self {
+        Self::from_name(&name)
+    }
+}
+
+impl From<ContextType> for String {
+    fn from(context_type: ContextType) -> Self {
+        context_type.as_str().to_string()
+    }
+}
+
+/// A user-registered context type
+///
+/// Either schema, when set, is a JSON Schema the entry's `value` or
+/// `metadata` must satisfy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomContextType {
+    pub name: String,
+    pub description: Option<String>,
+    pub value_schema: Option<Value>,
+    pub metadata_schema: Option<Value>,
+}
+
+impl CustomContextType {
+    /// Check the name and that both schemas compile
+    pub fn check(&self) -> Result<(), MpcmError> {
+        if self.name.trim().is_empty() {
+            return Err(MpcmError::InvalidContextType("type name must not be empty".to_string()));
+        }
+        if ContextType::builtin(&self.name).is_some() {
+            return Err(MpcmError::InvalidContextType(format!(
+                "'{}' is a built-in type",
+                self.name
+            )));
+        }
+        for (field, schema) in self.schemas() {
+            jsonschema::validator_for(schema).map_err(|e| {
+                MpcmError::InvalidContextType(format!(
+                    "invalid {} schema for '{}': {}",
+                    field, self.name, e
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Validate an entry against this type's schemas
+    ///
+    /// A value that is not JSON is checked as a JSON string.
+    pub fn validate(&self, value: &str, metadata: Option<&Value>) -> Result<(), MpcmError> {
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+        let null = Value::Null;
+        let mut errors = Vec::new();
+
+        for (field, schema) in self.schemas() {
+            let instance = if field == "value" { &value } else { metadata.unwrap_or(&null) };
+            let validator = jsonschema::validator_for(schema).map_err(|e| {
+                MpcmError::InvalidContextType(format!(
+                    "invalid {} schema for '{}': {}",
+                    field, self.name, e
+                ))
+            })?;
+            errors.extend(validator.iter_errors(instance).map(|e| {
+                let path = e.instance_path.to_string();
+                format!("{}{}: {}", field, path, e)
+            }));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(MpcmError::ContextValidation {
+                context_type: self.name.clone(),
+                errors,
+            })
+        }
+    }
+
+    fn schemas(&self) -> impl Iterator<Item = (&'static str, &Value)> {
+        [("value", &self.value_schema), ("metadata", &self.metadata_schema)]
+            .into_iter()
+            .filter_map(|(field, schema)| schema.as_ref().map(|s| (field, s)))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Context {
     id: String,
     project_name: String,
     key: String,
-    context_type: String,
+    context_type: ContextType,
     value: String,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
@@ -17,13 +195,13 @@ pub struct Context {
 
 impl Context {
     /// Create a new context entry
-    pub fn new(project_name: &str, key: &str, context_type: &str, value: &str) -> Self {
+    pub fn new(project_name: &str, key: &str, context_type: ContextType, value: &str) -> Self {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4().to_string(),
             project_name: project_name.to_string(),
             key: key.to_string(),
-            context_type: context_type.to_string(),
+            context_type,
             value: value.to_string(),
             created_at: now,
             updated_at: now,
@@ -34,7 +212,7 @@ impl Context {
     pub fn id(&self) -> &str { &self.id }
     pub fn project_name(&self) -> &str { &self.project_name }
     pub fn key(&self) -> &str { &self.key }
-    pub fn context_type(&self) -> &str { &self.context_type }
+    pub fn context_type(&self) -> &str { self.context_type.as_str() }
     pub fn value(&self) -> &str { &self.value }
     pub fn created_at(&self) -> &DateTime<Utc> { &self.created_at }
     
@@ -52,7 +230,7 @@ impl Context {
             id,
             project_name,
             key,
-            context_type,
+            context_type: ContextType::from_name(&context_type),
             value,
             created_at,
             updated_at,
@@ -1,7 +1,33 @@
 //! Error types for MPCM core
 
+use serde_json::{json, Value};
 use thiserror::Error;
 
+/// Stable error codes reported to JSON-RPC clients
+pub mod codes {
+    pub const INVALID_PARAMS: i32 = -32602;
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+    pub const INTERNAL_ERROR: i32 = -32603;
+    pub const REQUEST_CANCELLED: i32 = -32800;
+    
+    pub const CONTEXT_NOT_FOUND: i32 = 1001;
+    pub const PROJECT_NOT_FOUND: i32 = 1002;
+    pub const DATABASE_ERROR: i32 = 1003;
+    pub const INVALID_CONTEXT_TYPE: i32 = 1004;
+    pub const CONTEXT_VALIDATION_FAILED: i32 = 1005;
+    pub const REVISION_NOT_FOUND: i32 = 1006;
+    pub const NOTHING_TO_RESTORE: i32 = 1007;
+    pub const PROJECT_DELETED: i32 = 1008;
+    pub const SERVICE_NOT_FOUND: i32 = 1101;
+    pub const SERVICE_ALREADY_REGISTERED: i32 = 1102;
+    pub const NO_SERVICE_FOR_TOOL: i32 = 1103;
+    pub const ALL_SERVICES_FAILED: i32 = 1104;
+    pub const PERMISSION_DENIED: i32 = 1201;
+    pub const UNAUTHENTICATED: i32 = 1202;
+    pub const DEADLINE_EXCEEDED: i32 = 1301;
+    pub const TOO_MANY_REQUESTS: i32 = 1302;
+}
+
 #[derive(Error, Debug)]
 pub enum MpcmError {
     #[error("Database error: {0}")]
@@ -18,6 +44,163 @@ pub enum MpcmError {
     
     #[error("Invalid context type: {0}")]
     InvalidContextType(String),
+    
+    #[error("Context of type '{context_type}' failed validation: {}", errors.join("; "))]
+    ContextValidation { context_type: String, errors: Vec<String> },
+    
+    #[error("Project not found: {project}")]
+    ProjectNotFound { project: String },
+    
+    #[error("Revision {revision} not found for context '{key}' in project '{project}'")]
+    RevisionNotFound { project: String, key: String, revision: i64 },
+    
+    #[error("Nothing restorable for {}", restore_target(project, key.as_deref()))]
+    NothingToRestore { project: String, key: Option<String> },
+    
+    #[error("Project '{project}' is deleted; restore it before writing to it")]
+    ProjectDeleted { project: String },
+    
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    
+    #[error("Method not found: {0}")]
+    MethodNotFound(String),
+    
+    #[error("Service {0} not found")]
+    ServiceNotFound(String),
+    
+    #[error("Service {0} is already registered")]
+    ServiceAlreadyRegistered(String),
+    
+    #[error("No service found for tool: {0}")]
+    NoServiceForTool(String),
+    
+    #[error("All services failed: {}", errors.join(", "))]
+    AllServicesFailed { errors: Vec<String> },
+    
+    #[error("Role '{role}' may not call {method}")]
+    PermissionDenied { role: String, method: String },
+    
+    #[error("Authentication failed: {0}")]
+    Unauthenticated(String),
+    
+    #[error("Request cancelled")]
+    RequestCancelled,
+    
+    #[error("Request exceeded its deadline of {timeout_ms} ms")]
+    DeadlineExceeded { timeout_ms: u64 },
+    
+    #[error("Too many requests in flight; at most {limit} may run at once")]
+    TooManyRequests { limit: usize },
+}
+
+fn restore_target(project: &str, key: Option<&str>) -> String {
+    match key {
+        Some(key) => format!("context '{}' in project '{}'", key, project),
+        None => format!("project '{}'", project),
+    }
+}
+
+impl MpcmError {
+    /// Numeric code clients can branch on
+    pub fn code(&self) -> i32 {
+        match self {
+            MpcmError::Database(_) => codes::DATABASE_ERROR,
+            MpcmError::InvalidArgument(_) => codes::INVALID_PARAMS,
+            MpcmError::Serialization(_) | MpcmError::Io(_) => codes::INTERNAL_ERROR,
+            MpcmError::ContextNotFound { .. } => codes::CONTEXT_NOT_FOUND,
+            MpcmError::InvalidContextType(_) => codes::INVALID_CONTEXT_TYPE,
+            MpcmError::ContextValidation { .. } => codes::CONTEXT_VALIDATION_FAILED,
+            MpcmError::ProjectNotFound { .. } => codes::PROJECT_NOT_FOUND,
+            MpcmError::RevisionNotFound { .. } => codes::REVISION_NOT_FOUND,
+            MpcmError::NothingToRestore { .. } => codes::NOTHING_TO_RESTORE,
+            MpcmError::ProjectDeleted { .. } => codes::PROJECT_DELETED,
+            MpcmError::MethodNotFound(_) => codes::METHOD_NOT_FOUND,
+            MpcmError::ServiceNotFound(_) => codes::SERVICE_NOT_FOUND,
+            MpcmError::ServiceAlreadyRegistered(_) => codes::SERVICE_ALREADY_REGISTERED,
+            MpcmError::NoServiceForTool(_) => codes::NO_SERVICE_FOR_TOOL,
+            MpcmError::AllServicesFailed { .. } => codes::ALL_SERVICES_FAILED,
+            MpcmError::PermissionDenied { .. } => codes::PERMISSION_DENIED,
+            MpcmError::Unauthenticated(_) => codes::UNAUTHENTICATED,
+            MpcmError::RequestCancelled => codes::REQUEST_CANCELLED,
+            MpcmError::DeadlineExceeded { .. } => codes::DEADLINE_EXCEEDED,
+            MpcmError::TooManyRequests { .. } => codes::TOO_MANY_REQUESTS,
+        }
+    }
+    
+    /// Short machine-readable name of the error kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MpcmError::Database(_) => "database",
+            MpcmError::Serialization(_) => "serialization",
+            MpcmError::Io(_) => "io",
+            MpcmError::ContextNotFound { .. } => "context_not_found",
+            MpcmError::InvalidContextType(_) => "invalid_context_type",
+            MpcmError::ContextValidation { .. } => "context_validation",
+            MpcmError::ProjectNotFound { .. } => "project_not_found",
+            MpcmError::RevisionNotFound { .. } => "revision_not_found",
+            MpcmError::NothingToRestore { .. } => "nothing_to_restore",
+            MpcmError::ProjectDeleted { .. } => "project_deleted",
+            MpcmError::InvalidArgument(_) => "invalid_argument",
+            MpcmError::MethodNotFound(_) => "method_not_found",
+            MpcmError::ServiceNotFound(_) => "service_not_found",
+            MpcmError::ServiceAlreadyRegistered(_) => "service_already_registered",
+            MpcmError::NoServiceForTool(_) => "no_service_for_tool",
+            MpcmError::AllServicesFailed { .. } => "all_services_failed",
+            MpcmError::PermissionDenied { .. } => "permission_denied",
+            MpcmError::Unauthenticated(_) => "unauthenticated",
+            MpcmError::RequestCancelled => "request_cancelled",
+            MpcmError::DeadlineExceeded { .. } => "deadline_exceeded",
+            MpcmError::TooManyRequests { .. } => "too_many_requests",
+        }
+    }
+    
+    /// Structured details for the JSON-RPC `data` field
+    pub fn data(&self) -> Value {
+        let details = match self {
+            MpcmError::ContextNotFound { project, key } => json!({ "project": project, "key": key }),
+            MpcmError::ContextValidation { context_type, errors } => {
+                json!({ "type": context_type, "errors": errors })
+            }
+            MpcmError::ProjectNotFound { project } | MpcmError::ProjectDeleted { project } => {
+                json!({ "project": project })
+            }
+            MpcmError::RevisionNotFound { project, key, revision } => {
+                json!({ "project": project, "key": key, "revision": revision })
+            }
+            MpcmError::NothingToRestore { project, key } => json!({ "project": project, "key": key }),
+            MpcmError::MethodNotFound(method) => json!({ "method": method }),
+            MpcmError::ServiceNotFound(service) | MpcmError::ServiceAlreadyRegistered(service) => {
+                json!({ "service": service })
+            }
+            MpcmError::NoServiceForTool(tool) => json!({ "tool": tool }),
+            MpcmError::AllServicesFailed { errors } => json!({ "errors": errors }),
+            MpcmError::PermissionDenied { role, method } => json!({ "role": role, "method": method }),
+            MpcmError::DeadlineExceeded { timeout_ms } => json!({ "timeout_ms": timeout_ms }),
+            MpcmError::TooManyRequests { limit } => json!({ "limit": limit }),
+            _ => json!({}),
+        };
+        
+        let mut data = json!({ "kind": self.kind() });
+        if let (Some(data), Value::Object(details)) = (data.as_object_mut(), details) {
+            data.extend(details);
+        }
+        data
+    }
+}
+
+/// Code and `data` payload for an error that reached the JSON-RPC layer
+///
+/// Typed errors anywhere in the chain keep their own code; database errors
+/// passed through with `?` map to `DATABASE_ERROR`; the rest are internal.
+pub fn error_details(error: &anyhow::Error) -> (i32, Option<Value>) {
+    if let Some(e) = error.chain().find_map(|cause| cause.downcast_ref::<MpcmError>()) {
+        return (e.code(), Some(e.data()));
+    }
+    if error.chain().any(|cause| cause.is::<sqlx::Error>()) {
+        return (codes::DATABASE_ERROR, Some(json!({ "kind": "database" })));
+    }
+    (codes::INTERNAL_ERROR, None)
 }
 
-pub type Result<T> = std::result::Result<T, MpcmError>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, MpcmError>;
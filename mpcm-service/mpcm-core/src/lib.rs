@@ -7,6 +7,7 @@ pub mod context;
 pub mod error;
 pub mod storage;
 pub mod storage_v2;
+pub mod migrations;
 pub mod registry;
 pub mod adapters;
 
@@ -24,7 +25,7 @@ mod tests {
         let ctx = Context::new(
             "test-project",
             "architecture-decision",
-            "decision",
+            ContextType::Decision,
             "Use Rust for performance"
         );
         
@@ -36,13 +37,28 @@ mod tests {
         assert!(ctx.created_at().timestamp() > 0);
     }
     
+    /// TDD: Only built-in type names parse; stored names round-trip
+    #[test]
+    fn test_context_type_parsing() {
+        assert_eq!("todo".parse::<ContextType>().unwrap(), ContextType::Todo);
+        assert!(matches!(
+            "mystery".parse::<ContextType>(),
+            Err(MpcmError::InvalidContextType(_))
+        ));
+        
+        let custom = ContextType::from_name("metric");
+        assert_eq!(custom, ContextType::Custom("metric".to_string()));
+        assert_eq!(serde_json::to_value(&custom).unwrap(), "metric");
+        assert_eq!(serde_json::from_value::<ContextType>("note".into()).unwrap(), ContextType::Note);
+    }
+    
     /// TDD: Test context serialization for storage
     #[test] 
     fn test_context_serialization() {
         let ctx = Context::new(
             "test-project",
             "test-key",
-            "note",
+            ContextType::Note,
             "Test value"
         );
         
@@ -80,7 +96,7 @@ mod tests {
         let ctx = Context::new(
             "test-project",
             "test-key",
-            "decision",
+            ContextType::Decision,
             "Important decision"
         );
         
@@ -96,4 +112,45 @@ mod tests {
         assert!(retrieved_ctx.is_some());
         assert_eq!(retrieved_ctx.unwrap().value(), "Important decision");
     }
+    
+    /// TDD: Overwrites keep the previous value as a revision
+    #[tokio::test]
+    async fn test_storage_keeps_revisions() {
+        use tempfile::TempDir;
+        
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        std::fs::write(&db_path, "").unwrap();
+        let storage = Storage::new(&db_path).await.unwrap();
+        
+        storage.store_context(&Context::new("p", "k", ContextType::Decision, "first")).await.unwrap();
+        storage.store_context_as(&Context::new("p", "k", ContextType::Decision, "second"), Some("architect")).await.unwrap();
+        storage.store_context(&Context::new("p", "k", ContextType::Decision, "second")).await.unwrap();
+        
+        let revisions = storage.list_revisions("p", "k").await.unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].context.value(), "first");
+        assert_eq!(revisions[0].role.as_deref(), Some("architect"));
+    }
+    
+    /// TDD: Search filters by project, substring and type
+    #[tokio::test]
+    async fn test_storage_search_and_list_projects() {
+        use tempfile::TempDir;
+        
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        std::fs::write(&db_path, "").unwrap();
+        let storage = Storage::new(&db_path).await.unwrap();
+        
+        storage.store_context(&Context::new("p", "db", ContextType::Decision, "Use SQLite")).await.unwrap();
+        storage.store_context(&Context::new("p", "docs", ContextType::Todo, "Write docs")).await.unwrap();
+        storage.store_context(&Context::new("q", "db", ContextType::Decision, "Use Postgres")).await.unwrap();
+        
+        assert_eq!(storage.list_projects().await.unwrap(), vec!["p", "q"]);
+        assert_eq!(storage.search_contexts(Some("p"), None, None, -1).await.unwrap().len(), 2);
+        assert_eq!(storage.search_contexts(None, Some("Use"), None, -1).await.unwrap().len(), 2);
+        assert_eq!(storage.search_contexts(None, None, Some("todo"), -1).await.unwrap().len(), 1);
+        assert_eq!(storage.search_contexts(None, None, None, 1).await.unwrap().len(), 1);
+    }
 }
@@ -0,0 +1,985 @@
+//! Versioned schema migrations for the v2 storage layer
+//! Mirrors the TypeScript `schema.ts` base schema plus its numbered migrations,
+//! so a database created by either implementation can be opened by the other.
+
+use anyhow::{anyhow, Context, Result};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use tracing::{debug, info};
+
+/// A single schema change inside a migration
+pub enum Step {
+    /// Raw SQL, may contain several statements
+    Sql(&'static str),
+    /// `ALTER TABLE ... ADD COLUMN`, skipped when the column already exists
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+}
+
+/// A numbered migration, recorded in `schema_migrations` once applied
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+/// Base schema from `schema.ts`, applied before any numbered migration
+const BASE_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS systems (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL,
+  hostname TEXT NOT NULL,
+  platform TEXT NOT NULL,
+  is_current BOOLEAN DEFAULT 0,
+  metadata TEXT,
+  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+  last_seen DATETIME DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE(hostname)
+);
+
+CREATE TABLE IF NOT EXISTS projects (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE,
+  description TEXT,
+  status TEXT DEFAULT 'active',
+  repository_url TEXT,
+  local_directory TEXT,
+  primary_system_id INTEGER,
+  tags TEXT,
+  metadata TEXT,
+  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+  last_accessed DATETIME DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (primary_system_id) REFERENCES systems(id)
+);
+
+CREATE TABLE IF NOT EXISTS context_entries (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  project_id INTEGER,
+  system_id INTEGER,
+  type TEXT NOT NULL,
+  key TEXT NOT NULL,
+  value TEXT NOT NULL,
+  is_system_specific BOOLEAN DEFAULT 0,
+  tags TEXT,
+  metadata TEXT,
+  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+  FOREIGN KEY (system_id) REFERENCES systems(id)
+);
+
+CREATE TABLE IF NOT EXISTS update_history (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  entity_type TEXT NOT NULL,
+  entity_id INTEGER NOT NULL,
+  action TEXT NOT NULL,
+  changes TEXT,
+  user_note TEXT,
+  timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS context_search USING fts5(
+  entity_id,
+  entity_type,
+  content,
+  tags
+);
+
+CREATE INDEX IF NOT EXISTS idx_projects_status ON projects(status);
+CREATE INDEX IF NOT EXISTS idx_projects_system ON projects(primary_system_id);
+CREATE INDEX IF NOT EXISTS idx_context_project ON context_entries(project_id);
+CREATE INDEX IF NOT EXISTS idx_context_system ON context_entries(system_id);
+CREATE INDEX IF NOT EXISTS idx_context_type ON context_entries(type);
+CREATE INDEX IF NOT EXISTS idx_context_key ON context_entries(key);
+CREATE INDEX IF NOT EXISTS idx_history_entity ON update_history(entity_type, entity_id);
+CREATE INDEX IF NOT EXISTS idx_history_timestamp ON update_history(timestamp);
+
+CREATE TRIGGER IF NOT EXISTS update_project_timestamp
+AFTER UPDATE ON projects
+BEGIN
+  UPDATE projects SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_context_timestamp
+AFTER UPDATE ON context_entries
+BEGIN
+  UPDATE context_entries SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS update_system_last_seen
+AFTER UPDATE ON systems
+BEGIN
+  UPDATE systems SET last_seen = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+"#;
+
+/// All known migrations, in version order
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema_with_unique_index",
+        steps: &[Step::Sql(r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_context_unique_key
+            ON context_entries(COALESCE(project_id, -1), key);
+            CREATE INDEX IF NOT EXISTS idx_context_project_type
+            ON context_entries(project_id, type);
+            CREATE INDEX IF NOT EXISTS idx_context_project_updated
+            ON context_entries(project_id, updated_at DESC);
+            CREATE INDEX IF NOT EXISTS idx_context_tags
+            ON context_entries(tags);
+            CREATE INDEX IF NOT EXISTS idx_context_system
+            ON context_entries(system_id, is_system_specific);
+        "#)],
+    },
+    Migration {
+        version: 2,
+        name: "Add role-based features",
+        steps: &[
+            Step::Sql(r#"
+                CREATE TABLE IF NOT EXISTS roles (
+                  id TEXT PRIMARY KEY,
+                  name TEXT NOT NULL UNIQUE,
+                  description TEXT,
+                  is_custom BOOLEAN DEFAULT FALSE,
+                  template_config JSON,
+                  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+                );
+                CREATE TABLE IF NOT EXISTS project_roles (
+                  project_id TEXT NOT NULL,
+                  role_id TEXT NOT NULL,
+                  is_active BOOLEAN DEFAULT TRUE,
+                  custom_config JSON,
+                  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                  PRIMARY KEY (project_id, role_id),
+                  FOREIGN KEY (project_id) REFERENCES projects(id),
+                  FOREIGN KEY (role_id) REFERENCES roles(id)
+                );
+                CREATE TABLE IF NOT EXISTS role_handoffs (
+                  id TEXT PRIMARY KEY,
+                  project_id TEXT NOT NULL,
+                  from_role_id TEXT NOT NULL,
+                  to_role_id TEXT NOT NULL,
+                  handoff_data JSON NOT NULL,
+                  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                  created_by_system_id TEXT,
+                  FOREIGN KEY (project_id) REFERENCES projects(id),
+                  FOREIGN KEY (from_role_id) REFERENCES roles(id),
+                  FOREIGN KEY (to_role_id) REFERENCES roles(id)
+                );
+                CREATE TABLE IF NOT EXISTS active_roles (
+                  project_id TEXT NOT NULL,
+                  system_id TEXT NOT NULL,
+                  role_id TEXT NOT NULL,
+                  activated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                  PRIMARY KEY (project_id, system_id),
+                  FOREIGN KEY (project_id) REFERENCES projects(id),
+                  FOREIGN KEY (role_id) REFERENCES roles(id),
+                  FOREIGN KEY (system_id) REFERENCES systems(id)
+                );
+            "#),
+            Step::AddColumn {
+                table: "context_entries",
+                column: "role_id",
+                definition: "TEXT REFERENCES roles(id)",
+            },
+            Step::AddColumn {
+                table: "update_history",
+                column: "role_id",
+                definition: "TEXT REFERENCES roles(id)",
+            },
+            Step::Sql(r#"
+                CREATE INDEX IF NOT EXISTS idx_context_entries_role ON context_entries(project_id, role_id);
+                CREATE INDEX IF NOT EXISTS idx_context_entries_role_type ON context_entries(project_id, role_id, type);
+                CREATE INDEX IF NOT EXISTS idx_role_handoffs_project ON role_handoffs(project_id);
+                CREATE INDEX IF NOT EXISTS idx_active_roles_system ON active_roles(system_id);
+
+                INSERT OR IGNORE INTO roles (id, name, description, is_custom, template_config) VALUES
+                  ('architect', 'Software Architect',
+                   'Responsible for system design, architecture decisions, and technical standards', 0,
+                   '{"focusAreas":["system-design","patterns","constraints","decisions"],"defaultTags":["architecture","design","decision"],"contextTypes":["decision","standard","reference"]}'),
+                  ('developer', 'Software Developer',
+                   'Implements features, writes code, and maintains code quality', 0,
+                   '{"focusAreas":["implementation","code-patterns","debugging","features"],"defaultTags":["implementation","code","feature"],"contextTypes":["code","todo","issue","note"]}'),
+                  ('devops', 'DevOps Engineer',
+                   'Manages deployment, infrastructure, and operational concerns', 0,
+                   '{"focusAreas":["deployment","infrastructure","monitoring","ci-cd"],"defaultTags":["deployment","infrastructure","operations"],"contextTypes":["config","status","issue","decision"]}'),
+                  ('qa', 'QA Engineer',
+                   'Ensures quality through testing, bug tracking, and test planning', 0,
+                   '{"focusAreas":["testing","quality","bugs","test-plans"],"defaultTags":["testing","quality","bug"],"contextTypes":["issue","todo","standard","note"]}'),
+                  ('product', 'Product Manager',
+                   'Defines requirements, priorities, and product direction', 0,
+                   '{"focusAreas":["requirements","user-stories","priorities","roadmap"],"defaultTags":["product","requirement","priority"],"contextTypes":["decision","todo","reference","note"]}');
+
+                CREATE TRIGGER IF NOT EXISTS update_roles_timestamp
+                AFTER UPDATE ON roles
+                FOR EACH ROW
+                BEGIN
+                  UPDATE roles SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+                END;
+            "#),
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "Add custom roles support",
+        steps: &[
+            Step::AddColumn {
+                table: "roles",
+                column: "parent_template",
+                definition: "TEXT",
+            },
+            Step::AddColumn {
+                table: "roles",
+                column: "author_system_id",
+                definition: "INTEGER REFERENCES systems(id)",
+            },
+            Step::Sql(r#"
+                CREATE TABLE IF NOT EXISTS role_templates (
+                  id TEXT PRIMARY KEY,
+                  name TEXT NOT NULL,
+                  description TEXT,
+                  base_config JSON NOT NULL,
+                  author TEXT,
+                  downloads INTEGER DEFAULT 0,
+                  version TEXT DEFAULT '1.0.0',
+                  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+                );
+                CREATE INDEX IF NOT EXISTS idx_roles_author ON roles(author_system_id);
+                CREATE INDEX IF NOT EXISTS idx_templates_downloads ON role_templates(downloads DESC);
+            "#),
+        ],
+    },
+    Migration {
+        version: 4,
+        name: "enhanced_project_management_taxonomy",
+        steps: &[
+            Step::AddColumn {
+                table: "projects",
+                column: "project_directory",
+                definition: "TEXT",
+            },
+            Step::AddColumn {
+                table: "projects",
+                column: "workflow_type",
+                definition: "TEXT CHECK (workflow_type IN ('agile-scrum', 'agile-kanban', 'waterfall', 'lean', 'custom'))",
+            },
+            Step::AddColumn {
+                table: "projects",
+                column: "team_data",
+                definition: "TEXT",
+            },
+            Step::AddColumn {
+                table: "projects",
+                column: "source_control_url",
+                definition: "TEXT",
+            },
+            Step::Sql(r#"
+                CREATE INDEX IF NOT EXISTS idx_projects_directory ON projects(project_directory);
+                CREATE INDEX IF NOT EXISTS idx_projects_source_control ON projects(source_control_url);
+
+                CREATE TABLE IF NOT EXISTS epics (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  project_id INTEGER NOT NULL,
+                  title TEXT NOT NULL,
+                  description TEXT NOT NULL,
+                  status TEXT NOT NULL CHECK (status IN ('planning', 'ready', 'in_progress', 'review', 'completed', 'cancelled', 'on_hold')),
+                  priority TEXT NOT NULL CHECK (priority IN ('critical', 'high', 'medium', 'low')),
+                  business_value TEXT NOT NULL,
+                  acceptance_criteria TEXT NOT NULL,
+                  estimated_story_points INTEGER DEFAULT 0,
+                  actual_story_points INTEGER DEFAULT 0,
+                  target_version TEXT,
+                  start_date TEXT,
+                  end_date TEXT,
+                  assigned_to_role TEXT,
+                  tags TEXT,
+                  metadata TEXT,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS sprints (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  project_id INTEGER NOT NULL,
+                  name TEXT NOT NULL,
+                  start_date TEXT NOT NULL,
+                  end_date TEXT NOT NULL,
+                  capacity_story_points INTEGER NOT NULL,
+                  sprint_goal TEXT NOT NULL,
+                  status TEXT NOT NULL CHECK (status IN ('planning', 'active', 'completed', 'cancelled')),
+                  retrospective_notes TEXT,
+                  velocity_achieved INTEGER DEFAULT 0,
+                  burndown_data TEXT,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS stories (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  epic_id INTEGER,
+                  sprint_id INTEGER,
+                  title TEXT NOT NULL,
+                  description TEXT NOT NULL,
+                  acceptance_criteria TEXT NOT NULL,
+                  story_points INTEGER NOT NULL DEFAULT 0,
+                  priority TEXT NOT NULL CHECK (priority IN ('critical', 'high', 'medium', 'low')),
+                  status TEXT NOT NULL CHECK (status IN ('backlog', 'ready_for_development', 'in_progress', 'code_review', 'testing', 'ready_for_deployment', 'completed', 'cancelled')),
+                  type TEXT NOT NULL CHECK (type IN ('feature', 'bug', 'technical_debt', 'research', 'documentation')),
+                  assignee_role TEXT,
+                  reviewer_role TEXT,
+                  labels TEXT,
+                  estimated_hours INTEGER,
+                  actual_hours INTEGER,
+                  blocked BOOLEAN DEFAULT 0,
+                  blocked_reason TEXT,
+                  dependencies TEXT,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (epic_id) REFERENCES epics(id) ON DELETE SET NULL,
+                  FOREIGN KEY (sprint_id) REFERENCES sprints(id) ON DELETE SET NULL
+                );
+
+                CREATE TABLE IF NOT EXISTS story_transitions (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  story_id INTEGER NOT NULL,
+                  from_status TEXT,
+                  to_status TEXT NOT NULL,
+                  transitioned_by_role TEXT NOT NULL,
+                  transition_date DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  notes TEXT,
+                  FOREIGN KEY (story_id) REFERENCES stories(id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS issues (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  project_id INTEGER NOT NULL,
+                  title TEXT NOT NULL,
+                  description TEXT NOT NULL,
+                  type TEXT NOT NULL CHECK (type IN ('bug', 'enhancement', 'task', 'question', 'documentation')),
+                  severity TEXT NOT NULL CHECK (severity IN ('critical', 'high', 'medium', 'low', 'trivial')),
+                  priority TEXT NOT NULL CHECK (priority IN ('critical', 'high', 'medium', 'low')),
+                  status TEXT NOT NULL CHECK (status IN ('open', 'in_progress', 'review', 'testing', 'resolved', 'closed', 'reopened')),
+                  reporter_role TEXT NOT NULL,
+                  assignee_role TEXT,
+                  reproduction_steps TEXT,
+                  expected_behavior TEXT,
+                  actual_behavior TEXT,
+                  environment TEXT,
+                  browser_version TEXT,
+                  tags TEXT,
+                  resolution TEXT,
+                  resolution_date TEXT,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS issue_relationships (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  issue_id INTEGER NOT NULL,
+                  related_type TEXT NOT NULL CHECK (related_type IN ('story', 'epic')),
+                  related_id INTEGER NOT NULL,
+                  relationship_type TEXT NOT NULL CHECK (relationship_type IN ('blocks', 'blocked_by', 'related_to', 'caused_by')),
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (issue_id) REFERENCES issues(id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS project_versions (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  project_id INTEGER NOT NULL,
+                  version TEXT NOT NULL,
+                  type TEXT NOT NULL CHECK (type IN ('major', 'minor', 'patch', 'hotfix')),
+                  status TEXT NOT NULL CHECK (status IN ('planning', 'in_development', 'feature_freeze', 'testing', 'release_candidate', 'released', 'deprecated')),
+                  changelog_summary TEXT NOT NULL,
+                  breaking_changes BOOLEAN DEFAULT 0,
+                  target_date TEXT,
+                  release_date TEXT,
+                  previous_version_id INTEGER,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+                  FOREIGN KEY (previous_version_id) REFERENCES project_versions(id),
+                  UNIQUE(project_id, version)
+                );
+
+                CREATE TABLE IF NOT EXISTS epic_versions (
+                  epic_id INTEGER NOT NULL,
+                  version_id INTEGER NOT NULL,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  PRIMARY KEY (epic_id, version_id),
+                  FOREIGN KEY (epic_id) REFERENCES epics(id) ON DELETE CASCADE,
+                  FOREIGN KEY (version_id) REFERENCES project_versions(id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS releases (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  version_id INTEGER NOT NULL,
+                  release_name TEXT NOT NULL,
+                  release_date TEXT NOT NULL,
+                  environment TEXT NOT NULL CHECK (environment IN ('development', 'testing', 'staging', 'production', 'demo')),
+                  deployment_method TEXT NOT NULL CHECK (deployment_method IN ('manual', 'automated', 'blue_green', 'canary', 'rolling')),
+                  status TEXT NOT NULL CHECK (status IN ('planned', 'in_progress', 'completed', 'failed', 'rolled_back', 'cancelled')),
+                  rollback_plan TEXT NOT NULL,
+                  deployment_notes TEXT NOT NULL,
+                  approval_required BOOLEAN DEFAULT 0,
+                  approved_by_role TEXT,
+                  approval_date TEXT,
+                  previous_release_id INTEGER,
+                  deployment_duration_minutes INTEGER,
+                  rollback_occurred BOOLEAN DEFAULT 0,
+                  rollback_reason TEXT,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (version_id) REFERENCES project_versions(id) ON DELETE CASCADE,
+                  FOREIGN KEY (previous_release_id) REFERENCES releases(id)
+                );
+
+                CREATE TABLE IF NOT EXISTS team_practices (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  practice_name TEXT NOT NULL UNIQUE,
+                  category TEXT NOT NULL CHECK (category IN ('development', 'testing', 'deployment', 'security', 'performance', 'documentation', 'communication', 'planning')),
+                  description TEXT NOT NULL,
+                  implementation_details TEXT NOT NULL,
+                  success_criteria TEXT NOT NULL,
+                  created_by_role TEXT NOT NULL,
+                  applicable_roles TEXT NOT NULL,
+                  tags TEXT,
+                  adoption_count INTEGER DEFAULT 0,
+                  effectiveness_score REAL DEFAULT 0.0,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                CREATE TABLE IF NOT EXISTS practice_adoptions (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  practice_id INTEGER NOT NULL,
+                  project_id INTEGER NOT NULL,
+                  adopted_by_role TEXT NOT NULL,
+                  adoption_date TEXT NOT NULL,
+                  customizations TEXT,
+                  success_metrics TEXT,
+                  feedback TEXT,
+                  still_in_use BOOLEAN DEFAULT 1,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (practice_id) REFERENCES team_practices(id) ON DELETE CASCADE,
+                  FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+                  UNIQUE(practice_id, project_id)
+                );
+
+                CREATE TABLE IF NOT EXISTS role_project_memory (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  project_id INTEGER NOT NULL,
+                  role TEXT NOT NULL,
+                  context_type TEXT NOT NULL CHECK (context_type IN ('architectural_decisions', 'implementation_notes', 'testing_strategies', 'deployment_configs', 'performance_insights', 'security_considerations', 'code_patterns', 'lessons_learned', 'current_work', 'blockers_and_issues')),
+                  context_data TEXT NOT NULL,
+                  tags TEXT,
+                  access_level TEXT NOT NULL CHECK (access_level IN ('public', 'project_team', 'role_specific', 'private')),
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS role_memory_changes (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  role_memory_id INTEGER NOT NULL,
+                  change_type TEXT NOT NULL CHECK (change_type IN ('creation', 'update', 'deletion', 'access_change')),
+                  old_data TEXT,
+                  new_data TEXT,
+                  changed_by_role TEXT NOT NULL,
+                  change_reason TEXT,
+                  change_date DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (role_memory_id) REFERENCES role_project_memory(id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS role_cross_project_memory (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  role TEXT NOT NULL,
+                  pattern_name TEXT NOT NULL,
+                  pattern_data TEXT NOT NULL,
+                  usage_count INTEGER DEFAULT 0,
+                  success_rate REAL DEFAULT 0.0,
+                  applicable_project_types TEXT,
+                  tags TEXT,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  UNIQUE(role, pattern_name)
+                );
+
+                CREATE TABLE IF NOT EXISTS role_skill_progressions (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  role TEXT NOT NULL,
+                  project_id INTEGER NOT NULL,
+                  skills_demonstrated TEXT NOT NULL,
+                  complexity_level TEXT NOT NULL CHECK (complexity_level IN ('beginner', 'intermediate', 'advanced', 'expert')),
+                  success_rating REAL NOT NULL CHECK (success_rating >= 0 AND success_rating <= 5),
+                  learning_outcomes TEXT,
+                  areas_for_improvement TEXT,
+                  recorded_date TEXT NOT NULL,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+                );
+            "#),
+            Step::AddColumn {
+                table: "context_entries",
+                column: "epic_id",
+                definition: "INTEGER REFERENCES epics(id)",
+            },
+            Step::AddColumn {
+                table: "context_entries",
+                column: "story_id",
+                definition: "INTEGER REFERENCES stories(id)",
+            },
+            Step::AddColumn {
+                table: "context_entries",
+                column: "sprint_id",
+                definition: "INTEGER REFERENCES sprints(id)",
+            },
+            Step::AddColumn {
+                table: "context_entries",
+                column: "version_id",
+                definition: "INTEGER REFERENCES project_versions(id)",
+            },
+            Step::Sql(r#"
+                CREATE INDEX IF NOT EXISTS idx_epics_project_status ON epics(project_id, status);
+                CREATE INDEX IF NOT EXISTS idx_epics_target_version ON epics(target_version);
+                CREATE INDEX IF NOT EXISTS idx_sprints_project_dates ON sprints(project_id, start_date, end_date);
+                CREATE INDEX IF NOT EXISTS idx_stories_epic ON stories(epic_id);
+                CREATE INDEX IF NOT EXISTS idx_stories_sprint ON stories(sprint_id);
+                CREATE INDEX IF NOT EXISTS idx_stories_status ON stories(status);
+                CREATE INDEX IF NOT EXISTS idx_stories_assignee ON stories(assignee_role);
+                CREATE INDEX IF NOT EXISTS idx_story_transitions_story ON story_transitions(story_id);
+                CREATE INDEX IF NOT EXISTS idx_issues_project_status ON issues(project_id, status);
+                CREATE INDEX IF NOT EXISTS idx_issues_severity ON issues(severity);
+                CREATE INDEX IF NOT EXISTS idx_issues_assignee ON issues(assignee_role);
+                CREATE INDEX IF NOT EXISTS idx_versions_project ON project_versions(project_id);
+                CREATE INDEX IF NOT EXISTS idx_versions_status ON project_versions(status);
+                CREATE INDEX IF NOT EXISTS idx_releases_version ON releases(version_id);
+                CREATE INDEX IF NOT EXISTS idx_releases_environment ON releases(environment);
+                CREATE INDEX IF NOT EXISTS idx_team_practices_category ON team_practices(category);
+                CREATE INDEX IF NOT EXISTS idx_practice_adoptions_project ON practice_adoptions(project_id);
+                CREATE INDEX IF NOT EXISTS idx_role_project_memory_project_role ON role_project_memory(project_id, role);
+                CREATE INDEX IF NOT EXISTS idx_role_project_memory_context_type ON role_project_memory(context_type);
+                CREATE INDEX IF NOT EXISTS idx_role_project_memory_access ON role_project_memory(access_level);
+                CREATE INDEX IF NOT EXISTS idx_role_cross_project_role ON role_cross_project_memory(role);
+                CREATE INDEX IF NOT EXISTS idx_role_skill_progressions_role ON role_skill_progressions(role);
+            "#),
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "fix_missing_system_id_column",
+        steps: &[
+            Step::AddColumn {
+                table: "projects",
+                column: "system_id",
+                definition: "INTEGER",
+            },
+            Step::Sql(r#"
+                UPDATE projects SET system_id = (
+                  SELECT id FROM systems WHERE is_current = 1 LIMIT 1
+                ) WHERE system_id IS NULL;
+                CREATE INDEX IF NOT EXISTS idx_projects_system_id ON projects(system_id);
+            "#),
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "context_full_text_search",
+        steps: &[Step::Sql(r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS context_fts USING fts5(
+              key,
+              value,
+              tags,
+              content='context_entries',
+              content_rowid='id'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS context_fts_insert
+            AFTER INSERT ON context_entries
+            BEGIN
+              INSERT INTO context_fts(rowid, key, value, tags)
+              VALUES (NEW.id, NEW.key, NEW.value, NEW.tags);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS context_fts_delete
+            AFTER DELETE ON context_entries
+            BEGIN
+              INSERT INTO context_fts(context_fts, rowid, key, value, tags)
+              VALUES ('delete', OLD.id, OLD.key, OLD.value, OLD.tags);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS context_fts_update
+            AFTER UPDATE OF key, value, tags ON context_entries
+            BEGIN
+              INSERT INTO context_fts(context_fts, rowid, key, value, tags)
+              VALUES ('delete', OLD.id, OLD.key, OLD.value, OLD.tags);
+              INSERT INTO context_fts(rowid, key, value, tags)
+              VALUES (NEW.id, NEW.key, NEW.value, NEW.tags);
+            END;
+
+            INSERT INTO context_fts(context_fts) VALUES ('rebuild');
+        "#)],
+    },
+    Migration {
+        version: 7,
+        name: "context_revisions",
+        steps: &[Step::Sql(r#"
+            CREATE TABLE IF NOT EXISTS context_revisions (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              context_id INTEGER NOT NULL,
+              revision INTEGER NOT NULL,
+              type TEXT NOT NULL,
+              value TEXT NOT NULL,
+              tags TEXT,
+              metadata TEXT,
+              role_id TEXT,
+              created_at DATETIME NOT NULL,
+              replaced_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+              replaced_by_role TEXT,
+              FOREIGN KEY (context_id) REFERENCES context_entries(id) ON DELETE CASCADE,
+              UNIQUE(context_id, revision)
+            );
+        "#)],
+    },
+    Migration {
+        version: 8,
+        name: "soft_delete_tombstones",
+        steps: &[
+            Step::AddColumn {
+                table: "projects",
+                column: "deleted_at",
+                definition: "DATETIME",
+            },
+            Step::AddColumn {
+                table: "context_entries",
+                column: "deleted_at",
+                definition: "DATETIME",
+            },
+            Step::Sql(r#"
+                CREATE INDEX IF NOT EXISTS idx_projects_deleted ON projects(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_context_deleted ON context_entries(deleted_at);
+            "#),
+        ],
+    },
+    Migration {
+        version: 9,
+        name: "update_history_project",
+        steps: &[
+            Step::AddColumn {
+                table: "update_history",
+                column: "project_id",
+                definition: "INTEGER",
+            },
+            Step::Sql(r#"
+                UPDATE update_history SET project_id = entity_id
+                WHERE entity_type = 'project' AND project_id IS NULL;
+                
+                UPDATE update_history
+                SET project_id = (SELECT project_id FROM context_entries WHERE id = update_history.entity_id)
+                WHERE entity_type IN ('context', 'context_entry') AND project_id IS NULL;
+                
+                CREATE INDEX IF NOT EXISTS idx_update_history_project
+                ON update_history(project_id, timestamp DESC);
+                CREATE INDEX IF NOT EXISTS idx_update_history_timestamp
+                ON update_history(timestamp DESC);
+            "#),
+        ],
+    },
+    Migration {
+        version: 10,
+        name: "respect_explicit_updated_at",
+        // Imports and retention need to write their own timestamps
+        steps: &[Step::Sql(r#"
+            DROP TRIGGER IF EXISTS update_project_timestamp;
+            CREATE TRIGGER update_project_timestamp
+            AFTER UPDATE ON projects
+            WHEN NEW.updated_at IS OLD.updated_at
+            BEGIN
+              UPDATE projects SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+            END;
+
+            DROP TRIGGER IF EXISTS update_context_timestamp;
+            CREATE TRIGGER update_context_timestamp
+            AFTER UPDATE ON context_entries
+            WHEN NEW.updated_at IS OLD.updated_at
+            BEGIN
+              UPDATE context_entries SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+            END;
+        "#)],
+    },
+    Migration {
+        version: 11,
+        name: "custom_context_types",
+        steps: &[Step::Sql(r#"
+            CREATE TABLE IF NOT EXISTS context_types (
+              name TEXT PRIMARY KEY,
+              description TEXT,
+              value_schema TEXT,
+              metadata_schema TEXT,
+              created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+        "#)],
+    },
+    Migration {
+        version: 12,
+        name: "tenants_and_api_tokens",
+        steps: &[
+            Step::Sql(r#"
+                CREATE TABLE IF NOT EXISTS tenants (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  name TEXT NOT NULL UNIQUE,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+                
+                CREATE TABLE IF NOT EXISTS api_tokens (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  name TEXT NOT NULL,
+                  tenant_id INTEGER,
+                  role TEXT NOT NULL,
+                  token_hash TEXT NOT NULL UNIQUE,
+                  prefix TEXT NOT NULL,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  last_used_at DATETIME,
+                  revoked_at DATETIME,
+                  FOREIGN KEY (tenant_id) REFERENCES tenants(id)
+                );
+            "#),
+            Step::AddColumn {
+                table: "projects",
+                column: "tenant_id",
+                definition: "INTEGER REFERENCES tenants(id)",
+            },
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_projects_tenant ON projects(tenant_id);"),
+        ],
+    },
+    Migration {
+        version: 13,
+        name: "v1_imports",
+        steps: &[Step::Sql(r#"
+            CREATE TABLE IF NOT EXISTS v1_imports (
+              v1_id TEXT PRIMARY KEY,
+              imported_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+        "#)],
+    },
+    Migration {
+        version: 14,
+        name: "system_specific_context_keys",
+        steps: &[Step::Sql(r#"
+            DROP INDEX IF EXISTS idx_context_unique_key;
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_context_unique_key
+            ON context_entries(COALESCE(project_id, -1), key, COALESCE(system_id, -1));
+        "#)],
+    },
+    Migration {
+        version: 15,
+        name: "v1_import_versions",
+        steps: &[Step::Sql("ALTER TABLE v1_imports ADD COLUMN v1_updated_at TEXT;")],
+    },
+    Migration {
+        version: 16,
+        name: "project_delete_tombstones",
+        steps: &[
+            Step::AddColumn {
+                table: "context_entries",
+                column: "deleted_with_project",
+                definition: "BOOLEAN NOT NULL DEFAULT 0",
+            },
+            // Before this, entries sharing the project's tombstone time were its deletion
+            Step::Sql(r#"
+                UPDATE context_entries SET deleted_with_project = 1
+                WHERE deleted_at IS NOT NULL
+                  AND deleted_at = (SELECT deleted_at FROM projects WHERE projects.id = context_entries.project_id);
+            "#),
+        ],
+    },
+];
+
+/// Highest schema version this build understands
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Bring the database up to the latest schema version
+///
+/// Fails without touching the schema if the database was written by a newer
+/// version than this build knows about.
+pub async fn run_migrations(pool: &SqlitePool) -> Result<i64> {
+    ensure_migration_table(pool).await?;
+
+    let current = current_version(pool).await?;
+    let latest = latest_version();
+    if current > latest {
+        return Err(anyhow!(
+            "Database schema version {} is newer than supported version {}",
+            current,
+            latest
+        ));
+    }
+
+    // Base schema is idempotent and always applied, matching the TypeScript server
+    sqlx::raw_sql(BASE_SCHEMA)
+        .execute(pool)
+        .await
+        .context("Failed to apply base schema")?;
+
+    for migration in MIGRATIONS {
+        if is_applied(pool, migration.version).await? {
+            continue;
+        }
+
+        info!("Running migration {}: {}", migration.version, migration.name);
+        let mut tx = pool.begin().await?;
+        for step in migration.steps {
+            apply_step(&mut tx, step)
+                .await
+                .with_context(|| format!("Migration {} failed", migration.version))?;
+        }
+        sqlx::query("INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+
+    current_version(pool).await
+}
+
+/// Current schema version, 0 for a fresh database
+pub async fn current_version(pool: &SqlitePool) -> Result<i64> {
+    let version = sqlx::query_scalar::<_, Option<i64>>("SELECT MAX(version) FROM schema_migrations")
+        .fetch_one(pool)
+        .await?;
+    Ok(version.unwrap_or(0))
+}
+
+async fn ensure_migration_table(pool: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn is_applied(pool: &SqlitePool, version: i64) -> Result<bool> {
+    let found = sqlx::query_scalar::<_, i64>("SELECT 1 FROM schema_migrations WHERE version = ?1")
+        .bind(version)
+        .fetch_optional(pool)
+        .await?;
+    Ok(found.is_some())
+}
+
+async fn apply_step(tx: &mut Transaction<'_, Sqlite>, step: &Step) -> Result<()> {
+    match step {
+        Step::Sql(sql) => {
+            sqlx::raw_sql(sql).execute(&mut **tx).await?;
+        }
+        Step::AddColumn { table, column, definition } => {
+            let exists = sqlx::query_scalar::<_, i64>(
+                "SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2"
+            )
+            .bind(table)
+            .bind(column)
+            .fetch_optional(&mut **tx)
+            .await?
+            .is_some();
+
+            if exists {
+                debug!("Column {}.{} already exists, skipping", table, column);
+            } else {
+                let sql = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition);
+                sqlx::raw_sql(&sql).execute(&mut **tx).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+    use tempfile::TempDir;
+
+    async fn open_pool(dir: &TempDir) -> SqlitePool {
+        let options = SqliteConnectOptions::new()
+            .filename(dir.path().join("test.db"))
+            .create_if_missing(true);
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fresh_database_migrates_to_latest() {
+        let temp_dir = TempDir::new().unwrap();
+        let pool = open_pool(&temp_dir).await;
+
+        let version = run_migrations(&pool).await.unwrap();
+        assert_eq!(version, latest_version());
+
+        // Core tables and later-added columns are present
+        let role_id = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('context_entries') WHERE name = 'role_id'"
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(role_id, 1);
+
+        let roles = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM roles")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(roles, 5);
+    }
+
+    #[tokio::test]
+    async fn test_migrations_are_idempotent() {
+        let temp_dir = TempDir::new().unwrap();
+        let pool = open_pool(&temp_dir).await;
+
+        run_migrations(&pool).await.unwrap();
+        run_migrations(&pool).await.unwrap();
+
+        let applied = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM schema_migrations")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, MIGRATIONS.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn test_refuses_newer_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        let pool = open_pool(&temp_dir).await;
+
+        run_migrations(&pool).await.unwrap();
+        sqlx::query("INSERT INTO schema_migrations (version, name) VALUES (?1, 'from_the_future')")
+            .bind(latest_version() + 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let err = run_migrations(&pool).await.unwrap_err();
+        assert!(err.to_string().contains("newer than supported"));
+    }
+}
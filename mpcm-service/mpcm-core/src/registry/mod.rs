@@ -5,17 +5,19 @@
 
 mod router;
 
-pub use router::{RequestRouter, ToolRequest, RoutingStrategy};
+pub use router::{DirectRoute, RequestRouter, ToolRequest, RoutingStrategy};
 
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use tracing::{debug, info, warn};
 
+use crate::error::MpcmError;
+
 /// Service capability definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceCapability {
@@ -101,6 +103,21 @@ pub struct ServiceRegistration {
     pub last_health_check: Option<DateTime<Utc>>,
 }
 
+/// Shut down a service that has been taken out of the registry
+///
+/// A request still running holds another reference; the service is then
+/// dropped without shutdown() once that request finishes.
+async fn shut_down(name: &str, mut provider: Arc<dyn ServiceProvider>) {
+    match Arc::get_mut(&mut provider) {
+        Some(provider) => {
+            if let Err(e) = provider.shutdown().await {
+                warn!("Service {} failed to shut down: {}", name, e);
+            }
+        }
+        None => warn!("Service {} removed but shutdown() not called (still in use)", name),
+    }
+}
+
 /// Service Registry - manages all registered services
 pub struct ServiceRegistry {
     /// Registered services
@@ -129,7 +146,7 @@ impl ServiceRegistry {
         {
             let services = self.services.read().await;
             if services.contains_key(&name) {
-                return Err(anyhow!("Service {} is already registered", name));
+                return Err(MpcmError::ServiceAlreadyRegistered(name).into());
             }
         }
         
@@ -172,16 +189,12 @@ impl ServiceRegistry {
         info!("Unregistering service: {}", name);
         
         // Remove the service
-        let _provider = {
+        let provider = {
             let mut services = self.services.write().await;
             services.remove(name)
-                .ok_or_else(|| anyhow!("Service {} not found", name))?
+                .ok_or_else(|| MpcmError::ServiceNotFound(name.to_string()))?
         };
-        
-        // Note: We can't call shutdown on the service because it's behind an Arc
-        // and we may not have exclusive access. In a production system, you might
-        // want to add a shutdown signal mechanism instead.
-        warn!("Service {} removed but shutdown() not called (shared ownership)", name);
+        shut_down(name, provider).await;
         
         // Remove metadata
         {
@@ -193,12 +206,83 @@ impl ServiceRegistry {
         Ok(())
     }
     
+    /// Remove the services named in `remove` and register `add` in one step
+    ///
+    /// Every new service is initialized first; if any fails, those already
+    /// started are shut down again and the registry is left as it was. A
+    /// service in `add` replaces one registered under the same name. Callers
+    /// never see the registry between the old set and the new one.
+    pub async fn replace_services(&self, remove: &[&str], add: Vec<Box<dyn ServiceProvider>>) -> Result<()> {
+        let mut started: Vec<(Box<dyn ServiceProvider>, Vec<ServiceCapability>)> = Vec::new();
+        for mut provider in add {
+            let capabilities = async {
+                provider.initialize().await?;
+                provider.get_capabilities().await
+            }
+            .await;
+            match capabilities {
+                Ok(capabilities) => started.push((provider, capabilities)),
+                Err(e) => {
+                    let name = provider.name().to_string();
+                    for (mut provider, _) in started.into_iter().chain([(provider, Vec::new())]) {
+                        if let Err(e) = provider.shutdown().await {
+                            warn!("Service {} failed to shut down: {}", provider.name(), e);
+                        }
+                    }
+                    return Err(e.context(format!("Failed to start service {}", name)));
+                }
+            }
+        }
+        
+        let mut replaced = Vec::new();
+        {
+            let mut services = self.services.write().await;
+            let mut metadata = self.metadata.write().await;
+            let names = remove.iter().map(|name| name.to_string());
+            for name in names.chain(started.iter().map(|(provider, _)| provider.name().to_string())) {
+                if let Some(provider) = services.remove(&name) {
+                    metadata.remove(&name);
+                    replaced.push((name, provider));
+                }
+            }
+            for (provider, capabilities) in started {
+                let name = provider.name().to_string();
+                info!("Registering service: {}", name);
+                metadata.insert(name.clone(), ServiceRegistration {
+                    name: name.clone(),
+                    capabilities,
+                    status: ServiceStatus::Active,
+                    last_error: None,
+                    registered_at: Utc::now(),
+                    last_health_check: None,
+                });
+                services.insert(name, Arc::from(provider));
+            }
+        }
+        
+        for (name, provider) in replaced {
+            shut_down(&name, provider).await;
+            info!("Service {} unregistered", name);
+        }
+        Ok(())
+    }
+    
+    /// Unregister and shut down every service
+    pub async fn shutdown_all(&self) {
+        let names: Vec<String> = self.services.read().await.keys().cloned().collect();
+        for name in names {
+            if let Err(e) = self.unregister(&name).await {
+                warn!("Failed to unregister service {}: {}", name, e);
+            }
+        }
+    }
+    
     /// Get a service by name
     pub async fn get_service(&self, name: &str) -> Result<Arc<dyn ServiceProvider>> {
         let services = self.services.read().await;
         services.get(name)
             .cloned()
-            .ok_or_else(|| anyhow!("Service {} not found", name))
+            .ok_or_else(|| MpcmError::ServiceNotFound(name.to_string()).into())
     }
     
     /// List all registered services
@@ -291,7 +375,7 @@ impl ServiceRegistry {
         let metadata = self.metadata.read().await;
         metadata.get(name)
             .cloned()
-            .ok_or_else(|| anyhow!("Service {} not found", name))
+            .ok_or_else(|| MpcmError::ServiceNotFound(name.to_string()).into())
     }
     
     /// Start health check task
@@ -324,11 +408,13 @@ impl ServiceRegistry {
 mod tests {
     use super::*;
     use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     
     // Mock service provider for testing
     struct MockService {
         name: String,
         initialized: bool,
+        shutdowns: Arc<AtomicUsize>,
     }
     
     #[async_trait]
@@ -342,6 +428,10 @@ mod tests {
         }
         
         async fn initialize(&mut self) -> Result<()> {
+            // Lets tests stand in for a service that cannot start
+            if self.name.starts_with("broken") {
+                anyhow::bail!("{} cannot start", self.name);
+            }
             self.initialized = true;
             Ok(())
         }
@@ -368,6 +458,7 @@ mod tests {
         
         async fn shutdown(&mut self) -> Result<()> {
             self.initialized = false;
+            self.shutdowns.fetch_add(1, Ordering::SeqCst);
             Ok(())
         }
     }
@@ -379,6 +470,7 @@ mod tests {
         let service = Box::new(MockService {
             name: "test_service".to_string(),
             initialized: false,
+            shutdowns: Arc::default(),
         });
         
         // Register service
@@ -388,6 +480,7 @@ mod tests {
         let service2 = Box::new(MockService {
             name: "test_service".to_string(),
             initialized: false,
+            shutdowns: Arc::default(),
         });
         assert!(registry.register(service2).await.is_err());
         
@@ -404,6 +497,7 @@ mod tests {
         let service = Box::new(MockService {
             name: "test_service".to_string(),
             initialized: false,
+            shutdowns: Arc::default(),
         });
         
         registry.register(service).await.unwrap();
@@ -420,4 +514,63 @@ mod tests {
         let result = registry.execute("test_service", command).await.unwrap();
         assert!(result.success);
     }
+    
+    #[tokio::test]
+    async fn test_shutdown_all_shuts_services_down() {
+        let registry = ServiceRegistry::new(60);
+        let shutdowns = Arc::new(AtomicUsize::new(0));
+        
+        for name in ["first", "second"] {
+            let service = Box::new(MockService {
+                name: name.to_string(),
+                initialized: false,
+                shutdowns: shutdowns.clone(),
+            });
+            registry.register(service).await.unwrap();
+        }
+        
+        // A service still in use is removed without being shut down
+        let held = registry.get_service("first").await.unwrap();
+        registry.shutdown_all().await;
+        assert!(registry.list_services().await.is_empty());
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+        drop(held);
+    }
+    
+    #[tokio::test]
+    async fn test_replace_services_is_all_or_nothing() {
+        let registry = ServiceRegistry::new(60);
+        let shutdowns = Arc::new(AtomicUsize::new(0));
+        let mock = |name: &str| -> Box<dyn ServiceProvider> {
+            Box::new(MockService {
+                name: name.to_string(),
+                initialized: false,
+                shutdowns: shutdowns.clone(),
+            })
+        };
+        let names = |registry: &ServiceRegistry| {
+            let services = registry.services.clone();
+            async move {
+                let mut names: Vec<String> = services.read().await.keys().cloned().collect();
+                names.sort();
+                names
+            }
+        };
+        
+        registry.replace_services(&[], vec![mock("first"), mock("second")]).await.unwrap();
+        assert_eq!(names(&registry).await, ["first", "second"]);
+        
+        // One service failing to start leaves the old set in place
+        let result = registry.replace_services(&["first"], vec![mock("second"), mock("broken")]).await;
+        assert!(result.is_err());
+        assert_eq!(names(&registry).await, ["first", "second"]);
+        assert_eq!(registry.list_services().await.len(), 2);
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 2);
+        
+        // The replaced and removed services are shut down once swapped out
+        registry.replace_services(&["first"], vec![mock("second"), mock("third")]).await.unwrap();
+        assert_eq!(names(&registry).await, ["second", "third"]);
+        assert_eq!(registry.list_services().await.len(), 2);
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 4);
+    }
 }
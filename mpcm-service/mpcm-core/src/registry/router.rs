@@ -2,12 +2,14 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use anyhow::{anyhow, Result};
+use tokio::sync::RwLock;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use tracing::{debug, info};
 
 use super::{ServiceRegistry, ServiceCommand, ServiceResult};
+use crate::error::MpcmError;
 
 /// MCP tool request
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +35,12 @@ pub struct DirectRoute;
 /// Request router - handles routing MCP requests to appropriate services
 pub struct RequestRouter {
     registry: Arc<ServiceRegistry>,
+    /// Routing table, replaceable while requests are being routed
+    routes: RwLock<Routes>,
+}
+
+/// Tool mappings and the strategy for everything else
+struct Routes {
     /// Mapping of tool names to service names for direct routing
     tool_mappings: HashMap<String, String>,
     /// Default routing strategy
@@ -44,19 +52,31 @@ impl RequestRouter {
     pub fn new(registry: Arc<ServiceRegistry>) -> Self {
         Self {
             registry,
-            tool_mappings: HashMap::new(),
-            default_strategy: RoutingStrategy::FirstMatch,
+            routes: RwLock::new(Routes {
+                tool_mappings: HashMap::new(),
+                default_strategy: RoutingStrategy::FirstMatch,
+            }),
         }
     }
     
     /// Set default routing strategy
     pub fn set_default_strategy(&mut self, strategy: RoutingStrategy) {
-        self.default_strategy = strategy;
+        self.routes.get_mut().default_strategy = strategy;
     }
     
     /// Add a direct tool mapping
     pub fn add_tool_mapping(&mut self, tool: impl Into<String>, service: impl Into<String>) {
-        self.tool_mappings.insert(tool.into(), service.into());
+        self.routes.get_mut().tool_mappings.insert(tool.into(), service.into());
+    }
+    
+    /// Replace the default strategy and every tool mapping at once
+    ///
+    /// Requests already being routed finish with the old table.
+    pub async fn replace_routes(&self, strategy: RoutingStrategy, tool_mappings: HashMap<String, String>) {
+        let mut routes = self.routes.write().await;
+        routes.default_strategy = strategy;
+        routes.tool_mappings = tool_mappings;
+        info!("Routing {} mapped tools, {:?} for the rest", routes.tool_mappings.len(), strategy);
     }
     
     /// Route a tool request
@@ -69,10 +89,15 @@ impl RequestRouter {
     ) -> Result<ServiceResult> {
         info!("Routing request for tool: {}", request.tool);
         
+        let (mapped, strategy) = {
+            let routes = self.routes.read().await;
+            (routes.tool_mappings.get(&request.tool).cloned(), routes.default_strategy)
+        };
+        
         // Check for direct mapping first
-        if let Some(service_name) = self.tool_mappings.get(&request.tool) {
+        if let Some(service_name) = mapped {
             return self.execute_on_service(
-                service_name,
+                &service_name,
                 request,
                 project_name,
                 role_id,
@@ -81,7 +106,7 @@ impl RequestRouter {
         }
         
         // Use routing strategy
-        match self.default_strategy {
+        match strategy {
             RoutingStrategy::FirstMatch => {
                 self.route_first_match(request, project_name, role_id, context).await
             }
@@ -89,7 +114,7 @@ impl RequestRouter {
                 self.route_broadcast(request, project_name, role_id, context).await
             }
             RoutingStrategy::Direct(_) => {
-                Err(anyhow!("Direct routing requires tool mapping"))
+                Err(MpcmError::InvalidArgument("Direct routing requires tool mapping".to_string()).into())
             }
         }
     }
@@ -106,7 +131,7 @@ impl RequestRouter {
         let services = self.registry.find_by_capability(&request.tool).await;
         
         if services.is_empty() {
-            return Err(anyhow!("No service found for tool: {}", request.tool));
+            return Err(MpcmError::NoServiceForTool(request.tool).into());
         }
         
         // Use the first service
@@ -132,7 +157,7 @@ impl RequestRouter {
         let services = self.registry.find_by_capability(&request.tool).await;
         
         if services.is_empty() {
-            return Err(anyhow!("No service found for tool: {}", request.tool));
+            return Err(MpcmError::NoServiceForTool(request.tool).into());
         }
         
         let mut all_results = Vec::new();
@@ -162,7 +187,7 @@ impl RequestRouter {
         
         // Aggregate results
         if all_results.is_empty() && !errors.is_empty() {
-            return Err(anyhow!("All services failed: {}", errors.join(", ")));
+            return Err(MpcmError::AllServicesFailed { errors }.into());
         }
         
         Ok(ServiceResult {
@@ -238,4 +263,25 @@ mod tests {
         
         assert!(result.success);
     }
+    
+    #[tokio::test]
+    async fn test_replace_routes() {
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let router = RequestRouter::new(registry.clone());
+        let temp_dir = TempDir::new().unwrap();
+        registry.register(Box::new(FileSystemAdapter::new(temp_dir.path()))).await.unwrap();
+        
+        let request = || ToolRequest {
+            tool: "listDirectory".to_string(),
+            args: serde_json::json!({ "path": "." }),
+        };
+        
+        // Direct routing refuses tools without a mapping
+        router.replace_routes(RoutingStrategy::Direct(DirectRoute), HashMap::new()).await;
+        assert!(router.route_request(request(), None, None, None).await.is_err());
+        
+        let mappings = HashMap::from([("listDirectory".to_string(), "filesystem".to_string())]);
+        router.replace_routes(RoutingStrategy::Direct(DirectRoute), mappings).await;
+        assert!(router.route_request(request(), None, None, None).await.unwrap().success);
+    }
 }
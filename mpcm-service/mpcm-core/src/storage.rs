@@ -1,9 +1,20 @@
 //! Storage implementation using SQLx
 
 use crate::{Context, MpcmError, Result};
+use serde::Serialize;
 use sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
 use std::path::Path;
 
+/// An earlier value of a context entry
+#[derive(Debug, Clone, Serialize)]
+pub struct Revision {
+    /// The value as it was, with `updated_at` set to when it was replaced
+    #[serde(flatten)]
+    pub context: Context,
+    /// Role that replaced it, if the writer named one
+    pub role: Option<String>,
+}
+
 pub struct Storage {
     pool: SqlitePool,
 }
@@ -56,16 +67,62 @@ impl Storage {
         .execute(pool)
         .await?;
         
+        // Previous values of overwritten contexts
+        sqlx::query(r#"
+            CREATE TABLE IF NOT EXISTS v1_context_revisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                context_id TEXT NOT NULL,
+                project_name TEXT NOT NULL,
+                key TEXT NOT NULL,
+                context_type TEXT NOT NULL,
+                value TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                replaced_at TEXT NOT NULL,
+                role TEXT
+            )
+        "#)
+        .execute(pool)
+        .await?;
+        
         // Create indices for performance
         sqlx::query("CREATE INDEX IF NOT EXISTS idx_project_name ON contexts(project_name)")
             .execute(pool)
             .await?;
             
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_revisions_key ON v1_context_revisions(project_name, key)")
+            .execute(pool)
+            .await?;
+            
         Ok(())
     }
     
     /// Store a context entry
+    ///
+    /// Overwriting an existing key with a different value keeps the old value
+    /// in `v1_context_revisions`.
     pub async fn store_context(&self, context: &Context) -> Result<()> {
+        self.store_context_as(context, None).await
+    }
+    
+    /// Store a context entry, recording `role` as the author of any overwrite
+    pub async fn store_context_as(&self, context: &Context, role: Option<&str>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query(r#"
+            INSERT INTO v1_context_revisions (context_id, project_name, key, context_type, value, created_at, replaced_at, role)
+            SELECT id, project_name, key, context_type, value, updated_at, ?3, ?6
+            FROM contexts
+            WHERE project_name = ?1 AND key = ?2 AND (value != ?4 OR context_type != ?5)
+        "#)
+        .bind(context.project_name())
+        .bind(context.key())
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(context.value())
+        .bind(context.context_type())
+        .bind(role)
+        .execute(&mut *tx)
+        .await?;
+        
         sqlx::query(r#"
             INSERT INTO contexts (id, project_name, key, context_type, value, created_at, updated_at)
             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
@@ -81,12 +138,40 @@ impl Storage {
         .bind(context.value())
         .bind(context.created_at().to_rfc3339())
         .bind(context.created_at().to_rfc3339())
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
         
+        tx.commit().await?;
         Ok(())
     }
     
+    /// List previous values of a context entry, oldest first
+    pub async fn list_revisions(&self, project_name: &str, key: &str) -> Result<Vec<Revision>> {
+        let rows = sqlx::query_as::<_, (String, String, String, String, String, String, String, Option<String>)>(
+            "SELECT context_id, project_name, key, context_type, value, created_at, replaced_at, role
+             FROM v1_context_revisions WHERE project_name = ?1 AND key = ?2 ORDER BY id ASC"
+        )
+        .bind(project_name)
+        .bind(key)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.into_iter()
+            .map(|(id, project_name, key, context_type, value, created_at, replaced_at, role)| {
+                let context = Context::from_storage(
+                    id,
+                    project_name,
+                    key,
+                    context_type,
+                    value,
+                    parse_rfc3339(&created_at)?,
+                    parse_rfc3339(&replaced_at)?,
+                );
+                Ok(Revision { context, role })
+            })
+            .collect()
+    }
+    
     /// Retrieve a context entry
     pub async fn get_context(&self, project_name: &str, key: &str) -> Result<Option<Context>> {
         let row = sqlx::query_as::<_, (String, String, String, String, String, String, String)>(
@@ -128,4 +213,78 @@ impl Storage {
             None => Ok(None),
         }
     }
+    
+    /// Entries matching every given filter, most recently updated first
+    ///
+    /// `query` matches a substring of the key or value; a negative `limit`
+    /// returns every match.
+    pub async fn search_contexts(
+        &self,
+        project_name: Option<&str>,
+        query: Option<&str>,
+        context_type: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Context>> {
+        let rows = sqlx::query_as::<_, (String, String, String, String, String, String, String)>(
+            "SELECT id, project_name, key, context_type, value, created_at, updated_at
+             FROM contexts
+             WHERE (?1 IS NULL OR project_name = ?1)
+               AND (?2 IS NULL OR key LIKE ?2 OR value LIKE ?2)
+               AND (?3 IS NULL OR context_type = ?3)
+             ORDER BY updated_at DESC
+             LIMIT ?4"
+        )
+        .bind(project_name)
+        .bind(query.map(|q| format!("%{}%", q)))
+        .bind(context_type)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.into_iter()
+            .map(|(id, project_name, key, context_type, value, created_at, updated_at)| {
+                Ok(Context::from_storage(
+                    id,
+                    project_name,
+                    key,
+                    context_type,
+                    value,
+                    parse_rfc3339(&created_at)?,
+                    parse_rfc3339(&updated_at)?,
+                ))
+            })
+            .collect()
+    }
+    
+    /// Names of every project with at least one entry
+    pub async fn list_projects(&self) -> Result<Vec<String>> {
+        let names = sqlx::query_scalar::<_, String>(
+            "SELECT DISTINCT project_name FROM contexts ORDER BY project_name"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(names)
+    }
+    
+    /// Fold the write-ahead log back into the database file
+    pub async fn checkpoint(&self) -> Result<()> {
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    
+    /// Close every pooled connection
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+}
+
+fn parse_rfc3339(s: &str) -> Result<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.with_timezone(&chrono::Utc))
+        .map_err(|e| MpcmError::Serialization(serde_json::Error::io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Invalid date format: {}", e)
+        ))))
 }
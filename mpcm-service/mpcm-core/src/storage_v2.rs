@@ -1,12 +1,35 @@
 //! Storage implementation using SQLx
 //! Maintains compatibility with existing TypeScript schema
 
-use anyhow::{anyhow, Result};
-use chrono::{DateTime, Utc};
+use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use sqlx::{sqlite::SqlitePoolOptions, SqlitePool, Row};
-use std::path::Path;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePoolOptions, SqliteRow},
+    QueryBuilder, Row, Sqlite, SqlitePool, Transaction,
+};
+use similar::TextDiff;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::error::MpcmError;
+use crate::migrations;
+
+mod archive;
+mod backup;
+mod context_types;
+mod legacy;
+mod retention;
+mod tenants;
+
+pub use archive::{
+    ArchivedProjectRole, ArchivedRole, ArchivedRoleHandoff, ConflictPolicy, ImportResult,
+    ProjectArchive, ARCHIVE_FORMAT, ARCHIVE_VERSION,
+};
+pub use backup::BackupInfo;
+pub use retention::{ExpiredEntry, RetentionPolicy, RetentionReport, RetentionRule};
+pub use tenants::{ApiToken, IssuedToken, TOKEN_PREFIX};
 
 /// Context entry matching TypeScript schema
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,10 +66,305 @@ pub struct Project {
     pub last_accessed: DateTime<Utc>,
 }
 
+/// Machine that context can be tied to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct System {
+    pub id: i64,
+    pub name: String,
+    pub hostname: String,
+    pub platform: String,
+    pub is_current: bool,
+    pub metadata: Option<JsonValue>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Identity of a machine as detected at startup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemInfo {
+    pub name: String,
+    pub hostname: String,
+    pub platform: String,
+    pub metadata: JsonValue,
+}
+
+impl SystemInfo {
+    /// Describe the machine this process runs on
+    ///
+    /// Platform names follow Node's `process.platform` so rows match the
+    /// TypeScript server.
+    pub fn detect() -> Self {
+        let hostname = hostname::get()
+            .ok()
+            .and_then(|h| h.into_string().ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        let name = std::env::var("COMPUTERNAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| hostname.clone());
+        let platform = match std::env::consts::OS {
+            "macos" => "darwin",
+            "windows" => "win32",
+            os => os,
+        };
+        
+        Self {
+            name,
+            hostname,
+            platform: platform.to_string(),
+            metadata: serde_json::json!({
+                "arch": std::env::consts::ARCH,
+                "family": std::env::consts::FAMILY,
+                "cpus": std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            }),
+        }
+    }
+}
+
+/// Project metadata fields to write in `store_project_context`
+///
+/// Fields left as `None` keep their stored value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectUpdate {
+    pub description: Option<String>,
+    pub status: Option<String>,
+    pub repository_url: Option<String>,
+    pub local_directory: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub metadata: Option<JsonValue>,
+    pub primary_system_id: Option<i64>,
+}
+
+/// How multiple tags in a search filter are combined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    /// Entry has at least one of the tags
+    #[default]
+    Any,
+    /// Entry has every one of the tags
+    All,
+}
+
+/// Result ordering for context searches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// bm25 rank when a text query is given, otherwise most recently updated
+    #[default]
+    Relevance,
+    UpdatedDesc,
+    UpdatedAsc,
+    CreatedDesc,
+    CreatedAsc,
+    Key,
+}
+
+impl SortOrder {
+    fn as_sql(self, ranked: bool) -> &'static str {
+        match self {
+            SortOrder::Relevance if ranked => "rank ASC",
+            SortOrder::Relevance | SortOrder::UpdatedDesc => "ce.updated_at DESC",
+            SortOrder::UpdatedAsc => "ce.updated_at ASC",
+            SortOrder::CreatedDesc => "ce.created_at DESC",
+            SortOrder::CreatedAsc => "ce.created_at ASC",
+            SortOrder::Key => "ce.key ASC",
+        }
+    }
+}
+
+/// Typed filter for `Storage::search_context`
+///
+/// Every value is bound as a query parameter; empty fields impose no constraint.
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    pub project_name: Option<String>,
+    /// Full-text query over key, value and tags; supports "quoted phrases" and prefix*
+    pub query: Option<String>,
+    pub types: Vec<String>,
+    pub tags: Vec<String>,
+    pub tag_match: TagMatch,
+    pub role_id: Option<String>,
+    /// Lower bound on `updated_at`, ISO timestamp or relative like "-7d"
+    pub since: Option<String>,
+    /// Upper bound on `updated_at`, same formats as `since`
+    pub until: Option<String>,
+    pub is_system_specific: Option<bool>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+    pub sort: SortOrder,
+    /// Only projects owned by this tenant
+    pub tenant: Option<String>,
+}
+
+impl SearchFilter {
+    /// Default page size when no limit is given
+    pub const DEFAULT_LIMIT: i64 = 20;
+    /// Larger limits are clamped to this page size
+    pub const MAX_LIMIT: i64 = 100;
+    
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn project(mut self, project_name: impl Into<String>) -> Self {
+        self.project_name = Some(project_name.into());
+        self
+    }
+    
+    pub fn tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+    
+    pub fn query(mut self, query: impl Into<String>) -> Self {
+        self.query = Some(query.into());
+        self
+    }
+    
+    pub fn context_type(mut self, context_type: impl Into<String>) -> Self {
+        self.types.push(context_type.into());
+        self
+    }
+    
+    pub fn tags(mut self, tags: Vec<String>, tag_match: TagMatch) -> Self {
+        self.tags = tags;
+        self.tag_match = tag_match;
+        self
+    }
+    
+    pub fn role(mut self, role_id: impl Into<String>) -> Self {
+        self.role_id = Some(role_id.into());
+        self
+    }
+    
+    pub fn since(mut self, since: impl Into<String>) -> Self {
+        self.since = Some(since.into());
+        self
+    }
+    
+    pub fn until(mut self, until: impl Into<String>) -> Self {
+        self.until = Some(until.into());
+        self
+    }
+    
+    pub fn system_specific(mut self, is_system_specific: bool) -> Self {
+        self.is_system_specific = Some(is_system_specific);
+        self
+    }
+    
+    pub fn page(mut self, offset: i64, limit: i64) -> Self {
+        self.offset = Some(offset);
+        self.limit = Some(limit);
+        self
+    }
+    
+    pub fn sort(mut self, sort: SortOrder) -> Self {
+        self.sort = sort;
+        self
+    }
+    
+    /// Reject paging values SQLite would misread; a negative LIMIT means no limit
+    fn validate(&self) -> Result<()> {
+        if self.limit.is_some_and(|limit| limit < 0) {
+            return Err(MpcmError::InvalidArgument("limit must not be negative".to_string()).into());
+        }
+        if self.offset.is_some_and(|offset| offset < 0) {
+            return Err(MpcmError::InvalidArgument("offset must not be negative".to_string()).into());
+        }
+        Ok(())
+    }
+    
+    /// Append `AND ...` clauses for every set field
+    fn push_conditions(&self, qb: &mut QueryBuilder<'_, Sqlite>, current_system: Option<i64>) {
+        qb.push(" AND ce.deleted_at IS NULL AND p.deleted_at IS NULL");
+        
+        if let Some(proj) = &self.project_name {
+            qb.push(" AND p.name = ").push_bind(proj.clone());
+        }
+        
+        if let Some(tenant) = &self.tenant {
+            qb.push(" AND p.tenant_id = (SELECT id FROM tenants WHERE name = ")
+                .push_bind(tenant.clone())
+                .push(")");
+        }
+        
+        if !self.types.is_empty() {
+            qb.push(" AND ce.type IN (");
+            let mut list = qb.separated(", ");
+            for t in &self.types {
+                list.push_bind(t.clone());
+            }
+            qb.push(")");
+        }
+        
+        if !self.tags.is_empty() {
+            match self.tag_match {
+                TagMatch::Any => {
+                    qb.push(" AND EXISTS (SELECT 1 FROM json_each(ce.tags) WHERE json_each.value IN (");
+                    let mut list = qb.separated(", ");
+                    for tag in &self.tags {
+                        list.push_bind(tag.clone());
+                    }
+                    qb.push("))");
+                }
+                TagMatch::All => {
+                    for tag in &self.tags {
+                        qb.push(" AND EXISTS (SELECT 1 FROM json_each(ce.tags) WHERE json_each.value = ")
+                            .push_bind(tag.clone())
+                            .push(")");
+                    }
+                }
+            }
+        }
+        
+        if let Some(role) = &self.role_id {
+            qb.push(" AND ce.role_id = ").push_bind(role.clone());
+        }
+        
+        if let Some(since) = self.since.as_deref().and_then(parse_time_filter) {
+            qb.push(" AND ce.updated_at >= datetime(").push_bind(since).push(")");
+        }
+        
+        if let Some(until) = self.until.as_deref().and_then(parse_time_filter) {
+            qb.push(" AND ce.updated_at <= datetime(").push_bind(until).push(")");
+        }
+        
+        if let Some(sys_specific) = self.is_system_specific {
+            qb.push(" AND ce.is_system_specific = ").push_bind(sys_specific);
+            if sys_specific {
+                qb.push(" AND ce.system_id IS ").push_bind(current_system);
+            }
+        }
+    }
+    
+    /// Append ORDER BY, LIMIT and OFFSET
+    ///
+    /// Entries tied to the current system sort ahead of shared ones, and
+    /// entries tied to other systems come last.
+    fn push_order_and_page(&self, qb: &mut QueryBuilder<'_, Sqlite>, ranked: bool, current_system: Option<i64>) {
+        qb.push(" ORDER BY CASE WHEN ce.is_system_specific = 0 THEN 1 WHEN ce.system_id = ")
+            .push_bind(current_system)
+            .push(" THEN 0 ELSE 2 END, ")
+            .push(self.sort.as_sql(ranked));
+        qb.push(" LIMIT ").push_bind(self.limit.unwrap_or(Self::DEFAULT_LIMIT).min(Self::MAX_LIMIT));
+        qb.push(" OFFSET ").push_bind(self.offset.unwrap_or(0));
+    }
+}
+
 pub struct Storage {
     pool: SqlitePool,
+    db_path: PathBuf,
+    /// System row registered for this machine
+    current_system_id: Option<i64>,
+    /// Days a soft-deleted project or entry can still be restored
+    tombstone_retention_days: i64,
+    /// Rules applied by scheduled maintenance
+    retention_policy: RetentionPolicy,
 }
 
+/// Default window during which deleted data can be restored
+pub const DEFAULT_TOMBSTONE_RETENTION_DAYS: i64 = 30;
+
 impl Storage {
     /// Create new storage instance with SQLite
     pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
@@ -57,19 +375,114 @@ impl Storage {
             std::fs::create_dir_all(parent)?;
         }
         
-        // Create database URL
-        let db_url = format!("sqlite:{}", db_path.display());
+        // Create the database file on first run
+        let options = SqliteConnectOptions::new()
+            .filename(db_path)
+            .create_if_missing(true);
         
         // Create connection pool with optimizations
         let pool = SqlitePoolOptions::new()
             .max_connections(5)
-            .connect(&db_url)
+            .connect_with(options)
             .await?;
         
         // Enable optimizations
         Self::enable_optimizations(&pool).await?;
         
-        Ok(Self { pool })
+        // Create or upgrade the schema
+        let version = migrations::run_migrations(&pool).await?;
+        info!("Database schema at version {}", version);
+        
+        let mut storage = Self {
+            pool,
+            db_path: db_path.to_path_buf(),
+            current_system_id: None,
+            tombstone_retention_days: DEFAULT_TOMBSTONE_RETENTION_DAYS,
+            retention_policy: RetentionPolicy::default(),
+        };
+        
+        // Register this machine so context writes can be tied to it
+        let system = storage.register_system(&SystemInfo::detect(), true).await?;
+        info!("Current system: {} ({})", system.name, system.hostname);
+        storage.current_system_id = Some(system.id);
+        
+        // Bring over anything a v1 server wrote to this file
+        storage.import_v1_contexts().await?;
+        
+        Ok(storage)
+    }
+    
+    /// Fold the write-ahead log back into the database file
+    pub async fn checkpoint(&self) -> Result<()> {
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    
+    /// Close every pooled connection
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+    
+    /// Id of the system row for this machine
+    pub fn current_system_id(&self) -> Option<i64> {
+        self.current_system_id
+    }
+    
+    /// Insert or refresh a system, optionally marking it as current
+    pub async fn register_system(&self, info: &SystemInfo, set_current: bool) -> Result<System> {
+        let mut tx = self.pool.begin().await?;
+        
+        if set_current {
+            sqlx::query("UPDATE systems SET is_current = 0 WHERE is_current = 1")
+                .execute(&mut *tx)
+                .await?;
+        }
+        
+        let row = sqlx::query(
+            r#"
+            INSERT INTO systems (name, hostname, platform, is_current, metadata)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(hostname) DO UPDATE SET
+                name = excluded.name,
+                platform = excluded.platform,
+                is_current = MAX(is_current, excluded.is_current),
+                metadata = excluded.metadata,
+                last_seen = CURRENT_TIMESTAMP
+            RETURNING id, name, hostname, platform, is_current, metadata, created_at, last_seen
+            "#
+        )
+        .bind(&info.name)
+        .bind(&info.hostname)
+        .bind(&info.platform)
+        .bind(set_current)
+        .bind(serde_json::to_string(&info.metadata)?)
+        .fetch_one(&mut *tx)
+        .await?;
+        
+        tx.commit().await?;
+        row_to_system(&row)
+    }
+    
+    /// List every known system, current first
+    pub async fn list_systems(&self) -> Result<Vec<System>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, name, hostname, platform, is_current, metadata, created_at, last_seen
+            FROM systems
+            ORDER BY is_current DESC, last_seen DESC
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter().map(row_to_system).collect()
+    }
+    
+    /// Set how long soft-deleted data stays restorable
+    pub fn set_tombstone_retention_days(&mut self, days: i64) {
+        self.tombstone_retention_days = days;
     }
     
     async fn enable_optimizations(pool: &SqlitePool) -> Result<()> {
@@ -106,6 +519,8 @@ impl Storage {
         is_system_specific: Option<bool>,
         role_id: Option<String>,
     ) -> Result<StorageResult> {
+        self.validate_context(context_type, value, metadata.as_ref()).await?;
+        
         // First, get or create the project
         let project_id = self.ensure_project(project_name).await?;
         
@@ -113,255 +528,720 @@ impl Storage {
         let tags_json = tags.map(|t| serde_json::to_string(&t).unwrap_or_default());
         let metadata_json = metadata.map(|m| serde_json::to_string(&m).unwrap_or_default());
         
-        // Insert or update context entry
-        // First try to update existing entry
-        let update_result = sqlx::query(
+        // System-specific entries belong to the machine that wrote them
+        let system_id = if is_system_specific.unwrap_or(false) {
+            self.current_system_id
+        } else {
+            None
+        };
+        
+        let mut tx = self.pool.begin().await?;
+        
+        // Each system keeps its own value next to the shared one
+        let existing = sqlx::query_as::<_, (i64, String, String, Option<String>, Option<String>, Option<String>)>(
             r#"
-            UPDATE context_entries SET
-                type = ?3,
-                value = ?4,
-                tags = ?5,
-                metadata = ?6,
-                is_system_specific = ?7,
-                role_id = ?8,
-                updated_at = CURRENT_TIMESTAMP
-            WHERE project_id = ?1 AND key = ?2
+            SELECT id, type, value, tags, metadata, deleted_at FROM context_entries
+            WHERE project_id = ?1 AND key = ?2 AND system_id IS ?3
             "#
         )
         .bind(project_id)
         .bind(key)
-        .bind(context_type)
-        .bind(value)
-        .bind(&tags_json)
-        .bind(&metadata_json)
-        .bind(is_system_specific.unwrap_or(false))
-        .bind(&role_id)
-        .execute(&self.pool)
+        .bind(system_id)
+        .fetch_optional(&mut *tx)
         .await?;
         
-        // If no rows were updated, insert new entry
-        if update_result.rows_affected() == 0 {
-            sqlx::query(
-                r#"
-                INSERT INTO context_entries (
-                    project_id, key, type, value, tags, metadata, 
-                    is_system_specific, role_id, created_at, updated_at
+        let (context_id, action) = match existing {
+            Some((id, old_type, old_value, old_tags, old_metadata, deleted_at)) => {
+                // Keep the value being replaced as a revision
+                let changed = old_type != context_type
+                    || old_value != value
+                    || old_tags != tags_json
+                    || old_metadata != metadata_json;
+                if changed {
+                    record_revision(&mut tx, id, role_id.as_deref()).await?;
+                }
+                
+                let action = if deleted_at.is_some() {
+                    Some(ACTION_RESTORE)
+                } else if changed {
+                    Some(ACTION_UPDATE)
+                } else {
+                    None
+                };
+                
+                sqlx::query(
+                    r#"
+                    UPDATE context_entries SET
+                        type = ?2,
+                        value = ?3,
+                        tags = ?4,
+                        metadata = ?5,
+                        is_system_specific = ?6,
+                        role_id = ?7,
+                        system_id = ?8,
+                        deleted_at = NULL,
+                        deleted_with_project = 0,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE id = ?1
+                    "#
                 )
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
-                "#
-            )
-            .bind(project_id)
-            .bind(key)
-            .bind(context_type)
-            .bind(value)
-            .bind(&tags_json)
-            .bind(&metadata_json)
-            .bind(is_system_specific.unwrap_or(false))
-            .bind(&role_id)
-            .execute(&self.pool)
+                .bind(id)
+                .bind(context_type)
+                .bind(value)
+                .bind(&tags_json)
+                .bind(&metadata_json)
+                .bind(is_system_specific.unwrap_or(false))
+                .bind(&role_id)
+                .bind(system_id)
+                .execute(&mut *tx)
+                .await?;
+                
+                (id, action)
+            }
+            None => {
+                let id = sqlx::query(
+                    r#"
+                    INSERT INTO context_entries (
+                        project_id, key, type, value, tags, metadata, 
+                        is_system_specific, role_id, system_id, created_at, updated_at
+                    )
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+                    "#
+                )
+                .bind(project_id)
+                .bind(key)
+                .bind(context_type)
+                .bind(value)
+                .bind(&tags_json)
+                .bind(&metadata_json)
+                .bind(is_system_specific.unwrap_or(false))
+                .bind(&role_id)
+                .bind(system_id)
+                .execute(&mut *tx)
+                .await?
+                .last_insert_rowid();
+                
+                (id, Some(ACTION_CREATE))
+            }
+        };
+        
+        if let Some(action) = action {
+            HistoryEvent {
+                entity_type: ENTITY_CONTEXT,
+                entity_id: context_id,
+                project_id: Some(project_id),
+                action,
+                changes: Some(serde_json::json!({
+                    "key": key,
+                    "type": context_type,
+                    "value": value,
+                    "tags": tags_json,
+                })),
+                note: None,
+                role_id: role_id.as_deref(),
+            }
+            .record(&mut tx)
             .await?;
         }
         
+        tx.commit().await?;
+        
         Ok(StorageResult {
             success: true,
             message: Some(format!("Stored context '{}' for project '{}'", key, project_name)),
             key: Some(key.to_string()),
-            context_id: None, // We could fetch the ID if needed
+            context_id: Some(context_id.to_string()),
         })
     }
     
-    /// Ensure a project exists, creating it if necessary
-    async fn ensure_project(&self, project_name: &str) -> Result<i64> {
-        // Try to get existing project
-        let existing = sqlx::query_scalar::<_, i64>(
-            "SELECT id FROM projects WHERE name = ?1"
-        )
-        .bind(project_name)
-        .fetch_optional(&self.pool)
-        .await?;
-        
-        if let Some(id) = existing {
-            // Update last_accessed
-            sqlx::query("UPDATE projects SET last_accessed = CURRENT_TIMESTAMP WHERE id = ?1")
-                .bind(id)
-                .execute(&self.pool)
-                .await?;
-            return Ok(id);
-        }
+    /// List every revision of a context entry, oldest first
+    ///
+    /// The live value is included as the last revision with `is_current` set.
+    pub async fn list_revisions(&self, project_name: &str, key: &str) -> Result<Vec<ContextRevision>> {
+        let entry = self.find_entry(project_name, key).await?;
         
-        // Create new project
-        let result = sqlx::query(
+        let rows = sqlx::query(
             r#"
-            INSERT INTO projects (name, status, created_at, updated_at, last_accessed)
-            VALUES (?1, 'active', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+            SELECT revision, type, value, tags, metadata, role_id,
+                   created_at, replaced_at, replaced_by_role
+            FROM context_revisions
+            WHERE context_id = ?1
+            ORDER BY revision ASC
             "#
         )
-        .bind(project_name)
-        .execute(&self.pool)
+        .bind(entry.id)
+        .fetch_all(&self.pool)
         .await?;
         
-        Ok(result.last_insert_rowid())
-    }
-
-    /// Search context entries
-    pub async fn search_context(
-        &self,
-        project_name: Option<&str>,
-        query: Option<&str>,
-        context_type: Option<&str>,
-        _tags: Option<Vec<String>>,
-        since: Option<&str>,
-        limit: Option<i32>,
-    ) -> Result<Vec<ContextEntry>> {
-        let mut sql = String::from(
-            r#"
-            SELECT 
-                ce.id, ce.project_id, ce.system_id, ce.role_id,
-                ce.type, ce.key, ce.value, ce.is_system_specific,
-                ce.tags, ce.metadata, ce.created_at, ce.updated_at,
-                p.name as project_name
-            FROM context_entries ce
-            LEFT JOIN projects p ON ce.project_id = p.id
-            WHERE 1=1
-            "#
-        );
+        let mut revisions = rows.iter()
+            .map(row_to_revision)
+            .collect::<Result<Vec<_>>>()?;
         
-        let mut conditions = Vec::new();
+        let current_revision = revisions.last().map(|r| r.revision + 1).unwrap_or(1);
+        revisions.push(ContextRevision {
+            revision: current_revision,
+            context_type: entry.context_type,
+            value: entry.value,
+            tags: entry.tags,
+            metadata: entry.metadata,
+            role_id: entry.role_id,
+            created_at: entry.updated_at,
+            replaced_at: None,
+            replaced_by_role: None,
+            is_current: true,
+        });
         
-        // Add project filter
-        if let Some(proj) = project_name {
-            conditions.push(format!("p.name = '{}'", proj));
-        }
+        Ok(revisions)
+    }
+    
+    /// Get a single revision of a context entry
+    pub async fn get_revision(&self, project_name: &str, key: &str, revision: i64) -> Result<ContextRevision> {
+        self.list_revisions(project_name, key)
+            .await?
+            .into_iter()
+            .find(|r| r.revision == revision)
+            .ok_or_else(|| revision_not_found(project_name, key, revision))
+    }
+    
+    /// Compare two revisions of a context entry
+    pub async fn diff_revisions(
+        &self,
+        project_name: &str,
+        key: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<RevisionDiff> {
+        let revisions = self.list_revisions(project_name, key).await?;
+        let find = |n: i64| {
+            revisions.iter()
+                .find(|r| r.revision == n)
+                .ok_or_else(|| revision_not_found(project_name, key, n))
+        };
+        let old = find(from)?;
+        let new = find(to)?;
         
-        // Add type filter
-        if let Some(ct) = context_type {
-            conditions.push(format!("ce.type = '{}'", ct));
-        }
+        let unified = TextDiff::from_lines(&old.value, &new.value)
+            .unified_diff()
+            .header(&format!("{}@{}", key, from), &format!("{}@{}", key, to))
+            .to_string();
         
-        // Add query filter (search in key and value)
-        if let Some(q) = query {
-            conditions.push(format!(
-                "(ce.key LIKE '%{}%' OR ce.value LIKE '%{}%')", 
-                q, q
-            ));
-        }
+        Ok(RevisionDiff {
+            key: key.to_string(),
+            from,
+            to,
+            type_changed: old.context_type != new.context_type,
+            tags_changed: old.tags != new.tags,
+            metadata_changed: old.metadata != new.metadata,
+            unified,
+        })
+    }
+    
+    /// Make an earlier revision the live value again
+    ///
+    /// The value being replaced is kept as a revision, so a restore can itself be undone.
+    pub async fn restore_revision(
+        &self,
+        project_name: &str,
+        key: &str,
+        revision: i64,
+        role_id: Option<String>,
+    ) -> Result<StorageResult> {
+        let current = self.find_entry(project_name, key).await?;
+        let target = self.get_revision(project_name, key, revision).await?;
         
-        // Add time filter
-        if let Some(since_str) = since {
-            // Parse relative time like "-7d" or absolute ISO timestamp
-            if let Some(timestamp) = parse_time_filter(since_str) {
-                conditions.push(format!("ce.updated_at >= '{}'", timestamp));
-            }
-        }
+        self.store_context(
+            project_name,
+            key,
+            &target.context_type,
+            &target.value,
+            target.tags,
+            target.metadata,
+            Some(current.is_system_specific),
+            role_id,
+        )
+        .await?;
         
-        // Apply conditions
-        if !conditions.is_empty() {
-            sql.push_str(" AND ");
-            sql.push_str(&conditions.join(" AND "));
-        }
+        Ok(StorageResult {
+            success: true,
+            message: Some(format!("Restored context '{}' to revision {}", key, revision)),
+            key: Some(key.to_string()),
+            context_id: Some(current.id.to_string()),
+        })
+    }
+    
+    /// Delete a context entry
+    ///
+    /// Soft deletes leave a tombstone that `restore` can undo until the
+    /// retention window passes; `hard` removes the entry and its history now.
+    pub async fn delete_context(&self, project_name: &str, key: &str, hard: bool) -> Result<StorageResult> {
+        let entry = self.find_entry(project_name, key).await?;
         
-        // Add ordering and limit
-        sql.push_str(" ORDER BY ce.updated_at DESC");
-        if let Some(lim) = limit {
-            sql.push_str(&format!(" LIMIT {}", lim));
+        let mut tx = self.pool.begin().await?;
+        if hard {
+            purge_entries(&mut tx, &[entry.id]).await?;
         } else {
-            sql.push_str(" LIMIT 20"); // Default limit
+            sqlx::query("UPDATE context_entries SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?1")
+                .bind(entry.id)
+                .execute(&mut *tx)
+                .await?;
         }
         
-        // Execute query
-        let rows = sqlx::query(&sql)
-            .fetch_all(&self.pool)
-            .await?;
+        // Recorded after a purge so the deletion itself stays visible
+        HistoryEvent {
+            entity_type: ENTITY_CONTEXT,
+            entity_id: entry.id,
+            project_id: entry.project_id,
+            action: ACTION_DELETE,
+            changes: Some(serde_json::json!({ "key": key, "hard": hard })),
+            note: None,
+            role_id: None,
+        }
+        .record(&mut tx)
+        .await?;
+        tx.commit().await?;
         
-        // Convert rows to ContextEntry objects
-        let mut entries = Vec::new();
-        for row in rows {
-            entries.push(ContextEntry {
-                id: row.get("id"),
-                project_id: row.get("project_id"),
-                system_id: row.get("system_id"),
-                role_id: row.get("role_id"),
-                context_type: row.get("type"),
-                key: row.get("key"),
-                value: row.get("value"),
-                is_system_specific: row.get("is_system_specific"),
-                tags: row.get::<Option<String>, _>("tags")
-                    .and_then(|s| serde_json::from_str(&s).ok()),
-                metadata: row.get::<Option<String>, _>("metadata")
-                    .and_then(|s| serde_json::from_str(&s).ok()),
-                created_at: parse_datetime(&row.get::<String, _>("created_at"))?,
-                updated_at: parse_datetime(&row.get::<String, _>("updated_at"))?,
-            });
-        }
-        
-        Ok(entries)
+        Ok(StorageResult {
+            success: true,
+            message: Some(format!(
+                "{} context '{}' from project '{}'",
+                if hard { "Purged" } else { "Deleted" },
+                key,
+                project_name
+            )),
+            key: Some(key.to_string()),
+            context_id: Some(entry.id.to_string()),
+        })
     }
-
-    /// Get all context for a project
-    pub async fn get_project_context(
-        &self,
-        project_name: &str,
-        system_specific: Option<bool>,
-    ) -> Result<ProjectContextResult> {
-        // Get project info
-        let project = sqlx::query_as::<_, ProjectRow>(
-            r#"
-            SELECT id, name, description, status, repository_url, 
-                   local_directory, tags, metadata, primary_system_id,
-                   created_at, updated_at, last_accessed
-            FROM projects 
-            WHERE name = ?1
-            "#
+    
+    /// Delete a project together with its context entries
+    pub async fn delete_project(&self, project_name: &str, hard: bool) -> Result<StorageResult> {
+        let project_id = sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM projects WHERE name = ?1 AND deleted_at IS NULL"
         )
         .bind(project_name)
         .fetch_optional(&self.pool)
-        .await?;
-        
-        let project = match project {
-            Some(p) => p.into_project()?,
-            None => return Err(anyhow!("Project not found: {}", project_name)),
-        };
+        .await?
+        .ok_or_else(|| project_not_found(project_name))?;
         
-        // Get context entries
-        let mut query = String::from(
-            r#"
-            SELECT id, project_id, system_id, role_id, type, key, value,
-                   is_system_specific, tags, metadata, created_at, updated_at
-            FROM context_entries
-            WHERE project_id = ?1
-            "#
-        );
+        let mut tx = self.pool.begin().await?;
+        if hard {
+            purge_projects(&mut tx, &[project_id]).await?;
+        } else {
+            sqlx::query("UPDATE projects SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?1")
+                .bind(project_id)
+                .execute(&mut *tx)
+                .await?;
+            // Flagged so restore brings back exactly these, not entries deleted on their own
+            sqlx::query(
+                r#"
+                UPDATE context_entries SET deleted_at = CURRENT_TIMESTAMP, deleted_with_project = 1
+                WHERE project_id = ?1 AND deleted_at IS NULL
+                "#
+            )
+            .bind(project_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        HistoryEvent {
+            entity_type: ENTITY_PROJECT,
+            entity_id: project_id,
+            project_id: Some(project_id),
+            action: ACTION_DELETE,
+            changes: Some(serde_json::json!({ "name": project_name, "hard": hard })),
+            note: None,
+            role_id: None,
+        }
+        .record(&mut tx)
+        .await?;
+        tx.commit().await?;
+        
+        Ok(StorageResult {
+            success: true,
+            message: Some(format!(
+                "{} project '{}'",
+                if hard { "Purged" } else { "Deleted" },
+                project_name
+            )),
+            key: None,
+            context_id: None,
+        })
+    }
+    
+    /// Undo a soft delete that is still inside the retention window
+    ///
+    /// With a key, restores that entry; without one, restores the project
+    /// and the entries deleted along with it. Entries of a deleted project
+    /// can only be restored once the project is.
+    pub async fn restore(&self, project_name: &str, key: Option<&str>) -> Result<StorageResult> {
+        let window = format!("-{} days", self.tombstone_retention_days);
+        
+        let (project_id, project_deleted_at) = sqlx::query_as::<_, (i64, Option<String>)>(
+            "SELECT id, deleted_at FROM projects WHERE name = ?1"
+        )
+        .bind(project_name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| project_not_found(project_name))?;
+        
+        if key.is_some() && project_deleted_at.is_some() {
+            return Err(project_deleted(project_name));
+        }
+        
+        let mut tx = self.pool.begin().await?;
+        
+        // Checked before writing, so a refused restore holds no write lock
+        let event = if let Some(key) = key {
+            let entry_id = sqlx::query_scalar::<_, i64>(
+                r#"
+                SELECT id FROM context_entries
+                WHERE project_id = ?1 AND key = ?2
+                  AND (system_id IS NULL OR system_id = ?4)
+                  AND deleted_at IS NOT NULL AND deleted_at >= datetime('now', ?3)
+                ORDER BY system_id IS NULL
+                LIMIT 1
+                "#
+            )
+            .bind(project_id)
+            .bind(key)
+            .bind(&window)
+            .bind(self.current_system_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| MpcmError::NothingToRestore {
+                project: project_name.to_string(),
+                key: Some(key.to_string()),
+            })?;
+            sqlx::query("UPDATE context_entries SET deleted_at = NULL WHERE id = ?1")
+                .bind(entry_id)
+                .execute(&mut *tx)
+                .await?;
+            
+            HistoryEvent {
+                entity_type: ENTITY_CONTEXT,
+                entity_id: entry_id,
+                project_id: Some(project_id),
+                action: ACTION_RESTORE,
+                changes: Some(serde_json::json!({ "key": key })),
+                note: None,
+                role_id: None,
+            }
+        } else {
+            let restorable = sqlx::query_scalar::<_, i64>(
+                r#"
+                SELECT 1 FROM projects
+                WHERE id = ?1 AND deleted_at IS NOT NULL AND deleted_at >= datetime('now', ?2)
+                "#
+            )
+            .bind(project_id)
+            .bind(&window)
+            .fetch_optional(&mut *tx)
+            .await?;
+            
+            if restorable.is_none() {
+                return Err(MpcmError::NothingToRestore {
+                    project: project_name.to_string(),
+                    key: None,
+                }
+                .into());
+            }
+            sqlx::query("UPDATE projects SET deleted_at = NULL WHERE id = ?1")
+                .bind(project_id)
+                .execute(&mut *tx)
+                .await?;
+            
+            sqlx::query(
+                r#"
+                UPDATE context_entries SET deleted_at = NULL, deleted_with_project = 0
+                WHERE project_id = ?1 AND deleted_with_project = 1
+                "#
+            )
+            .bind(project_id)
+            .execute(&mut *tx)
+            .await?;
+            
+            HistoryEvent {
+                entity_type: ENTITY_PROJECT,
+                entity_id: project_id,
+                project_id: Some(project_id),
+                action: ACTION_RESTORE,
+                changes: Some(serde_json::json!({ "name": project_name })),
+                note: None,
+                role_id: None,
+            }
+        };
+        
+        event.record(&mut tx).await?;
+        tx.commit().await?;
         
-        if let Some(sys_specific) = system_specific {
-            query.push_str(&format!(" AND is_system_specific = {}", sys_specific as i32));
+        Ok(StorageResult {
+            success: true,
+            message: Some(match key {
+                Some(key) => format!("Restored context '{}' in project '{}'", key, project_name),
+                None => format!("Restored project '{}'", project_name),
+            }),
+            key: key.map(str::to_string),
+            context_id: None,
+        })
+    }
+    
+    /// Permanently remove soft-deleted data
+    ///
+    /// Removes tombstones older than `older_than_days` (default: the retention
+    /// window), optionally limited to one project. Hard deletes cascade to
+    /// revisions and `update_history`.
+    pub async fn purge(&self, project_name: Option<&str>, older_than_days: Option<i64>) -> Result<PurgeResult> {
+        let cutoff = format!("-{} days", older_than_days.unwrap_or(self.tombstone_retention_days));
+        
+        let mut tx = self.pool.begin().await?;
+        
+        let project_ids = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT id FROM projects
+            WHERE deleted_at IS NOT NULL AND deleted_at <= datetime('now', ?1)
+              AND (?2 IS NULL OR name = ?2)
+            "#
+        )
+        .bind(&cutoff)
+        .bind(project_name)
+        .fetch_all(&mut *tx)
+        .await?;
+        
+        let entry_ids = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT ce.id FROM context_entries ce
+            LEFT JOIN projects p ON ce.project_id = p.id
+            WHERE ce.deleted_at IS NOT NULL AND ce.deleted_at <= datetime('now', ?1)
+              AND (?2 IS NULL OR p.name = ?2)
+            "#
+        )
+        .bind(&cutoff)
+        .bind(project_name)
+        .fetch_all(&mut *tx)
+        .await?;
+        
+        let mut result = purge_entries(&mut tx, &entry_ids).await?;
+        let project_result = purge_projects(&mut tx, &project_ids).await?;
+        result.projects += project_result.projects;
+        result.context_entries += project_result.context_entries;
+        result.history_entries += project_result.history_entries;
+        
+        tx.commit().await?;
+        
+        info!(
+            "Purged {} projects, {} context entries, {} history rows",
+            result.projects, result.context_entries, result.history_entries
+        );
+        Ok(result)
+    }
+    
+    /// Look up a single live context entry
+    ///
+    /// This machine's entry wins over the shared one; other systems' entries
+    /// are never matched.
+    async fn find_entry(&self, project_name: &str, key: &str) -> Result<ContextEntry> {
+        let row = sqlx::query(
+            r#"
+            SELECT ce.id, ce.project_id, ce.system_id, ce.role_id, ce.type, ce.key, ce.value,
+                   ce.is_system_specific, ce.tags, ce.metadata, ce.created_at, ce.updated_at
+            FROM context_entries ce
+            JOIN projects p ON ce.project_id = p.id
+            WHERE p.name = ?1 AND ce.key = ?2
+              AND (ce.system_id IS NULL OR ce.system_id = ?3)
+              AND ce.deleted_at IS NULL AND p.deleted_at IS NULL
+            ORDER BY ce.system_id IS NULL
+            LIMIT 1
+            "#
+        )
+        .bind(project_name)
+        .bind(key)
+        .bind(self.current_system_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        match row {
+            Some(row) => row_to_entry(&row),
+            None => Err(MpcmError::ContextNotFound {
+                project: project_name.to_string(),
+                key: key.to_string(),
+            }
+            .into()),
+        }
+    }
+    
+    /// Ensure a project exists, creating it if necessary
+    ///
+    /// Soft-deleted projects are refused rather than revived, since their
+    /// entries would stay deleted; `restore` brings both back.
+    async fn ensure_project(&self, project_name: &str) -> Result<i64> {
+        // Try to get existing project
+        let existing = sqlx::query_as::<_, (i64, Option<String>)>(
+            "SELECT id, deleted_at FROM projects WHERE name = ?1"
+        )
+        .bind(project_name)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        if let Some((id, deleted_at)) = existing {
+            if deleted_at.is_some() {
+                return Err(project_deleted(project_name));
+            }
+            sqlx::query("UPDATE projects SET last_accessed = CURRENT_TIMESTAMP WHERE id = ?1")
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+            return Ok(id);
         }
         
-        query.push_str(" ORDER BY updated_at DESC");
+        // Create new project
+        let mut tx = self.pool.begin().await?;
+        let project_id = sqlx::query(
+            r#"
+            INSERT INTO projects (name, status, primary_system_id, created_at, updated_at, last_accessed)
+            VALUES (?1, 'active', ?2, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+            "#
+        )
+        .bind(project_name)
+        .bind(self.current_system_id)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        
+        HistoryEvent {
+            entity_type: ENTITY_PROJECT,
+            entity_id: project_id,
+            project_id: Some(project_id),
+            action: ACTION_CREATE,
+            changes: Some(serde_json::json!({ "name": project_name })),
+            note: None,
+            role_id: None,
+        }
+        .record(&mut tx)
+        .await?;
+        tx.commit().await?;
+        
+        Ok(project_id)
+    }
+
+    /// Search context entries
+    ///
+    /// A text query is matched against the FTS5 index and results carry a
+    /// bm25 score plus a highlighted snippet.
+    pub async fn search_context(&self, filter: &SearchFilter) -> Result<Vec<SearchHit>> {
+        filter.validate()?;
+        let fts_query = filter.query.as_deref().and_then(to_fts_query);
+        
+        let mut qb = QueryBuilder::<Sqlite>::new(
+            r#"
+            SELECT 
+                ce.id, ce.project_id, ce.system_id, ce.role_id,
+                ce.type, ce.key, ce.value, ce.is_system_specific,
+                ce.tags, ce.metadata, ce.created_at, ce.updated_at,
+                p.name as project_name
+            "#
+        );
+        
+        if fts_query.is_some() {
+            qb.push(
+                r#",
+                bm25(context_fts, 10.0, 1.0, 5.0) AS rank,
+                snippet(context_fts, -1, '<mark>', '</mark>', '...', 16) AS snippet
+                "#
+            );
+        }
+        
+        qb.push(" FROM context_entries ce LEFT JOIN projects p ON ce.project_id = p.id");
+        
+        match &fts_query {
+            Some(q) => {
+                qb.push(" JOIN context_fts ON context_fts.rowid = ce.id WHERE context_fts MATCH ")
+                    .push_bind(q.clone());
+            }
+            None => {
+                qb.push(" WHERE 1=1");
+            }
+        }
+        
+        filter.push_conditions(&mut qb, self.current_system_id);
+        filter.push_order_and_page(&mut qb, fts_query.is_some(), self.current_system_id);
+        
+        let rows = qb.build()
+            .fetch_all(&self.pool)
+            .await?;
+        
+        rows.iter()
+            .map(|row| {
+                let (score, snippet) = if fts_query.is_some() {
+                    (Some(-row.get::<f64, _>("rank")), row.get("snippet"))
+                } else {
+                    (None, None)
+                };
+                Ok(SearchHit {
+                    entry: row_to_entry(row)?,
+                    score,
+                    snippet,
+                })
+            })
+            .collect()
+    }
+
+    /// Get all context for a project
+    pub async fn get_project_context(
+        &self,
+        project_name: &str,
+        system_specific: Option<bool>,
+    ) -> Result<ProjectContextResult> {
+        // Get project info
+        let project = sqlx::query_as::<_, ProjectRow>(
+            r#"
+            SELECT id, name, description, status, repository_url, 
+                   local_directory, tags, metadata, primary_system_id,
+                   created_at, updated_at, last_accessed
+            FROM projects 
+            WHERE name = ?1 AND deleted_at IS NULL
+            "#
+        )
+        .bind(project_name)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        let project = match project {
+            Some(p) => p.into_project()?,
+            None => return Err(project_not_found(project_name)),
+        };
+        
+        let filter = match system_specific {
+            Some(true) => " AND is_system_specific = 1 AND system_id IS ?2",
+            Some(false) => " AND is_system_specific = 0",
+            None => "",
+        };
+        
+        // Prefer this machine's entries, then shared ones, then other systems',
+        // keeping only the preferred copy of each key
+        let preference = "CASE WHEN is_system_specific = 0 THEN 1 WHEN system_id = ?2 THEN 0 ELSE 2 END";
+        let query = format!(
+            r#"
+            SELECT id, project_id, system_id, role_id, type, key, value,
+                   is_system_specific, tags, metadata, created_at, updated_at
+            FROM (
+                SELECT *, ROW_NUMBER() OVER (PARTITION BY key ORDER BY {preference}, updated_at DESC) AS copy
+                FROM context_entries
+                WHERE project_id = ?1 AND deleted_at IS NULL{filter}
+            )
+            WHERE copy = 1
+            ORDER BY {preference}, updated_at DESC
+            "#
+        );
         
         let rows = sqlx::query(&query)
             .bind(project.id)
+            .bind(self.current_system_id)
             .fetch_all(&self.pool)
             .await?;
         
-        let mut entries = Vec::new();
-        for row in rows {
-            entries.push(ContextEntry {
-                id: row.get("id"),
-                project_id: row.get("project_id"),
-                system_id: row.get("system_id"),
-                role_id: row.get("role_id"),
-                context_type: row.get("type"),
-                key: row.get("key"),
-                value: row.get("value"),
-                is_system_specific: row.get("is_system_specific"),
-                tags: row.get::<Option<String>, _>("tags")
-                    .and_then(|s| serde_json::from_str(&s).ok()),
-                metadata: row.get::<Option<String>, _>("metadata")
-                    .and_then(|s| serde_json::from_str(&s).ok()),
-                created_at: parse_datetime(&row.get::<String, _>("created_at"))?,
-                updated_at: parse_datetime(&row.get::<String, _>("updated_at"))?,
-            });
-        }
+        let entries = rows.iter()
+            .map(row_to_entry)
+            .collect::<Result<Vec<_>>>()?;
         
         Ok(ProjectContextResult {
             project,
@@ -377,11 +1257,12 @@ impl Storage {
                    local_directory, tags, metadata, primary_system_id,
                    created_at, updated_at, last_accessed
             FROM projects
+            WHERE deleted_at IS NULL
             "#
         );
         
         if !include_archived.unwrap_or(false) {
-            query.push_str(" WHERE status != 'archived'");
+            query.push_str(" AND status != 'archived'");
         }
         
         query.push_str(" ORDER BY last_accessed DESC");
@@ -398,6 +1279,156 @@ impl Storage {
         Ok(projects)
     }
 
+    /// Create a project or patch its metadata
+    ///
+    /// Only the fields set in `update` are written, so callers can change a
+    /// single field without resending the rest.
+    pub async fn store_project_context(&self, project_name: &str, update: &ProjectUpdate) -> Result<Project> {
+        let tags_json = update.tags.as_ref().map(serde_json::to_string).transpose()?;
+        let metadata_json = update.metadata.as_ref().map(serde_json::to_string).transpose()?;
+        
+        let mut tx = self.pool.begin().await?;
+        
+        let existing = sqlx::query_scalar::<_, Option<String>>("SELECT deleted_at FROM projects WHERE name = ?1")
+            .bind(project_name)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if matches!(existing, Some(Some(_))) {
+            return Err(project_deleted(project_name));
+        }
+        let existed = existing.is_some();
+        
+        sqlx::query(
+            r#"
+            INSERT INTO projects (
+                name, description, status, repository_url, local_directory,
+                tags, metadata, primary_system_id,
+                created_at, updated_at, last_accessed
+            )
+            VALUES (?1, ?2, COALESCE(?3, 'active'), ?4, ?5, ?6, ?7, COALESCE(?8, ?9),
+                    CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+            ON CONFLICT(name) DO UPDATE SET
+                description = COALESCE(?2, description),
+                status = COALESCE(?3, status),
+                repository_url = COALESCE(?4, repository_url),
+                local_directory = COALESCE(?5, local_directory),
+                tags = COALESCE(?6, tags),
+                metadata = COALESCE(?7, metadata),
+                primary_system_id = COALESCE(?8, primary_system_id),
+                updated_at = CURRENT_TIMESTAMP,
+                last_accessed = CURRENT_TIMESTAMP
+            "#
+        )
+        .bind(project_name)
+        .bind(&update.description)
+        .bind(&update.status)
+        .bind(&update.repository_url)
+        .bind(&update.local_directory)
+        .bind(&tags_json)
+        .bind(&metadata_json)
+        .bind(update.primary_system_id)
+        .bind(self.current_system_id)
+        .execute(&mut *tx)
+        .await?;
+        
+        let row = sqlx::query_as::<_, ProjectRow>(
+            r#"
+            SELECT id, name, description, status, repository_url, 
+                   local_directory, tags, metadata, primary_system_id,
+                   created_at, updated_at, last_accessed
+            FROM projects 
+            WHERE name = ?1
+            "#
+        )
+        .bind(project_name)
+        .fetch_one(&mut *tx)
+        .await?;
+        
+        HistoryEvent {
+            entity_type: ENTITY_PROJECT,
+            entity_id: row.id,
+            project_id: Some(row.id),
+            action: if existed { ACTION_UPDATE } else { ACTION_CREATE },
+            changes: Some(serde_json::to_value(update)?),
+            note: None,
+            role_id: None,
+        }
+        .record(&mut tx)
+        .await?;
+        tx.commit().await?;
+        
+        info!("Stored project context for {}", project_name);
+        row.into_project()
+    }
+
+    /// List recorded changes, newest first
+    pub async fn get_recent_updates(&self, filter: &HistoryFilter) -> Result<Vec<UpdateRecord>> {
+        let mut qb = QueryBuilder::<Sqlite>::new(
+            r#"
+            SELECT h.id, h.entity_type, h.entity_id, h.project_id, p.name AS project_name,
+                   h.action, h.changes, h.user_note, h.role_id, h.timestamp
+            FROM update_history h
+            LEFT JOIN projects p ON h.project_id = p.id
+            WHERE 1=1
+            "#
+        );
+        
+        if let Some(project) = &filter.project_name {
+            qb.push(" AND p.name = ").push_bind(project.clone());
+        }
+        
+        if let Some(tenant) = &filter.tenant {
+            qb.push(" AND p.tenant_id = (SELECT id FROM tenants WHERE name = ")
+                .push_bind(tenant.clone())
+                .push(")");
+        }
+        
+        if let Some(entity_type) = &filter.entity_type {
+            // The TypeScript server logs context changes as 'context'
+            if entity_type == ENTITY_CONTEXT || entity_type == "context" {
+                qb.push(" AND h.entity_type IN ('context', 'context_entry')");
+            } else {
+                qb.push(" AND h.entity_type = ").push_bind(entity_type.clone());
+            }
+        }
+        
+        if let Some(action) = &filter.action {
+            qb.push(" AND h.action = ").push_bind(action.clone());
+        }
+        
+        let since = filter.since.as_deref().unwrap_or(HistoryFilter::DEFAULT_SINCE);
+        if let Some(since) = parse_time_filter(since) {
+            qb.push(" AND h.timestamp >= datetime(").push_bind(since).push(")");
+        }
+        
+        if let Some(until) = filter.until.as_deref().and_then(parse_time_filter) {
+            qb.push(" AND h.timestamp <= datetime(").push_bind(until).push(")");
+        }
+        
+        qb.push(" ORDER BY h.timestamp DESC, h.id DESC LIMIT ")
+            .push_bind(filter.limit.unwrap_or(HistoryFilter::DEFAULT_LIMIT));
+        
+        let rows = qb.build().fetch_all(&self.pool).await?;
+        
+        rows.iter()
+            .map(|row| {
+                let changes: Option<String> = row.get("changes");
+                Ok(UpdateRecord {
+                    id: row.get("id"),
+                    entity_type: row.get("entity_type"),
+                    entity_id: row.get("entity_id"),
+                    project_id: row.get("project_id"),
+                    project_name: row.get("project_name"),
+                    action: row.get("action"),
+                    changes: changes.and_then(|s| serde_json::from_str(&s).ok()),
+                    user_note: row.get("user_note"),
+                    role_id: row.get("role_id"),
+                    timestamp: parse_datetime(&row.get::<String, _>("timestamp"))?,
+                })
+            })
+            .collect()
+    }
+
     /// Update project status
     pub async fn update_project_status(
         &self,
@@ -405,42 +1436,40 @@ impl Storage {
         status: &str,
         note: Option<&str>,
     ) -> Result<StorageResult> {
-        let result = sqlx::query(
+        let mut tx = self.pool.begin().await?;
+        
+        let (project_id, old_status) = sqlx::query_as::<_, (i64, Option<String>)>(
+            "SELECT id, status FROM projects WHERE name = ?1 AND deleted_at IS NULL"
+        )
+        .bind(project_name)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| project_not_found(project_name))?;
+        
+        sqlx::query(
             r#"
             UPDATE projects 
             SET status = ?1, updated_at = CURRENT_TIMESTAMP
-            WHERE name = ?2
+            WHERE id = ?2
             "#
         )
         .bind(status)
-        .bind(project_name)
-        .execute(&self.pool)
+        .bind(project_id)
+        .execute(&mut *tx)
         .await?;
         
-        if result.rows_affected() == 0 {
-            return Err(anyhow!("Project not found: {}", project_name));
-        }
-        
-        // Add to update history if note provided
-        if let Some(note_text) = note {
-            if let Ok(Some(project_id)) = sqlx::query_scalar::<_, i64>(
-                "SELECT id FROM projects WHERE name = ?1"
-            )
-            .bind(project_name)
-            .fetch_optional(&self.pool)
-            .await {
-                sqlx::query(
-                    r#"
-                    INSERT INTO update_history (entity_type, entity_id, action, user_note)
-                    VALUES ('project', ?1, 'update', ?2)
-                    "#
-                )
-                .bind(project_id)
-                .bind(note_text)
-                .execute(&self.pool)
-                .await?;
-            }
+        HistoryEvent {
+            entity_type: ENTITY_PROJECT,
+            entity_id: project_id,
+            project_id: Some(project_id),
+            action: ACTION_STATUS_CHANGE,
+            changes: Some(serde_json::json!({ "from": old_status, "to": status })),
+            note,
+            role_id: None,
         }
+        .record(&mut tx)
+        .await?;
+        tx.commit().await?;
         
         Ok(StorageResult {
             success: true,
@@ -461,6 +1490,95 @@ pub struct StorageResult {
     pub context_id: Option<String>,
 }
 
+/// A stored version of a context entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextRevision {
+    pub revision: i64,
+    #[serde(rename = "type")]
+    pub context_type: String,
+    pub value: String,
+    pub tags: Option<Vec<String>>,
+    pub metadata: Option<JsonValue>,
+    /// Role that wrote this version
+    pub role_id: Option<String>,
+    /// When this version was written
+    pub created_at: DateTime<Utc>,
+    /// When this version was overwritten, `None` for the live value
+    pub replaced_at: Option<DateTime<Utc>>,
+    pub replaced_by_role: Option<String>,
+    pub is_current: bool,
+}
+
+/// Differences between two revisions of a context entry
+#[derive(Debug, Serialize)]
+pub struct RevisionDiff {
+    pub key: String,
+    pub from: i64,
+    pub to: i64,
+    pub type_changed: bool,
+    pub tags_changed: bool,
+    pub metadata_changed: bool,
+    /// Unified line diff of the values
+    pub unified: String,
+}
+
+/// A search result, with ranking details when a text query was used
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub entry: ContextEntry,
+    /// Negated bm25 rank, higher is more relevant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// Matching excerpt with hits wrapped in `<mark>` tags
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+/// Filter for `get_recent_updates`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryFilter {
+    pub project_name: Option<String>,
+    /// `project`, `context_entry` or `system`
+    pub entity_type: Option<String>,
+    /// e.g. `create`, `update`, `delete`, `restore`, `status_change`
+    pub action: Option<String>,
+    /// Relative ("-1d", "-12 hours") or absolute start of the window
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<i64>,
+    /// Only changes to projects owned by this tenant
+    pub tenant: Option<String>,
+}
+
+impl HistoryFilter {
+    pub const DEFAULT_SINCE: &'static str = "-1d";
+    pub const DEFAULT_LIMIT: i64 = 50;
+}
+
+/// One row of `update_history`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateRecord {
+    pub id: i64,
+    pub entity_type: String,
+    pub entity_id: i64,
+    pub project_id: Option<i64>,
+    pub project_name: Option<String>,
+    pub action: String,
+    pub changes: Option<JsonValue>,
+    pub user_note: Option<String>,
+    pub role_id: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Counts of rows removed by a purge
+#[derive(Debug, Default, Serialize)]
+pub struct PurgeResult {
+    pub projects: u64,
+    pub context_entries: u64,
+    pub history_entries: u64,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ProjectContextResult {
     pub project: Project,
@@ -506,23 +1624,280 @@ impl ProjectRow {
 }
 
 // Utility functions
+
+/// Copy the live value of a context entry into `context_revisions`
+async fn record_revision(
+    tx: &mut Transaction<'_, Sqlite>,
+    context_id: i64,
+    replaced_by_role: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO context_revisions (
+            context_id, revision, type, value, tags, metadata, role_id,
+            created_at, replaced_at, replaced_by_role
+        )
+        SELECT id,
+               COALESCE((SELECT MAX(revision) FROM context_revisions WHERE context_id = ?1), 0) + 1,
+               type, value, tags, metadata, role_id,
+               updated_at, CURRENT_TIMESTAMP, ?2
+        FROM context_entries
+        WHERE id = ?1
+        "#
+    )
+    .bind(context_id)
+    .bind(replaced_by_role)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+fn row_to_system(row: &SqliteRow) -> Result<System> {
+    let metadata: Option<String> = row.get("metadata");
+    Ok(System {
+        id: row.get("id"),
+        name: row.get("name"),
+        hostname: row.get("hostname"),
+        platform: row.get("platform"),
+        is_current: row.get("is_current"),
+        metadata: metadata.and_then(|s| serde_json::from_str(&s).ok()),
+        created_at: parse_datetime(&row.get::<String, _>("created_at"))?,
+        last_seen: parse_datetime(&row.get::<String, _>("last_seen"))?,
+    })
+}
+
+fn project_not_found(project_name: &str) -> anyhow::Error {
+    MpcmError::ProjectNotFound { project: project_name.to_string() }.into()
+}
+
+fn project_deleted(project_name: &str) -> anyhow::Error {
+    MpcmError::ProjectDeleted { project: project_name.to_string() }.into()
+}
+
+fn revision_not_found(project_name: &str, key: &str, revision: i64) -> anyhow::Error {
+    MpcmError::RevisionNotFound {
+        project: project_name.to_string(),
+        key: key.to_string(),
+        revision,
+    }
+    .into()
+}
+
+const ENTITY_PROJECT: &str = "project";
+const ENTITY_CONTEXT: &str = "context_entry";
+
+const ACTION_CREATE: &str = "create";
+const ACTION_UPDATE: &str = "update";
+const ACTION_DELETE: &str = "delete";
+const ACTION_RESTORE: &str = "restore";
+const ACTION_STATUS_CHANGE: &str = "status_change";
+const ACTION_IMPORT: &str = "import";
+
+/// A change to write into `update_history`
+struct HistoryEvent<'a> {
+    entity_type: &'a str,
+    entity_id: i64,
+    project_id: Option<i64>,
+    action: &'a str,
+    changes: Option<JsonValue>,
+    note: Option<&'a str>,
+    role_id: Option<&'a str>,
+}
+
+impl HistoryEvent<'_> {
+    async fn record(self, conn: &mut SqliteConnection) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO update_history (
+                entity_type, entity_id, project_id, action, changes, user_note, role_id
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#
+        )
+        .bind(self.entity_type)
+        .bind(self.entity_id)
+        .bind(self.project_id)
+        .bind(self.action)
+        .bind(self.changes.map(|c| c.to_string()))
+        .bind(self.note)
+        .bind(self.role_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Hard delete context entries and their history
+async fn purge_entries(tx: &mut Transaction<'_, Sqlite>, entry_ids: &[i64]) -> Result<PurgeResult> {
+    let mut result = PurgeResult::default();
+    
+    for id in entry_ids {
+        result.history_entries += sqlx::query(
+            "DELETE FROM update_history WHERE entity_type IN ('context', 'context_entry') AND entity_id = ?1"
+        )
+        .bind(id)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+        
+        sqlx::query("DELETE FROM context_revisions WHERE context_id = ?1")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        
+        result.context_entries += sqlx::query("DELETE FROM context_entries WHERE id = ?1")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?
+            .rows_affected();
+    }
+    
+    Ok(result)
+}
+
+/// Hard delete projects with all of their entries, role links and history
+async fn purge_projects(tx: &mut Transaction<'_, Sqlite>, project_ids: &[i64]) -> Result<PurgeResult> {
+    let mut result = PurgeResult::default();
+    
+    for id in project_ids {
+        let entry_ids = sqlx::query_scalar::<_, i64>("SELECT id FROM context_entries WHERE project_id = ?1")
+            .bind(id)
+            .fetch_all(&mut **tx)
+            .await?;
+        let entries = purge_entries(tx, &entry_ids).await?;
+        result.context_entries += entries.context_entries;
+        result.history_entries += entries.history_entries;
+        
+        result.history_entries += sqlx::query(
+            "DELETE FROM update_history WHERE entity_type = 'project' AND entity_id = ?1"
+        )
+        .bind(id)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+        
+        // Role tables reference projects without ON DELETE CASCADE
+        for table in ["project_roles", "active_roles", "role_handoffs"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE project_id = ?1", table))
+                .bind(id)
+                .execute(&mut **tx)
+                .await?;
+        }
+        
+        result.projects += sqlx::query("DELETE FROM projects WHERE id = ?1")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?
+            .rows_affected();
+    }
+    
+    Ok(result)
+}
+
+fn row_to_revision(row: &SqliteRow) -> Result<ContextRevision> {
+    Ok(ContextRevision {
+        revision: row.get("revision"),
+        context_type: row.get("type"),
+        value: row.get("value"),
+        tags: row.get::<Option<String>, _>("tags")
+            .and_then(|s| serde_json::from_str(&s).ok()),
+        metadata: row.get::<Option<String>, _>("metadata")
+            .and_then(|s| serde_json::from_str(&s).ok()),
+        role_id: row.get("role_id"),
+        created_at: parse_datetime(&row.get::<String, _>("created_at"))?,
+        replaced_at: row.get::<Option<String>, _>("replaced_at")
+            .map(|s| parse_datetime(&s))
+            .transpose()?,
+        replaced_by_role: row.get("replaced_by_role"),
+        is_current: false,
+    })
+}
+
+fn row_to_entry(row: &SqliteRow) -> Result<ContextEntry> {
+    Ok(ContextEntry {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        system_id: row.get("system_id"),
+        role_id: row.get("role_id"),
+        context_type: row.get("type"),
+        key: row.get("key"),
+        value: row.get("value"),
+        is_system_specific: row.get("is_system_specific"),
+        tags: row.get::<Option<String>, _>("tags")
+            .and_then(|s| serde_json::from_str(&s).ok()),
+        metadata: row.get::<Option<String>, _>("metadata")
+            .and_then(|s| serde_json::from_str(&s).ok()),
+        created_at: parse_datetime(&row.get::<String, _>("created_at"))?,
+        updated_at: parse_datetime(&row.get::<String, _>("updated_at"))?,
+    })
+}
+
 fn parse_datetime(s: &str) -> Result<DateTime<Utc>> {
+    // SQLite's CURRENT_TIMESTAMP is UTC without an offset
     Ok(DateTime::parse_from_rfc3339(s)
-        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S"))
         .map(|dt| dt.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").map(|dt| dt.and_utc()))
         .unwrap_or_else(|_| Utc::now()))
 }
 
+/// Convert user input into a safe FTS5 expression
+///
+/// `"quoted text"` becomes a phrase, a trailing `*` becomes a prefix query and
+/// every other term is quoted so punctuation cannot be read as FTS5 syntax.
+/// Terms are combined with implicit AND.
+fn to_fts_query(input: &str) -> Option<String> {
+    fn quote(term: &str) -> String {
+        format!("\"{}\"", term.replace('"', "\"\""))
+    }
+    
+    let mut terms = Vec::new();
+    let mut chars = input.chars().peekable();
+    
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            if !phrase.trim().is_empty() {
+                terms.push(quote(phrase.trim()));
+            }
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            match word.strip_suffix('*') {
+                Some(prefix) if !prefix.is_empty() => terms.push(format!("{}*", quote(prefix))),
+                Some(_) => {}
+                None => terms.push(quote(&word)),
+            }
+        }
+    }
+    
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
 fn parse_time_filter(s: &str) -> Option<String> {
-    // Handle relative times like "-7d", "-1h", etc.
-    if s.starts_with('-') {
-        let amount = s[1..s.len()-1].parse::<i64>().ok()?;
-        let unit = s.chars().last()?;
-        
-        let duration = match unit {
-            'd' => chrono::Duration::days(amount),
-            'h' => chrono::Duration::hours(amount),
-            'm' => chrono::Duration::minutes(amount),
+    // Handle relative times like "-7d", "-1h", "-1 day", "-2 weeks"
+    if let Some(relative) = s.strip_prefix('-') {
+        let relative = relative.trim();
+        let split = relative.find(|c: char| !c.is_ascii_digit()).unwrap_or(relative.len());
+        let amount = relative[..split].parse::<i64>().ok()?;
+        
+        let duration = match relative[split..].trim() {
+            "d" | "day" | "days" => chrono::Duration::days(amount),
+            "h" | "hour" | "hours" => chrono::Duration::hours(amount),
+            "m" | "min" | "minute" | "minutes" => chrono::Duration::minutes(amount),
+            "w" | "week" | "weeks" => chrono::Duration::weeks(amount),
             _ => return None,
         };
         
@@ -533,3 +1908,629 @@ fn parse_time_filter(s: &str) -> Option<String> {
         Some(s.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    #[tokio::test]
+    async fn test_fresh_database_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("fresh.db")).await.unwrap();
+        
+        storage
+            .store_context("test-project", "test-key", "decision", "Use SQLite", None, None, None, None)
+            .await
+            .unwrap();
+        
+        let result = storage.get_project_context("test-project", None).await.unwrap();
+        assert_eq!(result.project.name, "test-project");
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.entries[0].value, "Use SQLite");
+    }
+    
+    async fn seeded_storage(temp_dir: &TempDir) -> Storage {
+        let storage = Storage::new(temp_dir.path().join("search.db")).await.unwrap();
+        let tags = |t: &[&str]| Some(t.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+        
+        storage
+            .store_context("alpha", "db-choice", "decision", "Use SQLite", tags(&["db", "arch"]), None, None, Some("architect".into()))
+            .await
+            .unwrap();
+        storage
+            .store_context("alpha", "api-style", "decision", "JSON-RPC", tags(&["arch"]), None, None, None)
+            .await
+            .unwrap();
+        storage
+            .store_context("alpha", "todo-1", "todo", "Write docs", tags(&["docs"]), None, None, None)
+            .await
+            .unwrap();
+        storage
+            .store_context("it's", "quoted", "note", "O'Reilly", None, None, None, None)
+            .await
+            .unwrap();
+        storage
+    }
+    
+    #[tokio::test]
+    async fn test_search_filter_tags_and_types() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = seeded_storage(&temp_dir).await;
+        
+        let any = SearchFilter::new()
+            .project("alpha")
+            .tags(vec!["db".into(), "docs".into()], TagMatch::Any);
+        assert_eq!(storage.search_context(&any).await.unwrap().len(), 2);
+        
+        let all = SearchFilter::new()
+            .tags(vec!["db".into(), "arch".into()], TagMatch::All);
+        let entries = storage.search_context(&all).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].entry.key, "db-choice");
+        
+        let typed = SearchFilter::new()
+            .context_type("decision")
+            .role("architect");
+        assert_eq!(storage.search_context(&typed).await.unwrap().len(), 1);
+        
+        let paged = SearchFilter::new()
+            .project("alpha")
+            .sort(SortOrder::Key)
+            .page(1, 1);
+        let entries = storage.search_context(&paged).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].entry.key, "db-choice");
+    }
+    
+    #[tokio::test]
+    async fn test_search_page_size_is_bounded() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("paging.db")).await.unwrap();
+        for i in 0..=SearchFilter::MAX_LIMIT {
+            storage
+                .store_context("alpha", &format!("note-{i}"), "note", "Paged", None, None, None, None)
+                .await
+                .unwrap();
+        }
+        
+        let huge = SearchFilter::new().page(0, i64::MAX);
+        let hits = storage.search_context(&huge).await.unwrap();
+        assert_eq!(hits.len() as i64, SearchFilter::MAX_LIMIT);
+        
+        for filter in [SearchFilter::new().page(0, -1), SearchFilter::new().page(-1, 10)] {
+            let err = storage.search_context(&filter).await.unwrap_err();
+            assert!(matches!(err.downcast_ref(), Some(MpcmError::InvalidArgument(_))));
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_search_filter_binds_values() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = seeded_storage(&temp_dir).await;
+        
+        let quoted = SearchFilter::new()
+            .project("it's")
+            .query("O'Reilly")
+            .since("-1d");
+        let entries = storage.search_context(&quoted).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        
+        let injected = SearchFilter::new().project("alpha' OR '1'='1");
+        assert!(storage.search_context(&injected).await.unwrap().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_full_text_search_ranking_and_snippets() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = seeded_storage(&temp_dir).await;
+        
+        // Prefix query matches "SQLite" and the hit is highlighted
+        let hits = storage.search_context(&SearchFilter::new().query("sqli*")).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entry.key, "db-choice");
+        assert!(hits[0].snippet.as_deref().unwrap().contains("<mark>SQLite</mark>"));
+        assert!(hits[0].score.is_some());
+        
+        // Phrase query with punctuation is treated literally
+        let hits = storage.search_context(&SearchFilter::new().query("\"api-style\"")).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        
+        // Tags are indexed too, and results come back best first
+        let hits = storage.search_context(&SearchFilter::new().query("arch*")).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits[0].score >= hits[1].score);
+    }
+    
+    #[tokio::test]
+    async fn test_full_text_index_follows_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = seeded_storage(&temp_dir).await;
+        
+        storage
+            .store_context("alpha", "db-choice", "decision", "Use Postgres", None, None, None, None)
+            .await
+            .unwrap();
+        let stale = SearchFilter::new().query("SQLite");
+        assert!(storage.search_context(&stale).await.unwrap().is_empty());
+        let fresh = SearchFilter::new().query("postgres");
+        assert_eq!(storage.search_context(&fresh).await.unwrap().len(), 1);
+        
+        sqlx::query("DELETE FROM context_entries WHERE key = 'db-choice'")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+        assert!(storage.search_context(&fresh).await.unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_to_fts_query() {
+        assert_eq!(to_fts_query("foo bar").unwrap(), "\"foo\" \"bar\"");
+        assert_eq!(to_fts_query("\"exact phrase\" pre*").unwrap(), "\"exact phrase\" \"pre\"*");
+        assert_eq!(to_fts_query("a-b OR").unwrap(), "\"a-b\" \"OR\"");
+        assert!(to_fts_query("  * \"\" ").is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_revisions_diff_and_restore() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("revisions.db")).await.unwrap();
+        
+        storage
+            .store_context("alpha", "arch", "decision", "Monolith\nSQLite", None, None, None, Some("architect".into()))
+            .await
+            .unwrap();
+        storage
+            .store_context("alpha", "arch", "decision", "Monolith\nPostgres", None, None, None, Some("developer".into()))
+            .await
+            .unwrap();
+        // Identical write does not add a revision
+        storage
+            .store_context("alpha", "arch", "decision", "Monolith\nPostgres", None, None, None, Some("developer".into()))
+            .await
+            .unwrap();
+        
+        let revisions = storage.list_revisions("alpha", "arch").await.unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].revision, 1);
+        assert_eq!(revisions[0].value, "Monolith\nSQLite");
+        assert_eq!(revisions[0].role_id.as_deref(), Some("architect"));
+        assert_eq!(revisions[0].replaced_by_role.as_deref(), Some("developer"));
+        assert!(revisions[1].is_current);
+        
+        let diff = storage.diff_revisions("alpha", "arch", 1, 2).await.unwrap();
+        assert!(diff.unified.contains("-SQLite"));
+        assert!(diff.unified.contains("+Postgres"));
+        assert!(!diff.type_changed);
+        
+        storage.restore_revision("alpha", "arch", 1, Some("architect".into())).await.unwrap();
+        let revisions = storage.list_revisions("alpha", "arch").await.unwrap();
+        assert_eq!(revisions.len(), 3);
+        assert_eq!(revisions[2].value, "Monolith\nSQLite");
+        assert_eq!(revisions[1].value, "Monolith\nPostgres");
+        
+        assert!(storage.get_revision("alpha", "arch", 9).await.is_err());
+        assert!(storage.list_revisions("alpha", "missing").await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_soft_delete_restore_and_purge() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = seeded_storage(&temp_dir).await;
+        
+        // Soft-deleted entries disappear from reads but can be restored
+        storage.delete_context("alpha", "db-choice", false).await.unwrap();
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        assert!(context.entries.iter().all(|e| e.key != "db-choice"));
+        let hits = storage.search_context(&SearchFilter::new().query("SQLite")).await.unwrap();
+        assert!(hits.is_empty());
+        
+        storage.restore("alpha", Some("db-choice")).await.unwrap();
+        let hits = storage.search_context(&SearchFilter::new().query("SQLite")).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(storage.restore("alpha", Some("db-choice")).await.is_err());
+        
+        // Deleting a project hides it with its entries; restore brings both back
+        storage.delete_project("alpha", false).await.unwrap();
+        assert!(storage.list_projects(Some(true)).await.unwrap().iter().all(|p| p.name != "alpha"));
+        assert!(storage.get_project_context("alpha", None).await.is_err());
+        storage.restore("alpha", None).await.unwrap();
+        assert_eq!(storage.get_project_context("alpha", None).await.unwrap().entries.len(), 3);
+        
+        // Tombstones inside the retention window survive a default purge
+        storage.delete_context("alpha", "todo-1", false).await.unwrap();
+        let purged = storage.purge(None, None).await.unwrap();
+        assert_eq!(purged.context_entries, 0);
+        
+        let purged = storage.purge(Some("alpha"), Some(0)).await.unwrap();
+        assert_eq!(purged.context_entries, 1);
+        assert!(storage.restore("alpha", Some("todo-1")).await.is_err());
+        
+        // Hard deletes cascade through history and revisions
+        storage.update_project_status("alpha", "active", Some("kickoff")).await.unwrap();
+        storage
+            .store_context("alpha", "api-style", "decision", "REST", None, None, None, None)
+            .await
+            .unwrap();
+        storage.delete_project("alpha", true).await.unwrap();
+        
+        // Only the record of the deletion itself is kept
+        let remaining: i64 = sqlx::query_scalar(
+            "SELECT (SELECT COUNT(*) FROM update_history WHERE action != 'delete' AND project_id NOT IN (SELECT id FROM projects)) \
+             + (SELECT COUNT(*) FROM context_revisions)"
+        )
+        .fetch_one(&storage.pool)
+        .await
+        .unwrap();
+        assert_eq!(remaining, 0);
+        assert!(storage.restore("alpha", None).await.is_err());
+        assert_eq!(storage.search_context(&SearchFilter::new()).await.unwrap().len(), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_writes_to_deleted_projects_are_refused() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = seeded_storage(&temp_dir).await;
+        storage.delete_project("alpha", false).await.unwrap();
+        
+        let err = storage
+            .store_context("alpha", "db-choice", "decision", "Postgres", None, None, None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(MpcmError::ProjectDeleted { .. })));
+        let err = storage
+            .store_project_context("alpha", &ProjectUpdate::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<MpcmError>().unwrap().code(), crate::codes::PROJECT_DELETED);
+        
+        // Nothing was written and the project is still restorable whole
+        storage.restore("alpha", None).await.unwrap();
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(context.entries.len(), 3);
+        assert!(context.entries.iter().all(|e| e.value != "Postgres"));
+    }
+    
+    #[tokio::test]
+    async fn test_project_restore_skips_entries_deleted_on_their_own() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = seeded_storage(&temp_dir).await;
+        
+        // Deleted in the same second as the project, but not by its deletion
+        storage.delete_context("alpha", "todo-1", false).await.unwrap();
+        storage.delete_project("alpha", false).await.unwrap();
+        
+        // Entries of a deleted project wait for the project itself
+        let err = storage.restore("alpha", Some("db-choice")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(MpcmError::ProjectDeleted { .. })));
+        assert!(storage.get_project_context("alpha", None).await.is_err());
+        
+        storage.restore("alpha", None).await.unwrap();
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        let mut keys: Vec<_> = context.entries.iter().map(|e| e.key.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, ["api-style", "db-choice"]);
+        storage.restore("alpha", Some("todo-1")).await.unwrap();
+        assert_eq!(storage.get_project_context("alpha", None).await.unwrap().entries.len(), 3);
+    }
+    
+    #[tokio::test]
+    async fn test_system_specific_context_prefers_current_system() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("systems.db")).await.unwrap();
+        let current = storage.current_system_id().unwrap();
+        
+        // Reopening the database keeps the same system row
+        let reopened = Storage::new(temp_dir.path().join("systems.db")).await.unwrap();
+        assert_eq!(reopened.current_system_id(), Some(current));
+        
+        storage
+            .store_context("alpha", "shared", "note", "everywhere", None, None, Some(false), None)
+            .await
+            .unwrap();
+        storage
+            .store_context("alpha", "local-path", "config", "/home/me", None, None, Some(true), None)
+            .await
+            .unwrap();
+        
+        // An entry written from another machine sharing the database
+        let other = storage
+            .register_system(
+                &SystemInfo {
+                    name: "laptop".into(),
+                    hostname: "laptop.local".into(),
+                    platform: "darwin".into(),
+                    metadata: serde_json::json!({}),
+                },
+                false,
+            )
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO context_entries (project_id, system_id, type, key, value, is_system_specific) \
+             SELECT id, ?1, 'config', 'laptop-path', '/Users/me', 1 FROM projects WHERE name = 'alpha'"
+        )
+        .bind(other.id)
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+        
+        let systems = storage.list_systems().await.unwrap();
+        assert_eq!(systems.len(), 2);
+        assert!(systems[0].is_current && systems[0].id == current);
+        assert!(!systems[1].is_current);
+        
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        let keys: Vec<_> = context.entries.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["local-path", "shared", "laptop-path"]);
+        assert_eq!(context.entries[0].system_id, Some(current));
+        assert_eq!(context.entries[1].system_id, None);
+        assert_eq!(context.project.primary_system_id, Some(current));
+        
+        let local = storage.get_project_context("alpha", Some(true)).await.unwrap();
+        assert_eq!(local.entries.len(), 1);
+        assert_eq!(local.entries[0].key, "local-path");
+        
+        let hits = storage
+            .search_context(&SearchFilter::new().project("alpha").sort(SortOrder::Key))
+            .await
+            .unwrap();
+        assert_eq!(hits[0].entry.key, "local-path");
+        assert_eq!(hits[2].entry.key, "laptop-path");
+        let hits = storage
+            .search_context(&SearchFilter::new().system_specific(true))
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_system_specific_entries_share_keys_with_shared_ones() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("systems.db")).await.unwrap();
+        
+        storage.store_context("alpha", "path", "config", "/srv/app", None, None, None, None).await.unwrap();
+        storage
+            .store_context("alpha", "path", "config", "/home/me/app", None, None, Some(true), None)
+            .await
+            .unwrap();
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        let values: Vec<_> = context.entries.iter().map(|e| e.value.as_str()).collect();
+        assert_eq!(values, ["/home/me/app"]);
+        let shared = storage.get_project_context("alpha", Some(false)).await.unwrap();
+        assert_eq!(shared.entries[0].value, "/srv/app");
+        
+        // Another machine's entry under the same key is allowed but never matched here
+        sqlx::query(
+            "INSERT INTO systems (name, hostname, platform) VALUES ('laptop', 'laptop.local', 'darwin'); \
+             INSERT INTO context_entries (project_id, system_id, type, key, value, is_system_specific) \
+             SELECT p.id, s.id, 'config', 'path', '/Users/me/app', 1 \
+             FROM projects p, systems s WHERE p.name = 'alpha' AND s.hostname = 'laptop.local'"
+        )
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+        
+        // Reads prefer this machine's value, then fall back to the shared one
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(context.entries.len(), 1);
+        assert_eq!(context.entries[0].value, "/home/me/app");
+        let revisions = storage.list_revisions("alpha", "path").await.unwrap();
+        assert_eq!(revisions.last().unwrap().value, "/home/me/app");
+        storage.delete_context("alpha", "path", false).await.unwrap();
+        let revisions = storage.list_revisions("alpha", "path").await.unwrap();
+        assert_eq!(revisions.last().unwrap().value, "/srv/app");
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        let values: Vec<_> = context.entries.iter().map(|e| e.value.as_str()).collect();
+        assert_eq!(values, ["/srv/app"]);
+        storage.delete_context("alpha", "path", false).await.unwrap();
+        assert!(storage.list_revisions("alpha", "path").await.is_err());
+        
+        storage.restore("alpha", Some("path")).await.unwrap();
+        let revisions = storage.list_revisions("alpha", "path").await.unwrap();
+        assert_eq!(revisions.last().unwrap().value, "/home/me/app");
+    }
+    
+    #[tokio::test]
+    async fn test_project_context_lists_each_key_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("systems.db")).await.unwrap();
+        
+        storage
+            .store_context("alpha", "path", "config", "/home/me/app", None, None, Some(true), None)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO systems (name, hostname, platform) VALUES ('laptop', 'laptop.local', 'darwin'); \
+             INSERT INTO context_entries (project_id, system_id, type, key, value, is_system_specific, updated_at) \
+             SELECT p.id, s.id, 'config', 'path', '/Users/me/app', 1, datetime('now', '+1 hour') \
+             FROM projects p, systems s WHERE p.name = 'alpha' AND s.hostname = 'laptop.local'"
+        )
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+        
+        // This machine's copy wins even though the other one is newer
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(context.entries.len(), 1);
+        assert_eq!(context.entries[0].value, "/home/me/app");
+        
+        storage.delete_context("alpha", "path", false).await.unwrap();
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(context.entries.len(), 1);
+        assert_eq!(context.entries[0].value, "/Users/me/app");
+    }
+    
+    #[tokio::test]
+    async fn test_recent_updates_cover_every_mutation() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = seeded_storage(&temp_dir).await;
+        
+        storage
+            .store_context("alpha", "db-choice", "decision", "Use Postgres", None, None, None, Some("developer".into()))
+            .await
+            .unwrap();
+        // Rewriting the same value is not a change
+        storage
+            .store_context("alpha", "db-choice", "decision", "Use Postgres", None, None, None, Some("developer".into()))
+            .await
+            .unwrap();
+        storage.delete_context("alpha", "todo-1", false).await.unwrap();
+        storage.restore("alpha", Some("todo-1")).await.unwrap();
+        storage.update_project_status("alpha", "paused", Some("waiting on review")).await.unwrap();
+        storage
+            .store_project_context("alpha", &ProjectUpdate {
+                description: Some("Alpha".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        
+        let alpha = HistoryFilter {
+            project_name: Some("alpha".into()),
+            ..Default::default()
+        };
+        let updates = storage.get_recent_updates(&alpha).await.unwrap();
+        let actions: Vec<_> = updates.iter().map(|u| (u.entity_type.as_str(), u.action.as_str())).collect();
+        assert_eq!(actions, [
+            ("project", "update"),
+            ("project", "status_change"),
+            ("context_entry", "restore"),
+            ("context_entry", "delete"),
+            ("context_entry", "update"),
+            ("context_entry", "create"),
+            ("context_entry", "create"),
+            ("context_entry", "create"),
+            ("project", "create"),
+        ]);
+        assert!(updates.iter().all(|u| u.project_name.as_deref() == Some("alpha")));
+        
+        let status = &updates[1];
+        assert_eq!(status.user_note.as_deref(), Some("waiting on review"));
+        assert_eq!(status.changes.as_ref().unwrap()["to"], "paused");
+        assert_eq!(updates[4].role_id.as_deref(), Some("developer"));
+        assert_eq!(updates[4].changes.as_ref().unwrap()["key"], "db-choice");
+        
+        let deletes = storage
+            .get_recent_updates(&HistoryFilter {
+                entity_type: Some("context_entry".into()),
+                action: Some("delete".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(deletes.len(), 1);
+        
+        let future = HistoryFilter {
+            since: Some("2999-01-01 00:00:00".into()),
+            ..Default::default()
+        };
+        assert!(storage.get_recent_updates(&future).await.unwrap().is_empty());
+        
+        let limited = HistoryFilter {
+            since: Some("-1 week".into()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(storage.get_recent_updates(&limited).await.unwrap().len(), 2);
+    }
+    
+    #[tokio::test]
+    async fn test_store_project_context_partial_update() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("projects.db")).await.unwrap();
+        
+        let created = storage
+            .store_project_context("alpha", &ProjectUpdate {
+                description: Some("First cut".into()),
+                repository_url: Some("https://example.com/alpha.git".into()),
+                tags: Some(vec!["rust".into()]),
+                metadata: Some(serde_json::json!({"owner": "core"})),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(created.status, "active");
+        assert_eq!(created.description.as_deref(), Some("First cut"));
+        
+        // Patching one field leaves the others alone
+        let patched = storage
+            .store_project_context("alpha", &ProjectUpdate {
+                local_directory: Some("/src/alpha".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(patched.id, created.id);
+        assert_eq!(patched.description.as_deref(), Some("First cut"));
+        assert_eq!(patched.repository_url.as_deref(), Some("https://example.com/alpha.git"));
+        assert_eq!(patched.local_directory.as_deref(), Some("/src/alpha"));
+        assert_eq!(patched.tags, Some(vec!["rust".to_string()]));
+        assert_eq!(patched.metadata, Some(serde_json::json!({"owner": "core"})));
+        
+        // Context writes reuse the project instead of creating a bare one
+        storage
+            .store_context("alpha", "k", "note", "v", None, None, None, None)
+            .await
+            .unwrap();
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(context.project.local_directory.as_deref(), Some("/src/alpha"));
+        assert_eq!(context.entries.len(), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_lookup_failures_are_typed() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("errors.db")).await.unwrap();
+        storage
+            .store_context("alpha", "k", "note", "v", None, None, None, None)
+            .await
+            .unwrap();
+        
+        let kind = |err: anyhow::Error| {
+            let (code, data) = crate::error::error_details(&err);
+            (code, data.unwrap()["kind"].as_str().unwrap().to_string())
+        };
+        
+        let err = storage.get_project_context("missing", None).await.unwrap_err();
+        assert_eq!(kind(err), (1002, "project_not_found".to_string()));
+        
+        let err = storage.delete_context("alpha", "missing", false).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<MpcmError>(),
+            Some(MpcmError::ContextNotFound { key, .. }) if key == "missing"
+        ));
+        
+        let err = storage.get_revision("alpha", "k", 9).await.unwrap_err();
+        assert_eq!(kind(err), (1006, "revision_not_found".to_string()));
+        
+        let err = storage.restore("alpha", None).await.unwrap_err();
+        let (code, data) = crate::error::error_details(&err);
+        assert_eq!(code, 1007);
+        assert_eq!(data.unwrap(), serde_json::json!({
+            "kind": "nothing_to_restore",
+            "project": "alpha",
+            "key": null,
+        }));
+    }
+    
+    #[tokio::test]
+    async fn test_checkpoint_empties_the_wal() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("wal.db");
+        let storage = Storage::new(&db_path).await.unwrap();
+        storage
+            .store_context("alpha", "db", "decision", "Use SQLite", None, None, None, None)
+            .await
+            .unwrap();
+        
+        let wal_path = temp_dir.path().join("wal.db-wal");
+        assert!(std::fs::metadata(&wal_path).unwrap().len() > 0);
+        storage.checkpoint().await.unwrap();
+        assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+        
+        storage.close().await;
+        let reopened = Storage::new(&db_path).await.unwrap();
+        assert_eq!(reopened.get_project_context("alpha", None).await.unwrap().entries.len(), 1);
+    }
+}
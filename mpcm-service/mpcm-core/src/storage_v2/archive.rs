@@ -0,0 +1,718 @@
+//! Portable project archives
+//!
+//! An archive is a single JSON document holding a project, its live context
+//! entries, its update history and the role data needed to restore them on
+//! another machine or database.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sqlx::{Row, Sqlite, Transaction};
+use tracing::info;
+
+use super::{
+    parse_datetime, record_revision, row_to_entry, ContextEntry, HistoryEvent, Project, ProjectRow,
+    Storage, UpdateRecord, ACTION_IMPORT, ENTITY_PROJECT,
+};
+use crate::error::MpcmError;
+use crate::migrations;
+
+/// Value of `format` in every archive
+pub const ARCHIVE_FORMAT: &str = "mpcm-project-archive";
+
+/// Current archive layout version; bump when fields change meaning
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// A project with everything needed to recreate it elsewhere
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectArchive {
+    pub format: String,
+    pub version: u32,
+    /// Schema version of the exporting database
+    pub schema_version: i64,
+    pub exported_at: DateTime<Utc>,
+    pub project: Project,
+    pub entries: Vec<ContextEntry>,
+    #[serde(default)]
+    pub history: Vec<UpdateRecord>,
+    /// Custom roles referenced by the project; built-in roles are not exported
+    #[serde(default)]
+    pub roles: Vec<ArchivedRole>,
+    #[serde(default)]
+    pub project_roles: Vec<ArchivedProjectRole>,
+    #[serde(default)]
+    pub role_handoffs: Vec<ArchivedRoleHandoff>,
+}
+
+/// Row of `roles`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedRole {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub template_config: Option<JsonValue>,
+    pub parent_template: Option<String>,
+}
+
+/// Row of `project_roles`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedProjectRole {
+    pub role_id: String,
+    pub is_active: bool,
+    pub custom_config: Option<JsonValue>,
+}
+
+/// Row of `role_handoffs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedRoleHandoff {
+    pub id: String,
+    pub from_role_id: String,
+    pub to_role_id: String,
+    pub handoff_data: JsonValue,
+    pub created_at: Option<String>,
+}
+
+/// What to do when an imported entry's key already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Keep the existing entry
+    #[default]
+    Skip,
+    /// Replace the existing entry with the archived one
+    Overwrite,
+    /// Keep whichever entry was updated last
+    KeepNewest,
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            "keep_newest" | "keep-newest" => Ok(Self::KeepNewest),
+            other => Err(MpcmError::InvalidArgument(format!("Unknown conflict policy: {}", other)).into()),
+        }
+    }
+}
+
+/// Summary of an import
+#[derive(Debug, Default, Serialize)]
+pub struct ImportResult {
+    pub project: String,
+    pub project_created: bool,
+    pub entries_created: u64,
+    pub entries_updated: u64,
+    pub entries_skipped: u64,
+    pub history_imported: u64,
+    pub roles_imported: u64,
+}
+
+impl Storage {
+    /// Serialize a project and its context into an archive
+    pub async fn export_project(&self, project_name: &str) -> Result<ProjectArchive> {
+        let project = sqlx::query_as::<_, ProjectRow>(
+            r#"
+            SELECT id, name, description, status, repository_url,
+                   local_directory, tags, metadata, primary_system_id,
+                   created_at, updated_at, last_accessed
+            FROM projects
+            WHERE name = ?1 AND deleted_at IS NULL
+            "#
+        )
+        .bind(project_name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| super::project_not_found(project_name))?
+        .into_project()?;
+
+        let entries = sqlx::query(
+            r#"
+            SELECT id, project_id, system_id, role_id, type, key, value,
+                   is_system_specific, tags, metadata, created_at, updated_at
+            FROM context_entries
+            WHERE project_id = ?1 AND deleted_at IS NULL
+            ORDER BY id
+            "#
+        )
+        .bind(project.id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(row_to_entry)
+        .collect::<Result<Vec<_>>>()?;
+
+        let history = sqlx::query(
+            r#"
+            SELECT id, entity_type, entity_id, project_id, action, changes,
+                   user_note, role_id, timestamp
+            FROM update_history
+            WHERE project_id = ?1
+            ORDER BY timestamp, id
+            "#
+        )
+        .bind(project.id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            let changes: Option<String> = row.get("changes");
+            Ok(UpdateRecord {
+                id: row.get("id"),
+                entity_type: row.get("entity_type"),
+                entity_id: row.get("entity_id"),
+                project_id: row.get("project_id"),
+                project_name: Some(project.name.clone()),
+                action: row.get("action"),
+                changes: changes.and_then(|s| serde_json::from_str(&s).ok()),
+                user_note: row.get("user_note"),
+                role_id: row.get("role_id"),
+                timestamp: parse_datetime(&row.get::<String, _>("timestamp"))?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        let project_roles = sqlx::query(
+            "SELECT role_id, is_active, custom_config FROM project_roles WHERE project_id = ?1"
+        )
+        .bind(project.id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| ArchivedProjectRole {
+            role_id: row.get("role_id"),
+            is_active: row.get::<Option<bool>, _>("is_active").unwrap_or(true),
+            custom_config: parse_json_column(row.get("custom_config")),
+        })
+        .collect::<Vec<_>>();
+
+        let role_handoffs = sqlx::query(
+            r#"
+            SELECT id, from_role_id, to_role_id, handoff_data, created_at
+            FROM role_handoffs
+            WHERE project_id = ?1
+            ORDER BY created_at
+            "#
+        )
+        .bind(project.id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| ArchivedRoleHandoff {
+            id: row.get("id"),
+            from_role_id: row.get("from_role_id"),
+            to_role_id: row.get("to_role_id"),
+            handoff_data: parse_json_column(row.get("handoff_data")).unwrap_or(JsonValue::Null),
+            created_at: row.get("created_at"),
+        })
+        .collect::<Vec<_>>();
+
+        let roles = sqlx::query(
+            r#"
+            SELECT id, name, description, template_config, parent_template
+            FROM roles
+            WHERE is_custom = 1 AND id IN (
+                SELECT role_id FROM context_entries WHERE project_id = ?1
+                UNION SELECT role_id FROM project_roles WHERE project_id = ?1
+                UNION SELECT from_role_id FROM role_handoffs WHERE project_id = ?1
+                UNION SELECT to_role_id FROM role_handoffs WHERE project_id = ?1
+            )
+            ORDER BY id
+            "#
+        )
+        .bind(project.id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| ArchivedRole {
+            id: row.get("id"),
+            name: row.get("name"),
+            description: row.get("description"),
+            template_config: parse_json_column(row.get("template_config")),
+            parent_template: row.get("parent_template"),
+        })
+        .collect::<Vec<_>>();
+
+        info!(
+            "Exported project {} with {} entries and {} history rows",
+            project.name,
+            entries.len(),
+            history.len()
+        );
+
+        Ok(ProjectArchive {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            schema_version: migrations::current_version(&self.pool).await?,
+            exported_at: Utc::now(),
+            project,
+            entries,
+            history,
+            roles,
+            project_roles,
+            role_handoffs,
+        })
+    }
+
+    /// Load an archive, resolving existing keys with `policy`
+    ///
+    /// The whole import runs in one transaction. Entries tied to a system
+    /// lose that link, since system ids are local to each database.
+    pub async fn import_project(&self, archive: &ProjectArchive, policy: ConflictPolicy) -> Result<ImportResult> {
+        self.import_project_as(archive, policy, None).await
+    }
+
+    /// Load an archive on behalf of `tenant`
+    ///
+    /// A project the archive creates belongs to `tenant` from the moment it
+    /// exists; one that exists already must belong to `tenant`.
+    pub async fn import_project_as(
+        &self,
+        archive: &ProjectArchive,
+        policy: ConflictPolicy,
+        tenant: Option<&str>,
+    ) -> Result<ImportResult> {
+        if archive.format != ARCHIVE_FORMAT {
+            return Err(MpcmError::InvalidArgument(format!(
+                "Not a project archive: format is '{}'",
+                archive.format
+            ))
+            .into());
+        }
+        if archive.version > ARCHIVE_VERSION {
+            return Err(MpcmError::InvalidArgument(format!(
+                "Archive version {} is newer than supported version {}",
+                archive.version, ARCHIVE_VERSION
+            ))
+            .into());
+        }
+
+        let source = &archive.project;
+        let mut result = ImportResult {
+            project: source.name.clone(),
+            ..Default::default()
+        };
+
+        let tenant_id = match tenant {
+            Some(tenant) => Some(self.ensure_tenant(tenant).await?),
+            None => None,
+        };
+        let mut tx = self.pool.begin().await?;
+
+        // Custom roles first so entries and role links can reference them
+        for role in &archive.roles {
+            result.roles_imported += sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO roles (id, name, description, is_custom, template_config, parent_template)
+                VALUES (?1, ?2, ?3, 1, ?4, ?5)
+                "#
+            )
+            .bind(&role.id)
+            .bind(&role.name)
+            .bind(&role.description)
+            .bind(role.template_config.as_ref().map(JsonValue::to_string))
+            .bind(&role.parent_template)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+
+        let existing = sqlx::query_as::<_, (i64, String, Option<i64>)>(
+            "SELECT id, updated_at, tenant_id FROM projects WHERE name = ?1"
+        )
+        .bind(&source.name)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let project_id = match existing {
+            Some((_, _, owner)) if tenant_id.is_some() && owner != tenant_id => {
+                return Err(MpcmError::ProjectNotFound {
+                    project: source.name.clone(),
+                }
+                .into());
+            }
+            Some((id, updated_at, _)) => {
+                if should_replace(policy, &updated_at, &source.updated_at)? {
+                    sqlx::query(
+                        r#"
+                        UPDATE projects SET
+                            description = ?2, status = ?3, repository_url = ?4,
+                            local_directory = ?5, tags = ?6, metadata = ?7,
+                            updated_at = ?8, deleted_at = NULL
+                        WHERE id = ?1
+                        "#
+                    )
+                    .bind(id)
+                    .bind(&source.description)
+                    .bind(&source.status)
+                    .bind(&source.repository_url)
+                    .bind(&source.local_directory)
+                    .bind(to_json_string(&source.tags)?)
+                    .bind(to_json_string(&source.metadata)?)
+                    .bind(format_datetime(&source.updated_at))
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                id
+            }
+            None => {
+                result.project_created = true;
+                sqlx::query(
+                    r#"
+                    INSERT INTO projects (
+                        name, description, status, repository_url, local_directory,
+                        tags, metadata, primary_system_id, tenant_id,
+                        created_at, updated_at, last_accessed
+                    )
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?11, ?9, ?10, CURRENT_TIMESTAMP)
+                    "#
+                )
+                .bind(&source.name)
+                .bind(&source.description)
+                .bind(&source.status)
+                .bind(&source.repository_url)
+                .bind(&source.local_directory)
+                .bind(to_json_string(&source.tags)?)
+                .bind(to_json_string(&source.metadata)?)
+                .bind(self.current_system_id)
+                .bind(format_datetime(&source.created_at))
+                .bind(format_datetime(&source.updated_at))
+                .bind(tenant_id)
+                .execute(&mut *tx)
+                .await?
+                .last_insert_rowid()
+            }
+        };
+
+        // Archived ids mapped to ids in this database, for history rows
+        let mut entry_ids = HashMap::new();
+        for entry in &archive.entries {
+            let role_id = known_role(&mut tx, entry.role_id.as_deref()).await?;
+            let existing = sqlx::query_as::<_, (i64, String)>(
+                "SELECT id, updated_at FROM context_entries WHERE project_id = ?1 AND key = ?2 AND system_id IS NULL"
+            )
+            .bind(project_id)
+            .bind(&entry.key)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let id = match existing {
+                Some((id, updated_at)) => {
+                    if should_replace(policy, &updated_at, &entry.updated_at)? {
+                        record_revision(&mut tx, id, role_id.as_deref()).await?;
+                        sqlx::query(
+                            r#"
+                            UPDATE context_entries SET
+                                type = ?2, value = ?3, tags = ?4, metadata = ?5,
+                                is_system_specific = ?6, system_id = NULL, role_id = ?7,
+                                updated_at = ?8, deleted_at = NULL, deleted_with_project = 0
+                            WHERE id = ?1
+                            "#
+                        )
+                        .bind(id)
+                        .bind(&entry.context_type)
+                        .bind(&entry.value)
+                        .bind(to_json_string(&entry.tags)?)
+                        .bind(to_json_string(&entry.metadata)?)
+                        .bind(entry.is_system_specific)
+                        .bind(&role_id)
+                        .bind(format_datetime(&entry.updated_at))
+                        .execute(&mut *tx)
+                        .await?;
+                        result.entries_updated += 1;
+                    } else {
+                        result.entries_skipped += 1;
+                    }
+                    id
+                }
+                None => {
+                    result.entries_created += 1;
+                    sqlx::query(
+                        r#"
+                        INSERT INTO context_entries (
+                            project_id, key, type, value, tags, metadata,
+                            is_system_specific, role_id, created_at, updated_at
+                        )
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                        "#
+                    )
+                    .bind(project_id)
+                    .bind(&entry.key)
+                    .bind(&entry.context_type)
+                    .bind(&entry.value)
+                    .bind(to_json_string(&entry.tags)?)
+                    .bind(to_json_string(&entry.metadata)?)
+                    .bind(entry.is_system_specific)
+                    .bind(&role_id)
+                    .bind(format_datetime(&entry.created_at))
+                    .bind(format_datetime(&entry.updated_at))
+                    .execute(&mut *tx)
+                    .await?
+                    .last_insert_rowid()
+                }
+            };
+            entry_ids.insert(entry.id, id);
+        }
+
+        for record in &archive.history {
+            let entity_id = match record.entity_type.as_str() {
+                ENTITY_PROJECT => project_id,
+                _ => match entry_ids.get(&record.entity_id) {
+                    Some(id) => *id,
+                    // History of entries that were not exported
+                    None => continue,
+                },
+            };
+            let role_id = known_role(&mut tx, record.role_id.as_deref()).await?;
+            let timestamp = format_datetime(&record.timestamp);
+
+            // Re-importing the same archive does not duplicate history
+            result.history_imported += sqlx::query(
+                r#"
+                INSERT INTO update_history (
+                    entity_type, entity_id, project_id, action, changes, user_note, role_id, timestamp
+                )
+                SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM update_history
+                    WHERE entity_type = ?1 AND entity_id = ?2 AND action = ?4 AND timestamp = ?8
+                )
+                "#
+            )
+            .bind(&record.entity_type)
+            .bind(entity_id)
+            .bind(project_id)
+            .bind(&record.action)
+            .bind(record.changes.as_ref().map(JsonValue::to_string))
+            .bind(&record.user_note)
+            .bind(&role_id)
+            .bind(&timestamp)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+
+        for link in &archive.project_roles {
+            if known_role(&mut tx, Some(&link.role_id)).await?.is_none() {
+                continue;
+            }
+            let conflict = match policy {
+                ConflictPolicy::Skip => "OR IGNORE",
+                _ => "OR REPLACE",
+            };
+            sqlx::query(&format!(
+                "INSERT {} INTO project_roles (project_id, role_id, is_active, custom_config) VALUES (?1, ?2, ?3, ?4)",
+                conflict
+            ))
+            .bind(project_id)
+            .bind(&link.role_id)
+            .bind(link.is_active)
+            .bind(link.custom_config.as_ref().map(JsonValue::to_string))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for handoff in &archive.role_handoffs {
+            let from = known_role(&mut tx, Some(&handoff.from_role_id)).await?;
+            let to = known_role(&mut tx, Some(&handoff.to_role_id)).await?;
+            if from.is_none() || to.is_none() {
+                continue;
+            }
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO role_handoffs (id, project_id, from_role_id, to_role_id, handoff_data, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, COALESCE(?6, CURRENT_TIMESTAMP))
+                "#
+            )
+            .bind(&handoff.id)
+            .bind(project_id)
+            .bind(&handoff.from_role_id)
+            .bind(&handoff.to_role_id)
+            .bind(handoff.handoff_data.to_string())
+            .bind(&handoff.created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        HistoryEvent {
+            entity_type: ENTITY_PROJECT,
+            entity_id: project_id,
+            project_id: Some(project_id),
+            action: ACTION_IMPORT,
+            changes: Some(serde_json::json!({
+                "policy": policy,
+                "exported_at": archive.exported_at,
+                "entries_created": result.entries_created,
+                "entries_updated": result.entries_updated,
+                "entries_skipped": result.entries_skipped,
+            })),
+            note: None,
+            role_id: None,
+        }
+        .record(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(
+            "Imported project {}: {} created, {} updated, {} skipped",
+            result.project, result.entries_created, result.entries_updated, result.entries_skipped
+        );
+        Ok(result)
+    }
+}
+
+/// Whether an archived row should replace the stored one
+fn should_replace(policy: ConflictPolicy, stored_updated_at: &str, archived_updated_at: &DateTime<Utc>) -> Result<bool> {
+    Ok(match policy {
+        ConflictPolicy::Skip => false,
+        ConflictPolicy::Overwrite => true,
+        ConflictPolicy::KeepNewest => *archived_updated_at > parse_datetime(stored_updated_at)?,
+    })
+}
+
+/// Keep a role id only if the role exists here, so foreign keys hold
+async fn known_role(tx: &mut Transaction<'_, Sqlite>, role_id: Option<&str>) -> Result<Option<String>> {
+    let Some(role_id) = role_id else {
+        return Ok(None);
+    };
+    Ok(sqlx::query_scalar::<_, String>("SELECT id FROM roles WHERE id = ?1")
+        .bind(role_id)
+        .fetch_optional(&mut **tx)
+        .await?)
+}
+
+fn parse_json_column(value: Option<String>) -> Option<JsonValue> {
+    value.and_then(|s| serde_json::from_str(&s).ok())
+}
+
+fn to_json_string<T: Serialize>(value: &Option<T>) -> Result<Option<String>> {
+    Ok(value.as_ref().map(serde_json::to_string).transpose()?)
+}
+
+/// Format a timestamp the way SQLite's CURRENT_TIMESTAMP does
+fn format_datetime(dt: &DateTime<Utc>) -> String {
+    dt.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_v2::{HistoryFilter, ProjectUpdate};
+    use tempfile::TempDir;
+
+    async fn source_storage(temp_dir: &TempDir) -> Storage {
+        let storage = Storage::new(temp_dir.path().join("source.db")).await.unwrap();
+        storage
+            .store_project_context("alpha", &ProjectUpdate {
+                description: Some("Alpha service".into()),
+                tags: Some(vec!["rust".into()]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO roles (id, name, is_custom) VALUES ('reviewer', 'Reviewer', 1)")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+        storage
+            .store_context("alpha", "db-choice", "decision", "Use SQLite", None, None, None, Some("reviewer".into()))
+            .await
+            .unwrap();
+        storage
+            .store_context("alpha", "todo-1", "todo", "Write docs", None, None, None, None)
+            .await
+            .unwrap();
+        storage
+    }
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = source_storage(&temp_dir).await;
+
+        let archive = source.export_project("alpha").await.unwrap();
+        assert_eq!(archive.format, ARCHIVE_FORMAT);
+        assert_eq!(archive.entries.len(), 2);
+        assert_eq!(archive.roles.len(), 1);
+        assert!(!archive.history.is_empty());
+
+        // Archives survive a trip through JSON
+        let json = serde_json::to_string(&archive).unwrap();
+        let archive: ProjectArchive = serde_json::from_str(&json).unwrap();
+
+        let target = Storage::new(temp_dir.path().join("target.db")).await.unwrap();
+        let result = target.import_project(&archive, ConflictPolicy::Skip).await.unwrap();
+        assert!(result.project_created);
+        assert_eq!(result.entries_created, 2);
+        assert_eq!(result.roles_imported, 1);
+
+        let context = target.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(context.project.description.as_deref(), Some("Alpha service"));
+        let decision = context.entries.iter().find(|e| e.key == "db-choice").unwrap();
+        assert_eq!(decision.role_id.as_deref(), Some("reviewer"));
+        assert_eq!(decision.created_at, archive.entries[0].created_at);
+        assert_eq!(decision.updated_at, archive.entries[0].updated_at);
+
+        // Importing again adds nothing but the import record
+        let history = HistoryFilter {
+            project_name: Some("alpha".into()),
+            ..Default::default()
+        };
+        let before = target.get_recent_updates(&history).await.unwrap().len();
+        let again = target.import_project(&archive, ConflictPolicy::Skip).await.unwrap();
+        assert_eq!(again.entries_skipped, 2);
+        assert_eq!(again.history_imported, 0);
+        assert_eq!(target.get_recent_updates(&history).await.unwrap().len(), before + 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_conflict_policies() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = source_storage(&temp_dir).await;
+        let mut archive = source.export_project("alpha").await.unwrap();
+
+        let target = Storage::new(temp_dir.path().join("target.db")).await.unwrap();
+        target
+            .store_context("alpha", "db-choice", "decision", "Use Postgres", None, None, None, None)
+            .await
+            .unwrap();
+        async fn value(storage: &Storage) -> String {
+            let context = storage.get_project_context("alpha", None).await.unwrap();
+            context.entries.into_iter().find(|e| e.key == "db-choice").unwrap().value
+        }
+
+        target.import_project(&archive, ConflictPolicy::Skip).await.unwrap();
+        assert_eq!(value(&target).await, "Use Postgres");
+
+        // The archived entry is older than the local one
+        archive.entries[0].updated_at = Utc::now() - chrono::Duration::days(1);
+        let result = target.import_project(&archive, ConflictPolicy::KeepNewest).await.unwrap();
+        assert_eq!(result.entries_updated, 0);
+        assert_eq!(value(&target).await, "Use Postgres");
+
+        archive.entries[0].updated_at = Utc::now() + chrono::Duration::days(1);
+        let result = target.import_project(&archive, ConflictPolicy::KeepNewest).await.unwrap();
+        assert_eq!(result.entries_updated, 1);
+        assert_eq!(value(&target).await, "Use SQLite");
+
+        target
+            .store_context("alpha", "db-choice", "decision", "Use MySQL", None, None, None, None)
+            .await
+            .unwrap();
+        target.import_project(&archive, ConflictPolicy::Overwrite).await.unwrap();
+        assert_eq!(value(&target).await, "Use SQLite");
+
+        // Overwritten values are kept as revisions
+        let revisions = target.list_revisions("alpha", "db-choice").await.unwrap();
+        assert!(revisions.iter().any(|r| r.value == "Use MySQL"));
+
+        archive.version = ARCHIVE_VERSION + 1;
+        assert!(target.import_project(&archive, ConflictPolicy::Skip).await.is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Online backups
+//!
+//! Backups are written with `VACUUM INTO`, which produces a consistent,
+//! compacted copy while the server keeps serving requests.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use tracing::{info, warn};
+
+use super::Storage;
+use crate::migrations;
+
+/// A backup file on disk
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub schema_version: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Storage {
+    /// Write a consistent copy of the database into `dir`
+    ///
+    /// Files are named `<db name>-<timestamp>.db`; only the newest `keep`
+    /// of them are kept.
+    pub async fn backup(&self, dir: &Path, keep: usize) -> Result<BackupInfo> {
+        std::fs::create_dir_all(dir)?;
+
+        let created_at = Utc::now();
+        let path = dir.join(format!(
+            "{}-{}.db",
+            self.backup_prefix(),
+            created_at.format("%Y%m%d-%H%M%S-%3f")
+        ));
+        let target = path
+            .to_str()
+            .ok_or_else(|| anyhow!("Backup path is not valid UTF-8: {:?}", path))?;
+
+        sqlx::query("VACUUM INTO ?1")
+            .bind(target)
+            .execute(&self.pool)
+            .await?;
+
+        let info = BackupInfo {
+            size_bytes: std::fs::metadata(&path)?.len(),
+            schema_version: migrations::current_version(&self.pool).await?,
+            path,
+            created_at,
+        };
+        info!("Backed up database to {:?} ({} bytes)", info.path, info.size_bytes);
+
+        for old in self.backup_files(dir)?.into_iter().rev().skip(keep.max(1)) {
+            match std::fs::remove_file(&old) {
+                Ok(()) => info!("Removed old backup {:?}", old),
+                Err(e) => warn!("Failed to remove old backup {:?}: {}", old, e),
+            }
+        }
+
+        Ok(info)
+    }
+
+    /// Backups of this database in `dir`, oldest first
+    pub fn backup_files(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let prefix = format!("{}-", self.backup_prefix());
+        let mut files = Vec::new();
+
+        if !dir.exists() {
+            return Ok(files);
+        }
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_backup = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".db"));
+            if is_backup {
+                files.push(path);
+            }
+        }
+
+        // Timestamps in the name sort chronologically
+        files.sort();
+        Ok(files)
+    }
+
+    /// Replace the database at `db_path` with a backup
+    ///
+    /// The backup must pass an integrity check and carry a schema this build
+    /// understands. The current database is kept next to it as
+    /// `<name>.pre-restore`. Run this only while the server is stopped.
+    pub async fn restore_backup(backup: &Path, db_path: &Path) -> Result<i64> {
+        let version = validate_backup(backup).await?;
+
+        let staged = db_path.with_extension("restore-tmp");
+        std::fs::copy(backup, &staged)?;
+
+        if db_path.exists() {
+            std::fs::rename(db_path, db_path.with_extension("pre-restore"))?;
+        }
+        // A leftover WAL would be replayed into the restored file
+        for suffix in ["-wal", "-shm"] {
+            let mut sidecar = db_path.as_os_str().to_owned();
+            sidecar.push(suffix);
+            let sidecar = PathBuf::from(sidecar);
+            if sidecar.exists() {
+                std::fs::remove_file(&sidecar)?;
+            }
+        }
+        std::fs::rename(&staged, db_path)?;
+
+        info!("Restored {:?} from {:?} at schema version {}", db_path, backup, version);
+        Ok(version)
+    }
+
+    fn backup_prefix(&self) -> String {
+        self.db_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("mpcm")
+            .to_string()
+    }
+}
+
+/// Check a backup file and return its schema version
+async fn validate_backup(backup: &Path) -> Result<i64> {
+    if !backup.is_file() {
+        bail!("Backup not found: {:?}", backup);
+    }
+
+    let options = SqliteConnectOptions::new().filename(backup).read_only(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+
+    let integrity = sqlx::query_scalar::<_, String>("PRAGMA integrity_check")
+        .fetch_one(&pool)
+        .await?;
+    if integrity != "ok" {
+        bail!("Backup {:?} failed integrity check: {}", backup, integrity);
+    }
+
+    let version = migrations::current_version(&pool)
+        .await
+        .map_err(|e| anyhow!("Backup {:?} has no readable schema version: {}", backup, e))?;
+    pool.close().await;
+
+    if version > migrations::latest_version() {
+        bail!(
+            "Backup schema version {} is newer than supported version {}",
+            version,
+            migrations::latest_version()
+        );
+    }
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_backup_rotation_and_restore() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("main.db");
+        let backups = temp_dir.path().join("backups");
+
+        let storage = Storage::new(&db_path).await.unwrap();
+        storage
+            .store_context("alpha", "k", "note", "before backup", None, None, None, None)
+            .await
+            .unwrap();
+
+        let first = storage.backup(&backups, 2).await.unwrap();
+        assert_eq!(first.schema_version, migrations::latest_version());
+        storage.backup(&backups, 2).await.unwrap();
+        let last = storage.backup(&backups, 2).await.unwrap();
+
+        let files = storage.backup_files(&backups).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(!first.path.exists());
+        assert_eq!(files[1], last.path);
+
+        storage
+            .store_context("alpha", "k", "note", "after backup", None, None, None, None)
+            .await
+            .unwrap();
+        storage.close().await;
+
+        let version = Storage::restore_backup(&last.path, &db_path).await.unwrap();
+        assert_eq!(version, migrations::latest_version());
+        assert!(db_path.with_extension("pre-restore").exists());
+
+        let restored = Storage::new(&db_path).await.unwrap();
+        let context = restored.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(context.entries[0].value, "before backup");
+    }
+
+    #[tokio::test]
+    async fn test_restore_rejects_newer_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("main.db");
+
+        let storage = Storage::new(&db_path).await.unwrap();
+        let backup = storage.backup(&temp_dir.path().join("backups"), 1).await.unwrap();
+        storage.close().await;
+
+        // Simulate a backup taken by a newer build
+        let options = SqliteConnectOptions::new().filename(&backup.path);
+        let pool = SqlitePoolOptions::new().connect_with(options).await.unwrap();
+        sqlx::query("INSERT INTO schema_migrations (version, name) VALUES (999, 'future')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        let err = Storage::restore_backup(&backup.path, &db_path).await.unwrap_err();
+        assert!(err.to_string().contains("newer than supported"));
+        assert!(!db_path.with_extension("pre-restore").exists());
+
+        let missing = temp_dir.path().join("missing.db");
+        assert!(Storage::restore_backup(&missing, &db_path).await.is_err());
+    }
+}
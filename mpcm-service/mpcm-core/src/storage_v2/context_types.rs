@@ -0,0 +1,220 @@
+//! Custom context types
+//!
+//! Built-in types are always accepted. Any other type must be registered
+//! first, optionally with JSON Schemas its entries are validated against.
+
+use anyhow::Result;
+use serde_json::Value as JsonValue;
+use sqlx::Row;
+use tracing::info;
+
+use super::Storage;
+use crate::context::{ContextType, CustomContextType};
+use crate::error::MpcmError;
+
+impl Storage {
+    /// Register a custom context type, replacing any earlier definition
+    pub async fn register_context_type(&self, context_type: &CustomContextType) -> Result<CustomContextType> {
+        context_type.check()?;
+
+        let to_text = |schema: &Option<JsonValue>| schema.as_ref().map(|s| s.to_string());
+        sqlx::query(
+            r#"
+            INSERT INTO context_types (name, description, value_schema, metadata_schema)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(name) DO UPDATE SET
+                description = excluded.description,
+                value_schema = excluded.value_schema,
+                metadata_schema = excluded.metadata_schema
+            "#
+        )
+        .bind(&context_type.name)
+        .bind(&context_type.description)
+        .bind(to_text(&context_type.value_schema))
+        .bind(to_text(&context_type.metadata_schema))
+        .execute(&self.pool)
+        .await?;
+
+        info!("Registered context type '{}'", context_type.name);
+        Ok(context_type.clone())
+    }
+
+    /// Registered custom context types, by name
+    pub async fn list_context_types(&self) -> Result<Vec<CustomContextType>> {
+        let rows = sqlx::query(
+            "SELECT name, description, value_schema, metadata_schema FROM context_types ORDER BY name"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_context_type).collect()
+    }
+
+    /// Look up a registered custom context type
+    pub async fn get_context_type(&self, name: &str) -> Result<Option<CustomContextType>> {
+        let row = sqlx::query(
+            "SELECT name, description, value_schema, metadata_schema FROM context_types WHERE name = ?1"
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(row_to_context_type).transpose()
+    }
+
+    /// Remove a custom context type that nothing uses
+    ///
+    /// Deleted entries and revisions count too, since restoring them would
+    /// bring back a type that no longer validates.
+    pub async fn unregister_context_type(&self, name: &str) -> Result<()> {
+        let in_use = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT (SELECT COUNT(*) FROM context_entries WHERE type = ?1)
+                 + (SELECT COUNT(*) FROM context_revisions WHERE type = ?1)
+            "#
+        )
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await?;
+        if in_use > 0 {
+            return Err(MpcmError::InvalidContextType(format!(
+                "'{}' is still used by {} entries or revisions",
+                name, in_use
+            ))
+            .into());
+        }
+
+        let removed = sqlx::query("DELETE FROM context_types WHERE name = ?1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if removed == 0 {
+            return Err(MpcmError::InvalidContextType(format!("'{}' is not a registered type", name)).into());
+        }
+
+        info!("Unregistered context type '{}'", name);
+        Ok(())
+    }
+
+    /// Check an entry's type, and its value and metadata for custom types
+    pub(super) async fn validate_context(
+        &self,
+        context_type: &str,
+        value: &str,
+        metadata: Option<&JsonValue>,
+    ) -> Result<ContextType> {
+        if let Some(builtin) = ContextType::builtin(context_type) {
+            return Ok(builtin);
+        }
+
+        let Some(custom) = self.get_context_type(context_type).await? else {
+            // Reuse the built-in parse error, which lists the accepted names
+            return Err(context_type.parse::<ContextType>().unwrap_err().into());
+        };
+        custom.validate(value, metadata)?;
+        Ok(ContextType::Custom(custom.name))
+    }
+}
+
+fn row_to_context_type(row: &sqlx::sqlite::SqliteRow) -> Result<CustomContextType> {
+    let parse = |column: &str| -> Result<Option<JsonValue>> {
+        Ok(row
+            .get::<Option<String>, _>(column)
+            .map(|s| serde_json::from_str(&s))
+            .transpose()?)
+    };
+
+    Ok(CustomContextType {
+        name: row.get("name"),
+        description: row.get("description"),
+        value_schema: parse("value_schema")?,
+        metadata_schema: parse("metadata_schema")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_custom_types_are_registered_and_validated() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("types.db")).await.unwrap();
+
+        let err = storage
+            .store_context("alpha", "k", "mystery", "value", None, None, None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<MpcmError>(), Some(MpcmError::InvalidContextType(_))));
+        assert!(storage.list_projects(None).await.unwrap().is_empty());
+
+        let builtin = CustomContextType {
+            name: "note".to_string(),
+            description: None,
+            value_schema: None,
+            metadata_schema: None,
+        };
+        assert!(storage.register_context_type(&builtin).await.is_err());
+
+        let metric = CustomContextType {
+            name: "metric".to_string(),
+            description: Some("A measured number".to_string()),
+            value_schema: Some(json!({ "type": "number", "minimum": 0 })),
+            metadata_schema: Some(json!({
+                "type": "object",
+                "required": ["unit"],
+                "properties": { "unit": { "type": "string" } }
+            })),
+        };
+        storage.register_context_type(&metric).await.unwrap();
+        assert_eq!(storage.list_context_types().await.unwrap(), vec![metric.clone()]);
+
+        storage
+            .store_context("alpha", "latency", "metric", "42", None, Some(json!({ "unit": "ms" })), None, None)
+            .await
+            .unwrap();
+
+        let err = storage
+            .store_context("alpha", "latency", "metric", "-1", None, Some(json!({})), None, None)
+            .await
+            .unwrap_err();
+        match err.downcast_ref::<MpcmError>() {
+            Some(MpcmError::ContextValidation { context_type, errors }) => {
+                assert_eq!(context_type, "metric");
+                assert_eq!(errors.len(), 2);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        assert!(storage.unregister_context_type("metric").await.is_err());
+        // A tombstone could be restored, so it still counts
+        storage.delete_context("alpha", "latency", false).await.unwrap();
+        assert!(storage.unregister_context_type("metric").await.is_err());
+        storage.restore("alpha", Some("latency")).await.unwrap();
+
+        // So does an old value kept as a revision
+        storage
+            .store_context("alpha", "latency", "note", "fast", None, None, None, None)
+            .await
+            .unwrap();
+        assert!(storage.unregister_context_type("metric").await.is_err());
+
+        storage.delete_context("alpha", "latency", true).await.unwrap();
+        storage.unregister_context_type("metric").await.unwrap();
+        assert!(storage.list_context_types().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_schema_is_rejected() {
+        let broken = CustomContextType {
+            name: "broken".to_string(),
+            description: None,
+            value_schema: Some(json!({ "type": "not-a-type" })),
+            metadata_schema: None,
+        };
+        assert!(matches!(broken.check(), Err(MpcmError::InvalidContextType(_))));
+    }
+}
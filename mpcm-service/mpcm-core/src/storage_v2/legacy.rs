@@ -0,0 +1,321 @@
+//! Import of data written by the v1 storage back end
+//!
+//! v1 kept every entry in a flat `contexts` table keyed by project name,
+//! with overwritten values, and the role that overwrote them, in
+//! `v1_context_revisions`. Rows are copied over
+//! the first time the database is opened after they were written, so a file
+//! a v1 server also writes to keeps catching up. Each copied row is
+//! recorded in `v1_imports` with the `updated_at` it had; v1 overwrites a
+//! row in place, so a newer `updated_at` is synced onto the v2 entry as a
+//! new revision. Purged and deleted entries are never brought back.
+
+use anyhow::Result;
+use sqlx::{Sqlite, Transaction};
+use tracing::info;
+
+use super::{record_revision, HistoryEvent, Storage, ACTION_CREATE, ACTION_UPDATE, ENTITY_CONTEXT};
+
+impl Storage {
+    /// Copy v1 context entries and their revisions into the v2 schema
+    ///
+    /// Projects are created as needed. Keys that already exist in a project
+    /// are left alone, unless v1 overwrites them later than v2 last did.
+    /// Running it again imports nothing twice. Returns the number of entries
+    /// imported or updated.
+    pub async fn import_v1_contexts(&self) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        if !table_exists(&mut tx, "contexts").await? {
+            return Ok(0);
+        }
+        let has_revisions = table_exists(&mut tx, "v1_context_revisions").await?;
+
+        // Rows imported before versions were recorded count as in sync
+        sqlx::query(
+            r#"
+            UPDATE v1_imports
+            SET v1_updated_at = (SELECT updated_at FROM contexts WHERE id = v1_imports.v1_id)
+            WHERE v1_updated_at IS NULL
+            "#
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let updated = sync_v1_overwrites(&mut tx, has_revisions).await?;
+
+        // Entries imported below get ids above this one
+        let last_id = sqlx::query_scalar::<_, i64>("SELECT COALESCE(MAX(id), 0) FROM context_entries")
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO projects (name, created_at, updated_at)
+            SELECT project_name, MIN(datetime(created_at)), MAX(datetime(updated_at))
+            FROM contexts
+            WHERE id NOT IN (SELECT v1_id FROM v1_imports)
+              AND project_name NOT IN (SELECT name FROM projects)
+            GROUP BY project_name
+            "#
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let imported = sqlx::query(
+            r#"
+            INSERT INTO context_entries (project_id, type, key, value, created_at, updated_at)
+            SELECT p.id, c.context_type, c.key, c.value, datetime(c.created_at), datetime(c.updated_at)
+            FROM contexts c
+            JOIN projects p ON p.name = c.project_name
+            WHERE c.id NOT IN (SELECT v1_id FROM v1_imports)
+              AND NOT EXISTS (
+                SELECT 1 FROM context_entries e
+                WHERE e.project_id = p.id AND e.key = c.key AND e.system_id IS NULL
+              )
+            ORDER BY c.created_at
+            "#
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        // Skipped rows count as seen too, or purging the v2 key would let them in
+        sqlx::query(
+            r#"
+            INSERT INTO v1_imports (v1_id, v1_updated_at)
+            SELECT id, updated_at FROM contexts WHERE true
+            ON CONFLICT(v1_id) DO UPDATE SET v1_updated_at = excluded.v1_updated_at
+            "#
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if imported == 0 {
+            tx.commit().await?;
+            if updated > 0 {
+                info!("Synced {} context entries overwritten in v1 storage", updated);
+            }
+            return Ok(updated);
+        }
+
+        if has_revisions {
+            sqlx::query(
+                r#"
+                INSERT INTO context_revisions (context_id, revision, type, value, created_at, replaced_at, replaced_by_role)
+                SELECT e.id,
+                       ROW_NUMBER() OVER (PARTITION BY e.id ORDER BY r.id),
+                       r.context_type, r.value, datetime(r.created_at), datetime(r.replaced_at), r.role
+                FROM v1_context_revisions r
+                JOIN projects p ON p.name = r.project_name
+                JOIN context_entries e ON e.project_id = p.id AND e.key = r.key AND e.system_id IS NULL
+                WHERE e.id > ?1
+                "#
+            )
+            .bind(last_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO update_history (entity_type, entity_id, project_id, action, changes, user_note, timestamp)
+            SELECT ?2, id, project_id, ?3, json_object('key', key, 'type', type, 'value', value),
+                   'Imported from v1 storage', created_at
+            FROM context_entries
+            WHERE id > ?1
+            "#
+        )
+        .bind(last_id)
+        .bind(ENTITY_CONTEXT)
+        .bind(ACTION_CREATE)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        info!("Imported {} context entries from v1 storage", imported);
+        if updated > 0 {
+            info!("Synced {} context entries overwritten in v1 storage", updated);
+        }
+        Ok(imported + updated)
+    }
+}
+
+/// Apply v1 overwrites of already imported rows to their live v2 entries
+///
+/// The v2 value is kept as a revision, attributed to the role v1 recorded
+/// for the overwrite. Entries v2 changed more recently keep their value;
+/// timestamps only have second precision, so a tie goes to v1.
+async fn sync_v1_overwrites(tx: &mut Transaction<'_, Sqlite>, has_revisions: bool) -> Result<u64> {
+    let changed = sqlx::query_as::<_, (String, i64, i64, String, String, String, String)>(
+        r#"
+        SELECT c.id, e.id, e.project_id, c.key, c.context_type, c.value, datetime(c.updated_at)
+        FROM contexts c
+        JOIN v1_imports i ON i.v1_id = c.id
+        JOIN projects p ON p.name = c.project_name
+        JOIN context_entries e ON e.project_id = p.id AND e.key = c.key AND e.system_id IS NULL
+        WHERE i.v1_updated_at != c.updated_at
+          AND e.deleted_at IS NULL
+          AND datetime(c.updated_at) >= e.updated_at
+          AND (e.value != c.value OR e.type != c.context_type)
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for (v1_id, entry_id, project_id, key, context_type, value, updated_at) in &changed {
+        let role = if has_revisions {
+            sqlx::query_scalar::<_, Option<String>>(
+                "SELECT role FROM v1_context_revisions WHERE context_id = ?1 ORDER BY id DESC LIMIT 1"
+            )
+            .bind(v1_id)
+            .fetch_optional(&mut **tx)
+            .await?
+            .flatten()
+        } else {
+            None
+        };
+
+        record_revision(tx, *entry_id, role.as_deref()).await?;
+        sqlx::query("UPDATE context_entries SET type = ?2, value = ?3, updated_at = ?4 WHERE id = ?1")
+            .bind(entry_id)
+            .bind(context_type)
+            .bind(value)
+            .bind(updated_at)
+            .execute(&mut **tx)
+            .await?;
+
+        HistoryEvent {
+            entity_type: ENTITY_CONTEXT,
+            entity_id: *entry_id,
+            project_id: Some(*project_id),
+            action: ACTION_UPDATE,
+            changes: Some(serde_json::json!({
+                "key": key,
+                "type": context_type,
+                "value": value,
+            })),
+            note: Some("Synced from v1 storage"),
+            role_id: None,
+        }
+        .record(tx)
+        .await?;
+    }
+
+    Ok(changed.len() as u64)
+}
+
+async fn table_exists(tx: &mut Transaction<'_, Sqlite>, name: &str) -> Result<bool> {
+    let found = sqlx::query_scalar::<_, i64>("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")
+        .bind(name)
+        .fetch_optional(&mut **tx)
+        .await?;
+    Ok(found.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage, Context, ContextType};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_v1_contexts_are_imported_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("shared.db");
+        std::fs::File::create(&db_path).unwrap();
+
+        let v1 = storage::Storage::new(&db_path).await.unwrap();
+        v1.store_context(&Context::new("alpha", "db", ContextType::Decision, "MySQL")).await.unwrap();
+        v1.store_context_as(&Context::new("alpha", "db", ContextType::Decision, "SQLite"), Some("architect"))
+            .await
+            .unwrap();
+        v1.store_context(&Context::new("beta", "todo", ContextType::Todo, "Ship it")).await.unwrap();
+
+        let storage = Storage::new(&db_path).await.unwrap();
+        let alpha = storage.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(alpha.entries.len(), 1);
+        assert_eq!(alpha.entries[0].value, "SQLite");
+        assert_eq!(alpha.entries[0].context_type, "decision");
+
+        let revisions = storage.list_revisions("alpha", "db").await.unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].value, "MySQL");
+        assert_eq!(revisions[0].replaced_by_role.as_deref(), Some("architect"));
+
+        let hits = storage
+            .search_context(&crate::storage_v2::SearchFilter::new().query("Ship"))
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+
+        // Later v1 writes catch up; earlier ones are not copied twice
+        v1.store_context(&Context::new("beta", "note", ContextType::Note, "Later")).await.unwrap();
+        assert_eq!(storage.import_v1_contexts().await.unwrap(), 1);
+        assert_eq!(storage.import_v1_contexts().await.unwrap(), 0);
+        assert_eq!(storage.get_project_context("beta", None).await.unwrap().entries.len(), 2);
+
+        // Both back ends keep working on the shared file
+        storage
+            .store_context("alpha", "db", "decision", "Postgres", None, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(storage.list_revisions("alpha", "db").await.unwrap().len(), 3);
+        v1.store_context(&Context::new("alpha", "db", ContextType::Decision, "DuckDB")).await.unwrap();
+        assert_eq!(v1.list_revisions("alpha", "db").await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_purged_imports_stay_gone() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("shared.db");
+        std::fs::File::create(&db_path).unwrap();
+
+        let v1 = storage::Storage::new(&db_path).await.unwrap();
+        v1.store_context(&Context::new("alpha", "db", ContextType::Decision, "SQLite")).await.unwrap();
+        v1.store_context(&Context::new("alpha", "todo", ContextType::Todo, "Ship it")).await.unwrap();
+
+        let storage = Storage::new(&db_path).await.unwrap();
+        storage.delete_context("alpha", "db", true).await.unwrap();
+        storage.close().await;
+
+        let storage = Storage::new(&db_path).await.unwrap();
+        let alpha = storage.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(alpha.entries.len(), 1);
+        assert_eq!(alpha.entries[0].key, "todo");
+        assert_eq!(storage.import_v1_contexts().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_v1_overwrites_after_import_are_synced() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("shared.db");
+        std::fs::File::create(&db_path).unwrap();
+
+        let v1 = storage::Storage::new(&db_path).await.unwrap();
+        v1.store_context(&Context::new("alpha", "db", ContextType::Decision, "MySQL")).await.unwrap();
+        v1.store_context(&Context::new("alpha", "todo", ContextType::Todo, "Ship it")).await.unwrap();
+
+        let storage = Storage::new(&db_path).await.unwrap();
+        assert_eq!(storage.import_v1_contexts().await.unwrap(), 0);
+
+        v1.store_context_as(&Context::new("alpha", "db", ContextType::Decision, "SQLite"), Some("architect"))
+            .await
+            .unwrap();
+        assert_eq!(storage.import_v1_contexts().await.unwrap(), 1);
+        assert_eq!(storage.import_v1_contexts().await.unwrap(), 0);
+
+        let alpha = storage.get_project_context("alpha", None).await.unwrap();
+        let entry = alpha.entries.iter().find(|e| e.key == "db").unwrap();
+        assert_eq!(entry.value, "SQLite");
+        let revisions = storage.list_revisions("alpha", "db").await.unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].value, "MySQL");
+        assert_eq!(revisions[0].replaced_by_role.as_deref(), Some("architect"));
+
+        // Deleted entries are not brought back by a later v1 overwrite
+        storage.delete_context("alpha", "todo", false).await.unwrap();
+        v1.store_context(&Context::new("alpha", "todo", ContextType::Todo, "Shipped")).await.unwrap();
+        assert_eq!(storage.import_v1_contexts().await.unwrap(), 0);
+        let alpha = storage.get_project_context("alpha", None).await.unwrap();
+        assert_eq!(alpha.entries.len(), 1);
+    }
+}
@@ -0,0 +1,311 @@
+//! Retention rules for aging out stale context
+//!
+//! Rules select entries by project and/or type and give them a maximum age.
+//! Expired entries are soft deleted, so they stay restorable until purged.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use tracing::info;
+
+use super::{
+    parse_datetime, HistoryEvent, Storage, ACTION_DELETE, ACTION_STATUS_CHANGE, ENTITY_CONTEXT,
+    ENTITY_PROJECT,
+};
+
+/// Maximum age for the entries a rule selects
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionRule {
+    /// Only entries in this project; every project if unset
+    pub project: Option<String>,
+    /// Only entries of this type; every type if unset
+    #[serde(rename = "type")]
+    pub context_type: Option<String>,
+    /// Days since the last update before an entry expires; unset keeps forever
+    pub max_age_days: Option<i64>,
+}
+
+impl RetentionRule {
+    fn matches(&self, project: Option<&str>, context_type: &str) -> bool {
+        self.project.as_deref().is_none_or(|p| Some(p) == project)
+            && self.context_type.as_deref().is_none_or(|t| t == context_type)
+    }
+
+    /// Project-and-type rules beat project rules, which beat type rules
+    fn specificity(&self) -> u8 {
+        (self.project.is_some() as u8) * 2 + self.context_type.is_some() as u8
+    }
+}
+
+/// Full set of retention settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    pub rules: Vec<RetentionRule>,
+    /// Archive projects whose `last_accessed` is older than this many days
+    pub archive_after_days: Option<i64>,
+}
+
+impl RetentionPolicy {
+    /// Maximum age for an entry; the most specific matching rule wins
+    pub fn max_age_days(&self, project: Option<&str>, context_type: &str) -> Option<i64> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(project, context_type))
+            .max_by_key(|rule| rule.specificity())
+            .and_then(|rule| rule.max_age_days)
+    }
+}
+
+/// An entry removed, or due to be removed, by retention
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiredEntry {
+    pub id: i64,
+    pub project_name: Option<String>,
+    pub key: String,
+    #[serde(rename = "type")]
+    pub context_type: String,
+    pub updated_at: DateTime<Utc>,
+    pub max_age_days: i64,
+}
+
+/// What a retention run did, or would do when `dry_run` is set
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub expired_entries: Vec<ExpiredEntry>,
+    pub archived_projects: Vec<String>,
+}
+
+impl Storage {
+    /// Retention policy used by scheduled maintenance
+    pub fn retention_policy(&self) -> &RetentionPolicy {
+        &self.retention_policy
+    }
+
+    /// Replace the retention policy used by scheduled maintenance
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy) {
+        self.retention_policy = policy;
+    }
+
+    /// Expire old entries and archive idle projects according to `policy`
+    ///
+    /// With `dry_run` nothing is changed and the report lists what would be.
+    pub async fn apply_retention(&self, policy: &RetentionPolicy, dry_run: bool) -> Result<RetentionReport> {
+        let now = Utc::now();
+        let mut report = RetentionReport {
+            dry_run,
+            ..Default::default()
+        };
+
+        if policy.rules.iter().any(|rule| rule.max_age_days.is_some()) {
+            let rows = sqlx::query(
+                r#"
+                SELECT ce.id, ce.key, ce.type, ce.updated_at, p.name AS project_name
+                FROM context_entries ce
+                LEFT JOIN projects p ON ce.project_id = p.id
+                WHERE ce.deleted_at IS NULL AND p.deleted_at IS NULL
+                ORDER BY ce.updated_at
+                "#
+            )
+            .fetch_all(&self.pool)
+            .await?;
+
+            for row in rows {
+                let project_name: Option<String> = row.get("project_name");
+                let context_type: String = row.get("type");
+                let Some(max_age_days) = policy.max_age_days(project_name.as_deref(), &context_type) else {
+                    continue;
+                };
+
+                let updated_at = parse_datetime(&row.get::<String, _>("updated_at"))?;
+                if updated_at < now - Duration::days(max_age_days) {
+                    report.expired_entries.push(ExpiredEntry {
+                        id: row.get("id"),
+                        project_name,
+                        key: row.get("key"),
+                        context_type,
+                        updated_at,
+                        max_age_days,
+                    });
+                }
+            }
+        }
+
+        if let Some(days) = policy.archive_after_days {
+            report.archived_projects = sqlx::query_scalar::<_, String>(
+                r#"
+                SELECT name FROM projects
+                WHERE deleted_at IS NULL AND status != 'archived'
+                  AND last_accessed < datetime('now', ?1)
+                ORDER BY last_accessed
+                "#
+            )
+            .bind(format!("-{} days", days))
+            .fetch_all(&self.pool)
+            .await?;
+        }
+
+        if dry_run {
+            return Ok(report);
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        for entry in &report.expired_entries {
+            let project_id = sqlx::query_scalar::<_, Option<i64>>(
+                "UPDATE context_entries SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?1 RETURNING project_id"
+            )
+            .bind(entry.id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            HistoryEvent {
+                entity_type: ENTITY_CONTEXT,
+                entity_id: entry.id,
+                project_id,
+                action: ACTION_DELETE,
+                changes: Some(serde_json::json!({
+                    "key": entry.key,
+                    "reason": "retention",
+                    "max_age_days": entry.max_age_days,
+                })),
+                note: None,
+                role_id: None,
+            }
+            .record(&mut tx)
+            .await?;
+        }
+
+        for name in &report.archived_projects {
+            let (project_id, old_status) = sqlx::query_as::<_, (i64, Option<String>)>(
+                "SELECT id, status FROM projects WHERE name = ?1"
+            )
+            .bind(name)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            sqlx::query("UPDATE projects SET status = 'archived', updated_at = CURRENT_TIMESTAMP WHERE id = ?1")
+                .bind(project_id)
+                .execute(&mut *tx)
+                .await?;
+
+            HistoryEvent {
+                entity_type: ENTITY_PROJECT,
+                entity_id: project_id,
+                project_id: Some(project_id),
+                action: ACTION_STATUS_CHANGE,
+                changes: Some(serde_json::json!({
+                    "from": old_status,
+                    "to": "archived",
+                    "reason": "retention",
+                })),
+                note: None,
+                role_id: None,
+            }
+            .record(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        info!(
+            "Retention expired {} entries and archived {} projects",
+            report.expired_entries.len(),
+            report.archived_projects.len()
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn rule(project: Option<&str>, context_type: Option<&str>, max_age_days: Option<i64>) -> RetentionRule {
+        RetentionRule {
+            project: project.map(str::to_string),
+            context_type: context_type.map(str::to_string),
+            max_age_days,
+        }
+    }
+
+    #[test]
+    fn test_most_specific_rule_wins() {
+        let policy = RetentionPolicy {
+            rules: vec![
+                rule(None, None, Some(365)),
+                rule(None, Some("note"), Some(90)),
+                rule(None, Some("decision"), None),
+                rule(Some("scratch"), None, Some(7)),
+                rule(Some("scratch"), Some("decision"), Some(30)),
+            ],
+            archive_after_days: None,
+        };
+
+        assert_eq!(policy.max_age_days(Some("alpha"), "note"), Some(90));
+        assert_eq!(policy.max_age_days(Some("alpha"), "decision"), None);
+        assert_eq!(policy.max_age_days(Some("alpha"), "todo"), Some(365));
+        assert_eq!(policy.max_age_days(Some("scratch"), "note"), Some(7));
+        assert_eq!(policy.max_age_days(Some("scratch"), "decision"), Some(30));
+        assert_eq!(RetentionPolicy::default().max_age_days(None, "note"), None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_retention_dry_run_and_apply() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("retention.db")).await.unwrap();
+
+        for (key, context_type) in [("old-note", "note"), ("old-decision", "decision"), ("new-note", "note")] {
+            storage
+                .store_context("alpha", key, context_type, "value", None, None, None, None)
+                .await
+                .unwrap();
+        }
+        storage
+            .store_context("idle", "k", "note", "value", None, None, None, None)
+            .await
+            .unwrap();
+
+        // Age everything except new-note, and leave "idle" untouched for a year
+        sqlx::query("UPDATE context_entries SET updated_at = datetime('now', '-120 days') WHERE key != 'new-note'")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE projects SET last_accessed = datetime('now', '-365 days') WHERE name = 'idle'")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+
+        let policy: RetentionPolicy = serde_json::from_value(serde_json::json!({
+            "rules": [
+                { "type": "note", "max_age_days": 90 },
+                { "type": "decision" },
+                { "project": "idle", "type": "note" },
+            ],
+            "archive_after_days": 180,
+        }))
+        .unwrap();
+
+        let report = storage.apply_retention(&policy, true).await.unwrap();
+        let keys: Vec<_> = report.expired_entries.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["old-note"]);
+        assert_eq!(report.archived_projects, ["idle"]);
+        assert_eq!(storage.get_project_context("alpha", None).await.unwrap().entries.len(), 3);
+
+        let report = storage.apply_retention(&policy, false).await.unwrap();
+        assert!(!report.dry_run);
+        let context = storage.get_project_context("alpha", None).await.unwrap();
+        assert!(context.entries.iter().all(|e| e.key != "old-note"));
+        assert_eq!(context.entries.len(), 2);
+
+        // Archived, and still restorable since entries are only tombstoned
+        assert!(storage.list_projects(None).await.unwrap().iter().all(|p| p.name != "idle"));
+        storage.restore("alpha", Some("old-note")).await.unwrap();
+
+        let again = storage.apply_retention(&policy, true).await.unwrap();
+        assert!(again.archived_projects.is_empty());
+    }
+}
@@ -0,0 +1,389 @@
+//! Tenants and API tokens
+//!
+//! A project belongs to at most one tenant; projects without one predate
+//! tenancy and are only visible to unscoped callers. Project names stay
+//! unique across tenants. Tokens are stored as SHA-256 hashes, so the secret
+//! is only ever returned when it is issued.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::Row;
+use tracing::info;
+
+use super::{parse_datetime, HistoryEvent, Storage, ACTION_CREATE, ENTITY_PROJECT};
+use crate::error::MpcmError;
+
+/// Prefix of every issued token, to make leaked tokens easy to spot
+pub const TOKEN_PREFIX: &str = "mpcm_";
+
+/// An API token, without its secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    pub tenant: Option<String>,
+    pub role: String,
+    /// First characters of the token, for telling tokens apart
+    pub prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A freshly issued token; the only time its secret is available
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: ApiToken,
+}
+
+const TOKEN_COLUMNS: &str = r#"
+    SELECT tk.id, tk.name, t.name AS tenant, tk.role, tk.prefix,
+           tk.created_at, tk.last_used_at, tk.revoked_at
+    FROM api_tokens tk
+    LEFT JOIN tenants t ON tk.tenant_id = t.id
+"#;
+
+impl Storage {
+    /// Issue a token for `tenant` (created on first use), or an unscoped one
+    pub async fn issue_token(&self, name: &str, tenant: Option<&str>, role: &str) -> Result<IssuedToken> {
+        if name.trim().is_empty() {
+            return Err(MpcmError::InvalidArgument("Token name must not be empty".to_string()).into());
+        }
+        let tenant_id = match tenant {
+            Some(tenant) => Some(self.ensure_tenant(tenant).await?),
+            None => None,
+        };
+
+        let token = format!(
+            "{}{}{}",
+            TOKEN_PREFIX,
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let prefix = token[..TOKEN_PREFIX.len() + 8].to_string();
+        let id = sqlx::query(
+            "INSERT INTO api_tokens (name, tenant_id, role, token_hash, prefix) VALUES (?1, ?2, ?3, ?4, ?5)"
+        )
+        .bind(name)
+        .bind(tenant_id)
+        .bind(role)
+        .bind(hash_token(&token))
+        .bind(&prefix)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+
+        info!("Issued token {} ({}) for tenant {:?}", id, prefix, tenant);
+        Ok(IssuedToken {
+            token,
+            info: self.get_token(id).await?,
+        })
+    }
+
+    /// Tokens, optionally only those of one tenant, oldest first
+    pub async fn list_tokens(&self, tenant: Option<&str>) -> Result<Vec<ApiToken>> {
+        let rows = match tenant {
+            Some(tenant) => {
+                sqlx::query(&format!("{} WHERE t.name = ?1 ORDER BY tk.id", TOKEN_COLUMNS))
+                    .bind(tenant)
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+            None => {
+                sqlx::query(&format!("{} ORDER BY tk.id", TOKEN_COLUMNS))
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+        };
+
+        rows.iter().map(row_to_token).collect()
+    }
+
+    /// Revoke a token; it stops authenticating immediately
+    pub async fn revoke_token(&self, id: i64) -> Result<ApiToken> {
+        let revoked = sqlx::query(
+            "UPDATE api_tokens SET revoked_at = CURRENT_TIMESTAMP WHERE id = ?1 AND revoked_at IS NULL"
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if revoked == 0 {
+            return Err(MpcmError::InvalidArgument(format!("No active token with id {}", id)).into());
+        }
+
+        info!("Revoked token {}", id);
+        self.get_token(id).await
+    }
+
+    /// The live token matching `token`, recording that it was used
+    pub async fn authenticate_token(&self, token: &str) -> Result<Option<ApiToken>> {
+        let id = sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM api_tokens WHERE token_hash = ?1 AND revoked_at IS NULL"
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+
+        sqlx::query("UPDATE api_tokens SET last_used_at = CURRENT_TIMESTAMP WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(Some(self.get_token(id).await?))
+    }
+
+    /// Fail with `ProjectNotFound` if the project exists outside `tenant`
+    ///
+    /// Projects that do not exist yet are allowed, so the tenant can create them.
+    pub async fn check_project_access(&self, project_name: &str, tenant: &str) -> Result<()> {
+        let owner = sqlx::query(
+            r#"
+            SELECT t.name AS tenant
+            FROM projects p
+            LEFT JOIN tenants t ON p.tenant_id = t.id
+            WHERE p.name = ?1
+            "#
+        )
+        .bind(project_name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match owner {
+            Some(row) if row.get::<Option<String>, _>("tenant").as_deref() != Some(tenant) => {
+                Err(MpcmError::ProjectNotFound {
+                    project: project_name.to_string(),
+                }
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether any tenant has been created
+    pub async fn has_tenants(&self) -> Result<bool> {
+        Ok(sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM tenants)")
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    /// Tenant owning `project_name`; `None` if it is unowned or does not exist
+    pub async fn project_tenant(&self, project_name: &str) -> Result<Option<String>> {
+        let owner = sqlx::query_scalar::<_, Option<String>>(
+            r#"
+            SELECT t.name
+            FROM projects p
+            LEFT JOIN tenants t ON p.tenant_id = t.id
+            WHERE p.name = ?1
+            "#
+        )
+        .bind(project_name)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(owner.flatten())
+    }
+
+    /// Create `project_name` for `tenant` unless it exists already
+    ///
+    /// Fails with `ProjectNotFound` if it exists outside `tenant`. The
+    /// project is created with its owner in one insert, so two tenants
+    /// racing for a new name cannot both write to it.
+    pub async fn claim_project(&self, project_name: &str, tenant: &str) -> Result<()> {
+        let tenant_id = self.ensure_tenant(tenant).await?;
+        let mut tx = self.pool.begin().await?;
+        let created = sqlx::query(
+            r#"
+            INSERT INTO projects (name, status, primary_system_id, tenant_id, created_at, updated_at, last_accessed)
+            VALUES (?1, 'active', ?2, ?3, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+            ON CONFLICT(name) DO NOTHING
+            "#
+        )
+        .bind(project_name)
+        .bind(self.current_system_id)
+        .bind(tenant_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if created.rows_affected() == 0 {
+            let owner = sqlx::query_scalar::<_, Option<i64>>("SELECT tenant_id FROM projects WHERE name = ?1")
+                .bind(project_name)
+                .fetch_one(&mut *tx)
+                .await?;
+            if owner != Some(tenant_id) {
+                return Err(MpcmError::ProjectNotFound {
+                    project: project_name.to_string(),
+                }
+                .into());
+            }
+        } else {
+            let project_id = created.last_insert_rowid();
+            HistoryEvent {
+                entity_type: ENTITY_PROJECT,
+                entity_id: project_id,
+                project_id: Some(project_id),
+                action: ACTION_CREATE,
+                changes: Some(serde_json::json!({ "name": project_name, "tenant": tenant })),
+                note: None,
+                role_id: None,
+            }
+            .record(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Names of the projects owned by `tenant`
+    pub async fn tenant_projects(&self, tenant: &str) -> Result<Vec<String>> {
+        Ok(sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT p.name FROM projects p
+            JOIN tenants t ON p.tenant_id = t.id
+            WHERE t.name = ?1
+            ORDER BY p.name
+            "#
+        )
+        .bind(tenant)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    pub(super) async fn ensure_tenant(&self, name: &str) -> Result<i64> {
+        if name.trim().is_empty() {
+            return Err(MpcmError::InvalidArgument("Tenant name must not be empty".to_string()).into());
+        }
+        sqlx::query("INSERT OR IGNORE INTO tenants (name) VALUES (?1)")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(sqlx::query_scalar::<_, i64>("SELECT id FROM tenants WHERE name = ?1")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    async fn get_token(&self, id: i64) -> Result<ApiToken> {
+        let row = sqlx::query(&format!("{} WHERE tk.id = ?1", TOKEN_COLUMNS))
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        row_to_token(&row)
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn row_to_token(row: &sqlx::sqlite::SqliteRow) -> Result<ApiToken> {
+    let optional_time = |column: &str| -> Result<Option<DateTime<Utc>>> {
+        row.get::<Option<String>, _>(column)
+            .map(|s| parse_datetime(&s))
+            .transpose()
+    };
+    Ok(ApiToken {
+        id: row.get("id"),
+        name: row.get("name"),
+        tenant: row.get("tenant"),
+        role: row.get("role"),
+        prefix: row.get("prefix"),
+        created_at: parse_datetime(&row.get::<String, _>("created_at"))?,
+        last_used_at: optional_time("last_used_at")?,
+        revoked_at: optional_time("revoked_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_v2::{ConflictPolicy, SearchFilter};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_tokens_authenticate_until_revoked() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("tokens.db")).await.unwrap();
+
+        let issued = storage.issue_token("ci", Some("team-a"), "read_write").await.unwrap();
+        assert!(issued.token.starts_with(TOKEN_PREFIX));
+        assert!(issued.token.starts_with(&issued.info.prefix));
+        assert_eq!(issued.info.tenant.as_deref(), Some("team-a"));
+
+        let found = storage.authenticate_token(&issued.token).await.unwrap().unwrap();
+        assert_eq!(found.id, issued.info.id);
+        assert!(found.last_used_at.is_some());
+        assert!(storage.authenticate_token("mpcm_guess").await.unwrap().is_none());
+
+        storage.issue_token("ops", None, "admin").await.unwrap();
+        assert_eq!(storage.list_tokens(None).await.unwrap().len(), 2);
+        assert_eq!(storage.list_tokens(Some("team-a")).await.unwrap().len(), 1);
+
+        let revoked = storage.revoke_token(issued.info.id).await.unwrap();
+        assert!(revoked.revoked_at.is_some());
+        assert!(storage.authenticate_token(&issued.token).await.unwrap().is_none());
+        assert!(storage.revoke_token(issued.info.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_projects_are_scoped_to_tenants() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().join("tenants.db")).await.unwrap();
+
+        assert!(!storage.has_tenants().await.unwrap());
+        for (tenant, project) in [("team-a", "alpha"), ("team-b", "beta")] {
+            storage.check_project_access(project, tenant).await.unwrap();
+            storage.claim_project(project, tenant).await.unwrap();
+            storage
+                .store_context(project, "k", "note", &format!("shared words in {}", project), None, None, None, None)
+                .await
+                .unwrap();
+        }
+        storage
+            .store_context("legacy", "k", "note", "shared words", None, None, None, None)
+            .await
+            .unwrap();
+
+        assert!(storage.has_tenants().await.unwrap());
+        assert_eq!(storage.tenant_projects("team-a").await.unwrap(), vec!["alpha"]);
+        assert_eq!(storage.project_tenant("alpha").await.unwrap().as_deref(), Some("team-a"));
+        assert_eq!(storage.project_tenant("legacy").await.unwrap(), None);
+        storage.check_project_access("alpha", "team-a").await.unwrap();
+        for project in ["beta", "legacy"] {
+            let err = storage.check_project_access(project, "team-a").await.unwrap_err();
+            assert!(matches!(err.downcast_ref(), Some(MpcmError::ProjectNotFound { .. })));
+        }
+
+        // Claiming never moves a project between tenants, nor hands out unowned ones
+        storage.claim_project("alpha", "team-a").await.unwrap();
+        for project in ["alpha", "legacy"] {
+            let err = storage.claim_project(project, "team-b").await.unwrap_err();
+            assert!(matches!(err.downcast_ref(), Some(MpcmError::ProjectNotFound { .. })));
+        }
+        assert_eq!(storage.tenant_projects("team-b").await.unwrap(), vec!["beta"]);
+
+        // Of two tenants racing to create a name, exactly one gets it
+        let (a, b) = tokio::join!(storage.claim_project("gamma", "team-a"), storage.claim_project("gamma", "team-b"));
+        assert!(a.is_ok() != b.is_ok());
+        let winner = if a.is_ok() { "team-a" } else { "team-b" };
+        assert_eq!(storage.project_tenant("gamma").await.unwrap().as_deref(), Some(winner));
+
+        // Imports are held to the same owner, and create projects already owned
+        let mut archive = storage.export_project("alpha").await.unwrap();
+        let err = storage.import_project_as(&archive, ConflictPolicy::Overwrite, Some("team-b")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(MpcmError::ProjectNotFound { .. })));
+        archive.project.name = "delta".to_string();
+        storage.import_project_as(&archive, ConflictPolicy::Skip, Some("team-b")).await.unwrap();
+        assert_eq!(storage.project_tenant("delta").await.unwrap().as_deref(), Some("team-b"));
+
+        let filter = SearchFilter::new().query("shared").tenant("team-a");
+        let hits = storage.search_context(&filter).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entry.value, "shared words in alpha");
+    }
+}
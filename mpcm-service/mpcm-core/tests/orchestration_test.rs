@@ -98,7 +98,8 @@ async fn test_multi_service_orchestration() {
     assert!(status_result.success);
     
     // Verify untracked file shows up
-    let stdout = status_result.data.unwrap()["stdout"].as_str().unwrap();
+    let status_data = status_result.data.unwrap();
+    let stdout = status_data["stdout"].as_str().unwrap();
     assert!(stdout.contains("README.md"));
     
     // Step 5: Stage the file
@@ -130,7 +131,8 @@ async fn test_multi_service_orchestration() {
     assert!(commit_result.success);
     
     // Verify commit message was enhanced with project context
-    let commit_msg = commit_result.data.unwrap()["commit_message"].as_str().unwrap();
+    let commit_data = commit_result.data.unwrap();
+    let commit_msg = commit_data["commit_message"].as_str().unwrap();
     assert!(commit_msg.contains(&format!("[{}]", project_name)));
     
     // Step 7: Verify clean git status
@@ -163,7 +165,7 @@ async fn test_broadcast_routing() {
     
     // Create router with broadcast strategy
     let mut router = RequestRouter::new(registry.clone());
-    router.set_default_strategy(crate::registry::RoutingStrategy::Broadcast);
+    router.set_default_strategy(mpcm_core::registry::RoutingStrategy::Broadcast);
     
     // Both filesystem and terminal can list directory contents
     // FileSystem has "listDirectory", Terminal can "execute ls"
@@ -0,0 +1,206 @@
+//! Caller identity and role checks
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::str::FromStr;
+
+use crate::mcp::MUTATING_METHODS;
+use mpcm_core::storage_v2::ApiToken;
+use mpcm_core::MpcmError;
+
+/// Methods only administrators may call
+const ADMIN_METHODS: [&str; 6] = [
+    "purge",
+    "register_context_type",
+    "unregister_context_type",
+    "issue_token",
+    "list_tokens",
+    "revoke_token",
+];
+
+/// Methods that reach across tenants, and so are closed to tenant callers
+const GLOBAL_METHODS: [&str; 4] = [
+    "retention_dry_run",
+    "purge",
+    "register_context_type",
+    "unregister_context_type",
+];
+
+/// What a caller is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum Role {
+    /// Everything, including purges and context type changes
+    Admin,
+    /// Read and write context and use the adapter tools
+    ReadWrite,
+    /// Read context only
+    ReadOnly,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::ReadWrite => "read_write",
+            Role::ReadOnly => "read_only",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = MpcmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "read_write" => Ok(Role::ReadWrite),
+            "read_only" => Ok(Role::ReadOnly),
+            other => Err(MpcmError::InvalidArgument(format!("Unknown role: {}", other))),
+        }
+    }
+}
+
+/// Who is on the other end of a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    /// Certificate common name or token name; `None` for local and anonymous callers
+    pub name: Option<String>,
+    /// Tenant the caller belongs to; unscoped callers see every project
+    pub tenant: Option<String>,
+    pub role: Role,
+    /// False for network callers that presented no credentials
+    pub authenticated: bool,
+}
+
+impl Identity {
+    /// The owner of the process, reached over stdio or the 0600 Unix socket
+    pub fn local() -> Self {
+        Self {
+            name: None,
+            tenant: None,
+            role: Role::Admin,
+            authenticated: true,
+        }
+    }
+
+    /// A network caller without a token or mapped certificate
+    ///
+    /// Only served while no tenant exists, unless tokens are required outright.
+    pub fn anonymous() -> Self {
+        Self {
+            name: None,
+            tenant: None,
+            role: Role::ReadWrite,
+            authenticated: false,
+        }
+    }
+
+    /// The caller holding `token`
+    pub fn from_token(token: &ApiToken) -> Result<Self, MpcmError> {
+        Ok(Self {
+            name: Some(token.name.clone()),
+            tenant: token.tenant.clone(),
+            role: token.role.parse()?,
+            authenticated: true,
+        })
+    }
+
+    /// Fail unless the caller may run the storage method `method`
+    pub fn authorize(&self, method: &str) -> Result<(), MpcmError> {
+        if self.tenant.is_some() && GLOBAL_METHODS.contains(&method) {
+            return Err(self.denied(method));
+        }
+        let allowed = match self.role {
+            Role::Admin => true,
+            Role::ReadWrite => !ADMIN_METHODS.contains(&method),
+            Role::ReadOnly => !ADMIN_METHODS.contains(&method) && !MUTATING_METHODS.contains(&method),
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(self.denied(method))
+        }
+    }
+
+    /// Whether the caller may see a project owned by `owner`
+    ///
+    /// Anonymous callers only see projects that belong to no tenant.
+    pub fn can_access(&self, owner: Option<&str>) -> bool {
+        match &self.tenant {
+            Some(tenant) => owner == Some(tenant.as_str()),
+            None => self.authenticated || owner.is_none(),
+        }
+    }
+
+    /// Fail unless the caller may run the adapter tool `tool` for `project`
+    ///
+    /// Adapter tools write files and run commands under an adapter root no
+    /// tenant owns. Anonymous and read-only callers get none of them, and
+    /// tenant callers only calls scoped to a project.
+    pub fn authorize_tool(&self, tool: &str, project: Option<&str>) -> Result<(), MpcmError> {
+        if !self.authenticated {
+            return Err(MpcmError::Unauthenticated(format!("{} needs a token", tool)));
+        }
+        if self.role == Role::ReadOnly || (self.tenant.is_some() && project.is_none()) {
+            return Err(self.denied(tool));
+        }
+        Ok(())
+    }
+
+    fn denied(&self, method: &str) -> MpcmError {
+        MpcmError::PermissionDenied {
+            role: self.role.as_str().to_string(),
+            method: method.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_limit_methods() {
+        let caller = |role| Identity {
+            name: Some("cn".to_string()),
+            tenant: None,
+            role,
+            authenticated: true,
+        };
+
+        assert!(Identity::local().authorize("purge").is_ok());
+
+        let writer = caller(Role::ReadWrite);
+        assert!(writer.authorize("store_context").is_ok());
+        assert!(writer.authorize("purge").is_err());
+        assert!(writer.authorize_tool("readFile", None).is_ok());
+        let err = Identity::anonymous().authorize_tool("execute", None).unwrap_err();
+        assert_eq!(err.code(), mpcm_core::error::codes::UNAUTHENTICATED);
+
+        let reader = caller(Role::ReadOnly);
+        assert!(reader.authorize("search_context").is_ok());
+        assert!(reader.authorize_tool("readFile", None).is_err());
+        let err = reader.authorize("store_context").unwrap_err();
+        assert_eq!(err.code(), mpcm_core::error::codes::PERMISSION_DENIED);
+
+        // Tenant admins manage their data, not the whole server
+        let tenant_admin = Identity {
+            tenant: Some("team-a".to_string()),
+            ..caller(Role::Admin)
+        };
+        assert!(tenant_admin.authorize("delete_project").is_ok());
+        assert!(tenant_admin.authorize("purge").is_err());
+        assert!(tenant_admin.authorize("retention_dry_run").is_err());
+        assert!(tenant_admin.authorize_tool("execute", None).is_err());
+        assert!(tenant_admin.authorize_tool("execute", Some("alpha")).is_ok());
+        assert!(tenant_admin.can_access(Some("team-a")));
+        assert!(!tenant_admin.can_access(Some("team-b")));
+        assert!(!tenant_admin.can_access(None));
+
+        assert!(Identity::local().can_access(Some("team-a")));
+        assert!(!Identity::anonymous().can_access(Some("team-a")));
+        assert!(Identity::anonymous().can_access(None));
+    }
+}
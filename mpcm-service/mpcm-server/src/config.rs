@@ -0,0 +1,445 @@
+//! TOML configuration file
+//!
+//! Every section is optional, as is every key in it. Keys outside
+//! `[adapters]` and `[routing]` mirror command line flags and lose to them
+//! and to their environment variables; adapter and routing settings only
+//! exist here. The merged settings are validated as a whole.
+//!
+//! ```toml
+//! [server]
+//! db_path = "~/.mpcm-pro/mpcm-pro.db"
+//! socket_path = "/tmp/mpcm.sock"
+//! log_level = "debug"
+//! max_connections = 50
+//!
+//! [auth]
+//! require_token = true
+//!
+//! [tls]
+//! addr = "0.0.0.0:7443"
+//! cert = "~/.mpcm-pro/server.pem"
+//! key = "~/.mpcm-pro/server.key"
+//!
+//! [storage]
+//! tombstone_retention_days = 30
+//!
+//! [backup]
+//! interval_minutes = 60
+//! keep = 7
+//!
+//! [maintenance]
+//! interval_minutes = 60
+//!
+//! [registry]
+//! health_check_interval = 30
+//!
+//! [adapters]
+//! root = "~/projects"
+//!
+//! [adapters.terminal]
+//! allowed_commands = ["ls", "cargo", "git"]
+//!
+//! [adapters.git]
+//! enabled = false
+//!
+//! [routing]
+//! strategy = "first_match"
+//! tools = { execute = "terminal" }
+//! ```
+
+use anyhow::{bail, Context as _, Result};
+use mpcm_core::adapters::TerminalAdapter;
+use mpcm_core::registry::{RequestRouter, RoutingStrategy};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tracing::Level;
+
+use crate::expand_home_dir;
+
+/// File name looked for in `~/.mpcm-pro/` when no `--config` is given
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Adapters the server knows how to start
+pub const ADAPTER_NAMES: &[&str] = &["filesystem", "git", "terminal"];
+
+/// Contents of a configuration file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    pub tls: TlsConfig,
+    pub storage: StorageConfig,
+    pub backup: BackupConfig,
+    pub maintenance: MaintenanceConfig,
+    pub registry: RegistryConfig,
+    pub adapters: AdaptersConfig,
+    pub routing: RoutingConfig,
+}
+
+/// `[server]` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub db_path: Option<PathBuf>,
+    pub socket_path: Option<PathBuf>,
+    pub log_level: Option<String>,
+    pub max_connections: Option<usize>,
+    pub request_timeout: Option<u64>,
+    pub shutdown_grace: Option<u64>,
+}
+
+/// `[auth]` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub require_token: Option<bool>,
+}
+
+/// `[tls]` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub addr: Option<SocketAddr>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub client_ca: Option<PathBuf>,
+    pub client_map: Option<PathBuf>,
+}
+
+/// `[storage]` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub tombstone_retention_days: Option<i64>,
+    pub retention_config: Option<PathBuf>,
+}
+
+/// `[backup]` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    pub dir: Option<PathBuf>,
+    /// Minutes between scheduled backups; 0 disables them
+    pub interval_minutes: Option<u64>,
+    pub keep: Option<usize>,
+}
+
+/// `[maintenance]` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaintenanceConfig {
+    /// Minutes between retention and tombstone purge runs; 0 disables them
+    pub interval_minutes: Option<u64>,
+}
+
+/// `[registry]` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistryConfig {
+    pub health_check_interval: Option<u64>,
+}
+
+/// `[adapters]` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptersConfig {
+    /// Directory every adapter works in unless it sets its own
+    pub root: Option<PathBuf>,
+    pub filesystem: AdapterConfig,
+    pub git: AdapterConfig,
+    pub terminal: TerminalConfig,
+}
+
+/// `[adapters.filesystem]` and `[adapters.git]`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdapterConfig {
+    pub enabled: bool,
+    pub root: Option<PathBuf>,
+}
+
+impl Default for AdapterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            root: None,
+        }
+    }
+}
+
+/// `[adapters.terminal]`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminalConfig {
+    pub enabled: bool,
+    pub root: Option<PathBuf>,
+    /// Commands that may be run; the adapter's built-in list if unset
+    pub allowed_commands: Option<Vec<String>>,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            root: None,
+            allowed_commands: None,
+        }
+    }
+}
+
+/// `[routing]` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutingConfig {
+    pub strategy: Option<Strategy>,
+    /// Tool names sent straight to the named adapter
+    pub tools: BTreeMap<String, String>,
+}
+
+/// How tools without a mapping are routed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// The first adapter offering the tool
+    FirstMatch,
+    /// Every adapter offering the tool
+    Broadcast,
+    /// Only mapped tools are routed
+    Direct,
+}
+
+impl From<Strategy> for RoutingStrategy {
+    fn from(strategy: Strategy) -> Self {
+        match strategy {
+            Strategy::FirstMatch => RoutingStrategy::FirstMatch,
+            Strategy::Broadcast => RoutingStrategy::Broadcast,
+            Strategy::Direct => RoutingStrategy::Direct(mpcm_core::registry::DirectRoute),
+        }
+    }
+}
+
+impl Config {
+    /// Read and validate the file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read config {:?}", path))?;
+        let config: Config = toml::from_str(&text).with_context(|| format!("Invalid config {:?}", path))?;
+        config.validate().with_context(|| format!("Invalid config {:?}", path))?;
+        Ok(config)
+    }
+
+    /// Where the config file is looked for when none is given
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".mpcm-pro").join(CONFIG_FILE_NAME))
+    }
+
+    /// Load `explicit`, or `~/.mpcm-pro/config.toml` if it exists
+    ///
+    /// Returns the path that was read alongside its contents; with neither
+    /// there is nothing to layer and the defaults are used.
+    pub fn discover(explicit: Option<&Path>) -> Result<Option<(PathBuf, Self)>> {
+        let path = match explicit {
+            Some(path) => expand_home_dir(path),
+            None => match Self::default_path() {
+                Some(path) => path,
+                None => return Ok(None),
+            },
+        };
+        if explicit.is_none() && !path.exists() {
+            return Ok(None);
+        }
+        let config = Self::load(&path)?;
+        Ok(Some((path, config)))
+    }
+
+    /// Check values that parse but cannot be used
+    pub fn validate(&self) -> Result<()> {
+        if let Some(level) = &self.server.log_level {
+            if level.parse::<Level>().is_err() {
+                bail!("server.log_level: unknown level {:?}", level);
+            }
+        }
+        if self.server.max_connections == Some(0) {
+            bail!("server.max_connections must be at least 1");
+        }
+        if self.backup.keep == Some(0) {
+            bail!("backup.keep must be at least 1");
+        }
+        if self.tls.cert.is_some() != self.tls.key.is_some() {
+            bail!("tls.cert and tls.key must be given together");
+        }
+        if self.tls.client_map.is_some() && self.tls.client_ca.is_none() {
+            bail!("tls.client_map needs tls.client_ca to verify the certificates it maps");
+        }
+
+        let adapters = &self.adapters;
+        let roots = [
+            ("adapters.root", &adapters.root),
+            ("adapters.filesystem.root", &adapters.filesystem.root),
+            ("adapters.git.root", &adapters.git.root),
+            ("adapters.terminal.root", &adapters.terminal.root),
+        ];
+        for (key, root) in roots {
+            if let Some(root) = root {
+                if !expand_home_dir(root).is_dir() {
+                    bail!("{}: {:?} is not a directory", key, root);
+                }
+            }
+        }
+        for command in adapters.terminal.allowed_commands.iter().flatten() {
+            if command.is_empty() || command.contains(char::is_whitespace) {
+                bail!("adapters.terminal.allowed_commands: {:?} is not a single command name", command);
+            }
+        }
+
+        for (tool, service) in &self.routing.tools {
+            if !ADAPTER_NAMES.contains(&service.as_str()) {
+                bail!("routing.tools.{}: unknown adapter {:?}", tool, service);
+            }
+            if !adapters.is_enabled(service) {
+                bail!("routing.tools.{}: adapter {:?} is disabled", tool, service);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AdaptersConfig {
+    fn is_enabled(&self, name: &str) -> bool {
+        match name {
+            "filesystem" => self.filesystem.enabled,
+            "git" => self.git.enabled,
+            "terminal" => self.terminal.enabled,
+            _ => false,
+        }
+    }
+
+    /// Directory the named adapter works in, if it runs at all
+    pub fn root_of(&self, name: &str) -> Option<PathBuf> {
+        if !self.is_enabled(name) {
+            return None;
+        }
+        let own = match name {
+            "filesystem" => &self.filesystem.root,
+            "git" => &self.git.root,
+            "terminal" => &self.terminal.root,
+            _ => return None,
+        };
+        own.as_ref().or(self.root.as_ref()).map(|root| expand_home_dir(root))
+    }
+
+    /// A terminal adapter working in `root` with the configured whitelist
+    pub fn terminal_adapter(&self, root: &Path) -> TerminalAdapter {
+        let mut terminal = TerminalAdapter::new(root);
+        if let Some(commands) = &self.terminal.allowed_commands {
+            terminal.set_allowed_commands(commands.clone());
+        }
+        terminal
+    }
+}
+
+impl RoutingConfig {
+    /// Replace the strategy and tool mappings of `router`
+    pub async fn apply(&self, router: &RequestRouter) {
+        let strategy = self.strategy.map_or(RoutingStrategy::FirstMatch, Into::into);
+        let mappings = self.tools.iter().map(|(tool, service)| (tool.clone(), service.clone())).collect();
+        router.replace_routes(strategy, mappings).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_and_validate() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(CONFIG_FILE_NAME);
+        let root = temp_dir.path().display();
+
+        std::fs::write(
+            &path,
+            format!(
+                r#"
+                [server]
+                log_level = "debug"
+                max_connections = 8
+
+                [auth]
+                require_token = true
+
+                [tls]
+                addr = "0.0.0.0:7443"
+                cert = "server.pem"
+                key = "server.key"
+
+                [backup]
+                interval_minutes = 30
+
+                [maintenance]
+                interval_minutes = 0
+
+                [adapters]
+                root = "{root}"
+
+                [adapters.git]
+                enabled = false
+
+                [adapters.terminal]
+                allowed_commands = ["ls", "cargo"]
+
+                [routing]
+                strategy = "broadcast"
+                tools = {{ execute = "terminal" }}
+                "#
+            ),
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.server.max_connections, Some(8));
+        assert_eq!(config.server.db_path, None);
+        assert_eq!(config.auth.require_token, Some(true));
+        assert_eq!(config.tls.addr, Some("0.0.0.0:7443".parse().unwrap()));
+        assert_eq!(config.backup.interval_minutes, Some(30));
+        assert_eq!(config.maintenance.interval_minutes, Some(0));
+        assert_eq!(config.routing.strategy, Some(Strategy::Broadcast));
+        assert_eq!(config.adapters.root_of("terminal"), Some(temp_dir.path().to_path_buf()));
+        assert_eq!(config.adapters.root_of("git"), None);
+        assert_eq!(config.adapters.root_of("filesystem"), Some(temp_dir.path().to_path_buf()));
+
+        // Unknown keys, bad values and dangling mappings are all refused
+        let invalid = [
+            "[server]\nport = 1",
+            "[server]\nlog_level = \"loud\"",
+            "[server]\nmax_connections = 0",
+            "[server]\nrequire_token = true",
+            "[backup]\nkeep = 0",
+            "[tls]\ncert = \"server.pem\"",
+            "[tls]\naddr = \"localhost\"",
+            "[adapters.terminal]\nallowed_commands = [\"rm -rf\"]",
+            "[adapters]\nroot = \"/does/not/exist\"",
+            "[routing]\nstrategy = \"random\"",
+            "[routing.tools]\nexecute = \"docker\"",
+            "[adapters.terminal]\nenabled = false\n[routing.tools]\nexecute = \"terminal\"",
+        ];
+        for text in invalid {
+            std::fs::write(&path, text).unwrap();
+            assert!(Config::load(&path).is_err(), "accepted {:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_discover_requires_an_explicit_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("missing.toml");
+        assert!(Config::discover(Some(&missing)).is_err());
+
+        std::fs::write(&missing, "").unwrap();
+        let (path, config) = Config::discover(Some(&missing)).unwrap().unwrap();
+        assert_eq!(path, missing);
+        assert_eq!(config, Config::default());
+    }
+}
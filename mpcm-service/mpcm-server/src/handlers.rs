@@ -1,23 +1,21 @@
-//! JSON-RPC request handlers for MPCM Server
+//! Request handlers for the TypeScript-compatible service dialect
 //! 
-//! This module implements all the MCP tool handlers for context management.
+//! This module implements the context management methods the TypeScript
+//! adapter sends as `ServiceRequest`s, over either storage back end.
 //! Each handler follows the JSON-RPC 2.0 specification.
 
 use anyhow::{anyhow, Result};
-use mpcm_core::storage::Storage;
+use mpcm_core::storage_v2::{ProjectUpdate, SearchFilter};
+use mpcm_core::{storage, storage_v2, MpcmError};
 use serde::Deserialize;
 use serde_json::{json, Value};
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing::{debug, error};
 
 /// JSON-RPC error codes
 mod error_codes {
-    pub const PARSE_ERROR: i32 = -32700;
     pub const INVALID_REQUEST: i32 = -32600;
     pub const METHOD_NOT_FOUND: i32 = -32601;
-    pub const INVALID_PARAMS: i32 = -32602;
-    pub const INTERNAL_ERROR: i32 = -32603;
 }
 
 /// Store context parameters
@@ -30,6 +28,7 @@ struct StoreContextParams {
     context_type: String,
     tags: Option<Vec<String>>,
     metadata: Option<Value>,
+    role_id: Option<String>,
 }
 
 /// Search context parameters
@@ -69,10 +68,19 @@ struct StoreProjectContextParams {
     metadata: Option<Value>,
 }
 
+/// Storage the service handlers read and write
+#[derive(Clone)]
+pub enum Backend {
+    /// The flat `contexts` table of the original server
+    V1(Arc<storage::Storage>),
+    /// The TypeScript-compatible schema
+    V2(Arc<storage_v2::Storage>),
+}
+
 /// Main request handler - routes to specific handlers based on method
 pub async fn handle_request(
     request: Value,
-    storage: Arc<RwLock<Storage>>,
+    backend: &Backend,
 ) -> Value {
     // Validate JSON-RPC request structure
     let id = request.get("id").cloned().unwrap_or(Value::Null);
@@ -88,17 +96,20 @@ pub async fn handle_request(
         }
     };
     
-    let params = request.get("params").cloned().unwrap_or(json!({}));
+    let params = match request.get("params") {
+        None | Some(Value::Null) => json!({}),
+        Some(params) => params.clone(),
+    };
     
     debug!("Handling request: method={}, id={:?}", method, id);
     
     // Route to appropriate handler
     let result = match method {
-        "store_context" => handle_store_context(params, storage).await,
-        "search_context" => handle_search_context(params, storage).await,
-        "get_project_context" => handle_get_project_context(params, storage).await,
-        "list_projects" => handle_list_projects(params, storage).await,
-        "store_project_context" => handle_store_project_context(params, storage).await,
+        "store_context" => handle_store_context(params, backend).await,
+        "search_context" => handle_search_context(params, backend).await,
+        "get_project_context" => handle_get_project_context(params, backend).await,
+        "list_projects" => handle_list_projects(params, backend).await,
+        "store_project_context" => handle_store_project_context(params, backend).await,
         _ => {
             return create_error_response(
                 id,
@@ -112,33 +123,51 @@ pub async fn handle_request(
         Ok(value) => create_success_response(id, value),
         Err(e) => {
             error!("Handler error for method {}: {}", method, e);
-            create_error_response(
-                id,
-                error_codes::INTERNAL_ERROR,
-                e.to_string(),
-            )
+            let (code, data) = mpcm_core::error_details(&e);
+            let mut response = create_error_response(id, code, e.to_string());
+            if let Some(data) = data {
+                response["error"]["data"] = data;
+            }
+            response
         }
     }
 }
 
 /// Handle store_context method
+///
+/// The v1 back end has nowhere to keep tags and metadata and drops them.
 async fn handle_store_context(
     params: Value,
-    storage: Arc<RwLock<Storage>>,
+    backend: &Backend,
 ) -> Result<Value> {
     let params: StoreContextParams = serde_json::from_value(params)
         .map_err(|e| anyhow!("Invalid parameters: {}", e))?;
     
-    // Create a Context object
-    let context = mpcm_core::Context::new(
-        &params.project_name,
-        &params.key,
-        &params.context_type,
-        &params.value,
-    );
-    
-    let storage = storage.read().await;
-    storage.store_context(&context).await?;
+    match backend {
+        Backend::V1(storage) => {
+            let context = mpcm_core::Context::new(
+                &params.project_name,
+                &params.key,
+                params.context_type.parse()?,
+                &params.value,
+            );
+            storage.store_context_as(&context, params.role_id.as_deref()).await?;
+        }
+        Backend::V2(storage) => {
+            storage
+                .store_context(
+                    &params.project_name,
+                    &params.key,
+                    &params.context_type,
+                    &params.value,
+                    params.tags,
+                    params.metadata,
+                    None,
+                    params.role_id,
+                )
+                .await?;
+        }
+    }
     
     Ok(json!({
         "success": true,
@@ -146,75 +175,137 @@ async fn handle_store_context(
     }))
 }
 
-/// Handle search_context method (simplified - returns empty for now)
+/// Handle search_context method
+///
+/// The v1 back end matches substrings and ignores tags and `since`.
 async fn handle_search_context(
     params: Value,
-    _storage: Arc<RwLock<Storage>>,
+    backend: &Backend,
 ) -> Result<Value> {
-    let _params: SearchContextParams = serde_json::from_value(params)
+    let params: SearchContextParams = serde_json::from_value(params)
         .map_err(|e| anyhow!("Invalid parameters: {}", e))?;
     
-    // TODO: Implement search functionality
-    // For now, return empty results
+    let entries = match backend {
+        Backend::V1(storage) => {
+            let contexts = storage
+                .search_contexts(
+                    params.project_name.as_deref(),
+                    params.query.as_deref(),
+                    params.context_type.as_deref(),
+                    params.limit.map_or(-1, i64::from),
+                )
+                .await?;
+            json!(contexts)
+        }
+        Backend::V2(storage) => {
+            let mut filter = SearchFilter::new();
+            filter.project_name = params.project_name;
+            filter.query = params.query;
+            filter.types = params.context_type.into_iter().collect();
+            filter.tags = params.tags.unwrap_or_default();
+            filter.since = params.since;
+            filter.limit = params.limit.map(i64::from);
+            json!(storage.search_context(&filter).await?)
+        }
+    };
+    
     Ok(json!({
-        "entries": [],
-        "count": 0
+        "count": entries.as_array().map_or(0, Vec::len),
+        "entries": entries,
     }))
 }
 
 /// Handle get_project_context method
 async fn handle_get_project_context(
     params: Value,
-    storage: Arc<RwLock<Storage>>,
+    backend: &Backend,
 ) -> Result<Value> {
     let params: GetProjectContextParams = serde_json::from_value(params)
         .map_err(|e| anyhow!("Invalid parameters: {}", e))?;
     
-    let storage = storage.read().await;
+    let (project, contexts) = match backend {
+        Backend::V1(storage) => {
+            // Projects exist only through their entries
+            let contexts = storage
+                .search_contexts(Some(&params.project_name), None, None, -1)
+                .await?;
+            let created_at = contexts
+                .iter()
+                .map(|c| *c.created_at())
+                .min()
+                .ok_or_else(|| MpcmError::ProjectNotFound { project: params.project_name.clone() })?;
+            let project = json!({
+                "name": params.project_name,
+                "status": "active",
+                "created_at": created_at.to_rfc3339(),
+            });
+            (project, json!(contexts))
+        }
+        Backend::V2(storage) => {
+            let result = storage
+                .get_project_context(&params.project_name, params.system_specific)
+                .await?;
+            (json!(result.project), json!(result.entries))
+        }
+    };
     
-    // For now, we'll return a simple response
-    // TODO: Implement full project context retrieval
     Ok(json!({
-        "project": {
-            "name": params.project_name,
-            "description": null,
-            "repository_url": null,
-            "local_directory": null,
-            "status": "active",
-            "tags": [],
-            "metadata": null,
-            "created_at": chrono::Utc::now().to_rfc3339(),
-            "updated_at": chrono::Utc::now().to_rfc3339(),
-        },
-        "contexts": [],
-        "context_count": 0
+        "project": project,
+        "context_count": contexts.as_array().map_or(0, Vec::len),
+        "contexts": contexts,
     }))
 }
 
-/// Handle list_projects method (simplified)
+/// Handle list_projects method
 async fn handle_list_projects(
     params: Value,
-    _storage: Arc<RwLock<Storage>>,
+    backend: &Backend,
 ) -> Result<Value> {
-    let _params: ListProjectsParams = serde_json::from_value(params)
+    let params: ListProjectsParams = serde_json::from_value(params)
         .map_err(|e| anyhow!("Invalid parameters: {}", e))?;
     
-    // TODO: Implement project listing
+    let projects = match backend {
+        Backend::V1(storage) => storage
+            .list_projects()
+            .await?
+            .into_iter()
+            .map(|name| json!({ "name": name, "status": "active" }))
+            .collect(),
+        Backend::V2(storage) => json!(storage.list_projects(params.include_archived).await?),
+    };
+    
     Ok(json!({
-        "projects": [],
-        "count": 0
+        "count": projects.as_array().map_or(0, Vec::len),
+        "projects": projects,
     }))
 }
 
-/// Handle store_project_context method (simplified)
+/// Handle store_project_context method
 async fn handle_store_project_context(
     params: Value,
-    _storage: Arc<RwLock<Storage>>,
+    backend: &Backend,
 ) -> Result<Value> {
     let params: StoreProjectContextParams = serde_json::from_value(params)
         .map_err(|e| anyhow!("Invalid parameters: {}", e))?;
     
-    // TODO: Implement project storage
+    let Backend::V2(storage) = backend else {
+        return Err(MpcmError::MethodNotFound(
+            "store_project_context (requires the v2 storage back end)".to_string(),
+        )
+        .into());
+    };
+    
+    let update = ProjectUpdate {
+        description: params.description,
+        status: params.status,
+        repository_url: params.repository_url,
+        local_directory: params.local_directory,
+        tags: params.tags,
+        metadata: params.metadata,
+        primary_system_id: None,
+    };
+    storage.store_project_context(&params.project_name, &update).await?;
+    
     Ok(json!({
         "success": true,
         "message": format!("Stored project context for '{}'", params.project_name)
@@ -291,8 +382,12 @@ mod tests {
         assert_eq!(params.tags.unwrap().len(), 2);
     }
     
-    #[test]
-    fn test_invalid_method_routing() {
+    #[tokio::test]
+    async fn test_invalid_method_routing() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = storage_v2::Storage::new(temp_dir.path().join("routing.db")).await.unwrap();
+        let backend = Backend::V2(Arc::new(storage));
+        
         // Test that invalid methods return METHOD_NOT_FOUND error
         let request = json!({
             "jsonrpc": "2.0",
@@ -301,7 +396,8 @@ mod tests {
             "id": 1
         });
         
-        // We'll test the response has error code -32601
-        // (This would need the actual handle_request to be testable synchronously)
+        let response = handle_request(request, &backend).await;
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["error"]["code"], -32601);
     }
 }
@@ -1,27 +1,15 @@
 //! JSON-RPC request handlers for MPCM Server (v2)
 //! Compatible with TypeScript MPCM implementation
 
-use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::sync::Arc;
-use tracing::{debug, error, info};
+use tracing::{debug, info};
 
-use mpcm_core::storage_v2::{Storage, StorageResult, ContextEntry, Project};
-
-/// JSON-RPC error codes
-pub mod error_codes {
-    pub const PARSE_ERROR: i32 = -32700;
-    pub const INVALID_REQUEST: i32 = -32600;
-    pub const METHOD_NOT_FOUND: i32 = -32601;
-    pub const INVALID_PARAMS: i32 = -32602;
-    pub const INTERNAL_ERROR: i32 = -32603;
-    
-    // Custom error codes
-    pub const CONTEXT_NOT_FOUND: i32 = 1001;
-    pub const PROJECT_NOT_FOUND: i32 = 1002;
-    pub const DATABASE_ERROR: i32 = 1003;
-}
+use crate::auth::Role;
+use mpcm_core::{ContextType, CustomContextType, MpcmError};
+use mpcm_core::storage_v2::{ConflictPolicy, HistoryFilter, ProjectArchive, RetentionPolicy, ProjectUpdate, SearchFilter, SortOrder, Storage, TagMatch};
 
 /// Store context parameters
 #[derive(Debug, Deserialize)]
@@ -44,9 +32,44 @@ pub struct SearchContextParams {
     query: Option<String>,
     #[serde(rename = "type")]
     context_type: Option<String>,
+    #[serde(default)]
+    types: Vec<String>,
     tags: Option<Vec<String>>,
+    #[serde(default)]
+    tag_match: TagMatch,
+    role_id: Option<String>,
     since: Option<String>,
-    limit: Option<i32>,
+    until: Option<String>,
+    is_system_specific: Option<bool>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+    #[serde(default)]
+    sort: SortOrder,
+}
+
+impl From<SearchContextParams> for SearchFilter {
+    fn from(params: SearchContextParams) -> Self {
+        let mut types = params.types;
+        if let Some(ct) = params.context_type {
+            types.push(ct);
+        }
+        
+        SearchFilter {
+            project_name: params.project_name,
+            query: params.query,
+            types,
+            tags: params.tags.unwrap_or_default(),
+            tag_match: params.tag_match,
+            role_id: params.role_id,
+            since: params.since,
+            until: params.until,
+            is_system_specific: params.is_system_specific,
+            offset: params.offset,
+            limit: params.limit,
+            sort: params.sort,
+            tenant: None,
+        }
+    }
 }
 
 /// Get project context parameters
@@ -70,6 +93,143 @@ pub struct UpdateProjectStatusParams {
     note: Option<String>,
 }
 
+/// Store project context parameters
+#[derive(Debug, Deserialize)]
+pub struct StoreProjectContextParams {
+    project_name: String,
+    #[serde(flatten)]
+    update: ProjectUpdate,
+}
+
+/// Get recent updates parameters
+#[derive(Debug, Deserialize)]
+pub struct GetRecentUpdatesParams {
+    project_name: Option<String>,
+    entity_type: Option<String>,
+    action: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    limit: Option<i64>,
+}
+
+impl From<GetRecentUpdatesParams> for HistoryFilter {
+    fn from(params: GetRecentUpdatesParams) -> Self {
+        HistoryFilter {
+            project_name: params.project_name,
+            entity_type: params.entity_type,
+            action: params.action,
+            since: params.since,
+            until: params.until,
+            limit: params.limit,
+            tenant: None,
+        }
+    }
+}
+
+/// Export project parameters
+#[derive(Debug, Deserialize)]
+pub struct ExportProjectParams {
+    project_name: String,
+}
+
+/// Import project parameters
+#[derive(Debug, Deserialize)]
+pub struct ImportProjectParams {
+    archive: ProjectArchive,
+    #[serde(default)]
+    policy: ConflictPolicy,
+}
+
+/// Retention dry run parameters
+#[derive(Debug, Default, Deserialize)]
+pub struct RetentionDryRunParams {
+    /// Policy to evaluate instead of the configured one
+    policy: Option<RetentionPolicy>,
+}
+
+/// Unregister context type parameters
+#[derive(Debug, Deserialize)]
+pub struct UnregisterContextTypeParams {
+    name: String,
+}
+
+/// Context revision parameters
+#[derive(Debug, Deserialize)]
+pub struct ContextRevisionsParams {
+    project_name: String,
+    key: String,
+}
+
+/// Diff context revisions parameters
+#[derive(Debug, Deserialize)]
+pub struct DiffContextRevisionsParams {
+    project_name: String,
+    key: String,
+    from: i64,
+    to: i64,
+}
+
+/// Restore context revision parameters
+#[derive(Debug, Deserialize)]
+pub struct RestoreContextRevisionParams {
+    project_name: String,
+    key: String,
+    revision: i64,
+    role_id: Option<String>,
+}
+
+/// Delete context parameters
+#[derive(Debug, Deserialize)]
+pub struct DeleteContextParams {
+    project_name: String,
+    key: String,
+    #[serde(default)]
+    hard: bool,
+}
+
+/// Delete project parameters
+#[derive(Debug, Deserialize)]
+pub struct DeleteProjectParams {
+    project_name: String,
+    #[serde(default)]
+    hard: bool,
+}
+
+/// Restore deleted data parameters
+#[derive(Debug, Deserialize)]
+pub struct RestoreParams {
+    project_name: String,
+    key: Option<String>,
+}
+
+/// Purge parameters
+#[derive(Debug, Deserialize)]
+pub struct PurgeParams {
+    project_name: Option<String>,
+    older_than_days: Option<i64>,
+}
+
+/// Issue token parameters
+#[derive(Debug, Deserialize)]
+pub struct IssueTokenParams {
+    name: String,
+    /// Tenant the token is scoped to; unscoped tokens see every project
+    tenant: Option<String>,
+    role: Role,
+}
+
+/// List tokens parameters
+#[derive(Debug, Default, Deserialize)]
+pub struct ListTokensParams {
+    tenant: Option<String>,
+}
+
+/// Revoke token parameters
+#[derive(Debug, Deserialize)]
+pub struct RevokeTokenParams {
+    id: i64,
+}
+
 /// Handle store_context request
 pub async fn handle_store_context(
     storage: Arc<Storage>,
@@ -98,18 +258,14 @@ pub async fn handle_store_context(
 pub async fn handle_search_context(
     storage: Arc<Storage>,
     params: SearchContextParams,
+    tenant: Option<&str>,
 ) -> Result<Value> {
     debug!("Searching context: {:?}", params);
     
+    let mut filter = SearchFilter::from(params);
+    filter.tenant = tenant.map(str::to_string);
     let entries = storage
-        .search_context(
-            params.project_name.as_deref(),
-            params.query.as_deref(),
-            params.context_type.as_deref(),
-            params.tags,
-            params.since.as_deref(),
-            params.limit,
-        )
+        .search_context(&filter)
         .await?;
     
     info!("Found {} context entries", entries.len());
@@ -138,12 +294,17 @@ pub async fn handle_get_project_context(
 pub async fn handle_list_projects(
     storage: Arc<Storage>,
     params: ListProjectsParams,
+    tenant: Option<&str>,
 ) -> Result<Value> {
     debug!("Listing projects: include_archived={:?}", params.include_archived);
     
-    let projects = storage
+    let mut projects = storage
         .list_projects(params.include_archived)
         .await?;
+    if let Some(tenant) = tenant {
+        let owned = storage.tenant_projects(tenant).await?;
+        projects.retain(|p| owned.contains(&p.name));
+    }
     
     info!("Found {} projects", projects.len());
     Ok(json!(projects))
@@ -168,33 +329,431 @@ pub async fn handle_update_project_status(
     Ok(json!(result))
 }
 
+/// Handle store_project_context request
+pub async fn handle_store_project_context(
+    storage: Arc<Storage>,
+    params: StoreProjectContextParams,
+) -> Result<Value> {
+    debug!("Storing project context: {}", params.project_name);
+    
+    let project = storage
+        .store_project_context(&params.project_name, &params.update)
+        .await?;
+    
+    Ok(json!(project))
+}
+
+/// Handle get_recent_updates request
+pub async fn handle_get_recent_updates(
+    storage: Arc<Storage>,
+    params: GetRecentUpdatesParams,
+    tenant: Option<&str>,
+) -> Result<Value> {
+    debug!("Getting recent updates: {:?}", params);
+    
+    let mut filter = HistoryFilter::from(params);
+    filter.tenant = tenant.map(str::to_string);
+    let updates = storage
+        .get_recent_updates(&filter)
+        .await?;
+    
+    info!("Found {} updates", updates.len());
+    Ok(json!(updates))
+}
+
+/// Handle export_project request
+pub async fn handle_export_project(
+    storage: Arc<Storage>,
+    params: ExportProjectParams,
+) -> Result<Value> {
+    debug!("Exporting project: {}", params.project_name);
+    
+    let archive = storage.export_project(&params.project_name).await?;
+    
+    Ok(json!(archive))
+}
+
+/// Handle import_project request
+pub async fn handle_import_project(
+    storage: Arc<Storage>,
+    params: ImportProjectParams,
+    tenant: Option<&str>,
+) -> Result<Value> {
+    debug!("Importing project {} ({:?})", params.archive.project.name, params.policy);
+    
+    let result = storage
+        .import_project_as(&params.archive, params.policy, tenant)
+        .await?;
+    
+    info!("Project {} imported", result.project);
+    Ok(json!(result))
+}
+
+/// Handle retention_dry_run request
+pub async fn handle_retention_dry_run(
+    storage: Arc<Storage>,
+    params: RetentionDryRunParams,
+) -> Result<Value> {
+    debug!("Evaluating retention policy");
+    
+    let policy = params.policy.as_ref().unwrap_or(storage.retention_policy());
+    let report = storage.apply_retention(policy, true).await?;
+    
+    Ok(json!(report))
+}
+
+/// Handle list_systems request
+pub async fn handle_list_systems(storage: Arc<Storage>) -> Result<Value> {
+    debug!("Listing systems");
+    
+    let systems = storage.list_systems().await?;
+    
+    Ok(json!({
+        "current_system_id": storage.current_system_id(),
+        "systems": systems,
+    }))
+}
+
+/// Handle register_context_type request
+pub async fn handle_register_context_type(
+    storage: Arc<Storage>,
+    params: CustomContextType,
+) -> Result<Value> {
+    info!("Registering context type: {}", params.name);
+    
+    let context_type = storage.register_context_type(&params).await?;
+    
+    Ok(json!(context_type))
+}
+
+/// Handle list_context_types request
+pub async fn handle_list_context_types(storage: Arc<Storage>) -> Result<Value> {
+    debug!("Listing context types");
+    
+    let custom = storage.list_context_types().await?;
+    
+    Ok(json!({
+        "builtin": ContextType::BUILTIN.map(|t| t.to_string()),
+        "custom": custom,
+    }))
+}
+
+/// Handle unregister_context_type request
+pub async fn handle_unregister_context_type(
+    storage: Arc<Storage>,
+    params: UnregisterContextTypeParams,
+) -> Result<Value> {
+    info!("Unregistering context type: {}", params.name);
+    
+    storage.unregister_context_type(&params.name).await?;
+    
+    Ok(json!({
+        "success": true,
+        "message": format!("Unregistered context type '{}'", params.name),
+    }))
+}
+
+/// Handle list_context_revisions request
+pub async fn handle_list_context_revisions(
+    storage: Arc<Storage>,
+    params: ContextRevisionsParams,
+) -> Result<Value> {
+    debug!("Listing revisions: project={}, key={}", params.project_name, params.key);
+    
+    let revisions = storage
+        .list_revisions(&params.project_name, &params.key)
+        .await?;
+    
+    info!("Found {} revisions for {}", revisions.len(), params.key);
+    Ok(json!(revisions))
+}
+
+/// Handle diff_context_revisions request
+pub async fn handle_diff_context_revisions(
+    storage: Arc<Storage>,
+    params: DiffContextRevisionsParams,
+) -> Result<Value> {
+    debug!("Diffing revisions of {}: {} -> {}", params.key, params.from, params.to);
+    
+    let diff = storage
+        .diff_revisions(&params.project_name, &params.key, params.from, params.to)
+        .await?;
+    
+    Ok(json!(diff))
+}
+
+/// Handle restore_context_revision request
+pub async fn handle_restore_context_revision(
+    storage: Arc<Storage>,
+    params: RestoreContextRevisionParams,
+) -> Result<Value> {
+    debug!("Restoring {} to revision {}", params.key, params.revision);
+    
+    let result = storage
+        .restore_revision(&params.project_name, &params.key, params.revision, params.role_id)
+        .await?;
+    
+    info!("Context {} restored to revision {}", params.key, params.revision);
+    Ok(json!(result))
+}
+
+/// Handle delete_context request
+pub async fn handle_delete_context(
+    storage: Arc<Storage>,
+    params: DeleteContextParams,
+) -> Result<Value> {
+    debug!("Deleting context {} (hard: {})", params.key, params.hard);
+    
+    let result = storage
+        .delete_context(&params.project_name, &params.key, params.hard)
+        .await?;
+    
+    info!("Context {} deleted from project {}", params.key, params.project_name);
+    Ok(json!(result))
+}
+
+/// Handle delete_project request
+pub async fn handle_delete_project(
+    storage: Arc<Storage>,
+    params: DeleteProjectParams,
+) -> Result<Value> {
+    debug!("Deleting project {} (hard: {})", params.project_name, params.hard);
+    
+    let result = storage
+        .delete_project(&params.project_name, params.hard)
+        .await?;
+    
+    info!("Project {} deleted", params.project_name);
+    Ok(json!(result))
+}
+
+/// Handle restore request
+pub async fn handle_restore(
+    storage: Arc<Storage>,
+    params: RestoreParams,
+) -> Result<Value> {
+    debug!("Restoring {:?} in project {}", params.key, params.project_name);
+    
+    let result = storage
+        .restore(&params.project_name, params.key.as_deref())
+        .await?;
+    
+    info!("Restored deleted data in project {}", params.project_name);
+    Ok(json!(result))
+}
+
+/// Handle purge request
+pub async fn handle_purge(
+    storage: Arc<Storage>,
+    params: PurgeParams,
+) -> Result<Value> {
+    debug!("Purging tombstones for {:?}", params.project_name);
+    
+    let result = storage
+        .purge(params.project_name.as_deref(), params.older_than_days)
+        .await?;
+    
+    Ok(json!(result))
+}
+
+/// Handle issue_token request
+///
+/// Tenant callers can only issue tokens for their own tenant.
+pub async fn handle_issue_token(
+    storage: Arc<Storage>,
+    params: IssueTokenParams,
+    tenant: Option<&str>,
+) -> Result<Value> {
+    let scope = match (tenant, params.tenant.as_deref()) {
+        (Some(own), Some(requested)) if own != requested => {
+            return Err(MpcmError::InvalidArgument(format!("Cannot issue tokens for tenant '{}'", requested)).into());
+        }
+        (Some(own), _) => Some(own),
+        (None, requested) => requested,
+    };
+    info!("Issuing {} token '{}' for tenant {:?}", params.role.as_str(), params.name, scope);
+    
+    let issued = storage.issue_token(&params.name, scope, params.role.as_str()).await?;
+    
+    Ok(json!(issued))
+}
+
+/// Handle list_tokens request
+pub async fn handle_list_tokens(
+    storage: Arc<Storage>,
+    params: ListTokensParams,
+    tenant: Option<&str>,
+) -> Result<Value> {
+    debug!("Listing tokens for tenant {:?}", params.tenant);
+    
+    let tokens = storage.list_tokens(tenant.or(params.tenant.as_deref())).await?;
+    
+    Ok(json!(tokens))
+}
+
+/// Handle revoke_token request
+pub async fn handle_revoke_token(
+    storage: Arc<Storage>,
+    params: RevokeTokenParams,
+    tenant: Option<&str>,
+) -> Result<Value> {
+    if let Some(tenant) = tenant {
+        let own = storage.list_tokens(Some(tenant)).await?;
+        if !own.iter().any(|t| t.id == params.id) {
+            return Err(MpcmError::InvalidArgument(format!("No active token with id {}", params.id)).into());
+        }
+    }
+    
+    let token = storage.revoke_token(params.id).await?;
+    
+    Ok(json!(token))
+}
+
+/// Project a request works on, if any
+pub(crate) fn target_project(params: &Value) -> Option<String> {
+    params
+        .get("project_name")
+        .or_else(|| {
+            // Imports name their project inside the archive
+            params.pointer("/archive/project/name")
+        })
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+/// Deserialize request parameters, reporting failures as invalid params
+///
+/// Absent or null params read as `{}`, so methods whose parameters are all
+/// optional can be called without any.
+pub(crate) fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params)
+        .map_err(|e| MpcmError::InvalidArgument(format!("Invalid parameters: {}", e)).into())
+}
+
+/// Methods that create the project they name if it does not exist
+const CREATING_METHODS: [&str; 2] = ["store_context", "store_project_context"];
+
 /// Main request handler
+///
+/// With a `tenant`, projects owned by anyone else look like they do not
+/// exist, listings only cover the tenant's projects, and projects the
+/// tenant creates are theirs from the start: writes that may create one
+/// claim it before they run, and imports create it already owned.
 pub async fn handle_request(
     method: &str,
     params: Value,
     storage: Arc<Storage>,
+    tenant: Option<&str>,
+) -> Result<Value> {
+    if let (Some(tenant), Some(project)) = (tenant, target_project(&params)) {
+        if CREATING_METHODS.contains(&method) {
+            storage.claim_project(&project, tenant).await?;
+        } else {
+            storage.check_project_access(&project, tenant).await?;
+        }
+    }
+    
+    dispatch(method, params, storage, tenant).await
+}
+
+async fn dispatch(
+    method: &str,
+    params: Value,
+    storage: Arc<Storage>,
+    tenant: Option<&str>,
 ) -> Result<Value> {
     match method {
         "store_context" => {
-            let params: StoreContextParams = serde_json::from_value(params)?;
+            let params: StoreContextParams = parse_params(params)?;
             handle_store_context(storage, params).await
         }
         "search_context" => {
-            let params: SearchContextParams = serde_json::from_value(params)?;
-            handle_search_context(storage, params).await
+            let params: SearchContextParams = parse_params(params)?;
+            handle_search_context(storage, params, tenant).await
         }
         "get_project_context" => {
-            let params: GetProjectContextParams = serde_json::from_value(params)?;
+            let params: GetProjectContextParams = parse_params(params)?;
             handle_get_project_context(storage, params).await
         }
         "list_projects" => {
-            let params: ListProjectsParams = serde_json::from_value(params)?;
-            handle_list_projects(storage, params).await
+            let params: ListProjectsParams = parse_params(params)?;
+            handle_list_projects(storage, params, tenant).await
         }
         "update_project_status" => {
-            let params: UpdateProjectStatusParams = serde_json::from_value(params)?;
+            let params: UpdateProjectStatusParams = parse_params(params)?;
             handle_update_project_status(storage, params).await
         }
-        _ => Err(anyhow!("Method not found: {}", method)),
+        "store_project_context" => {
+            let params: StoreProjectContextParams = parse_params(params)?;
+            handle_store_project_context(storage, params).await
+        }
+        "get_recent_updates" => {
+            let params: GetRecentUpdatesParams = parse_params(params)?;
+            handle_get_recent_updates(storage, params, tenant).await
+        }
+        "export_project" => {
+            let params: ExportProjectParams = parse_params(params)?;
+            handle_export_project(storage, params).await
+        }
+        "import_project" => {
+            let params: ImportProjectParams = parse_params(params)?;
+            handle_import_project(storage, params, tenant).await
+        }
+        "retention_dry_run" => {
+            let params: RetentionDryRunParams = parse_params(params)?;
+            handle_retention_dry_run(storage, params).await
+        }
+        "list_systems" => handle_list_systems(storage).await,
+        "register_context_type" => {
+            let params: CustomContextType = parse_params(params)?;
+            handle_register_context_type(storage, params).await
+        }
+        "list_context_types" => handle_list_context_types(storage).await,
+        "unregister_context_type" => {
+            let params: UnregisterContextTypeParams = parse_params(params)?;
+            handle_unregister_context_type(storage, params).await
+        }
+        "list_context_revisions" => {
+            let params: ContextRevisionsParams = parse_params(params)?;
+            handle_list_context_revisions(storage, params).await
+        }
+        "diff_context_revisions" => {
+            let params: DiffContextRevisionsParams = parse_params(params)?;
+            handle_diff_context_revisions(storage, params).await
+        }
+        "restore_context_revision" => {
+            let params: RestoreContextRevisionParams = parse_params(params)?;
+            handle_restore_context_revision(storage, params).await
+        }
+        "delete_context" => {
+            let params: DeleteContextParams = parse_params(params)?;
+            handle_delete_context(storage, params).await
+        }
+        "delete_project" => {
+            let params: DeleteProjectParams = parse_params(params)?;
+            handle_delete_project(storage, params).await
+        }
+        "restore" => {
+            let params: RestoreParams = parse_params(params)?;
+            handle_restore(storage, params).await
+        }
+        "purge" => {
+            let params: PurgeParams = parse_params(params)?;
+            handle_purge(storage, params).await
+        }
+        "issue_token" => {
+            let params: IssueTokenParams = parse_params(params)?;
+            handle_issue_token(storage, params, tenant).await
+        }
+        "list_tokens" => {
+            let params: ListTokensParams = parse_params(params)?;
+            handle_list_tokens(storage, params, tenant).await
+        }
+        "revoke_token" => {
+            let params: RevokeTokenParams = parse_params(params)?;
+            handle_revoke_token(storage, params, tenant).await
+        }
+        _ => Err(MpcmError::MethodNotFound(method.to_string()).into()),
     }
 }
@@ -0,0 +1,627 @@
+//! HTTP + Server-Sent Events transport
+//!
+//! Clients POST JSON-RPC requests to `/mcp`. A session starts with
+//! `initialize` (or by opening the SSE stream) and is named by the
+//! `Mcp-Session-Id` header. While a session has an open stream at
+//! `/mcp/sse`, its responses and any server notifications arrive there as
+//! `message` events and the POST is answered with 202; without a stream
+//! the response comes back in the POST body. Every write the session's
+//! caller could read about is announced on the stream as a
+//! `notifications/context_changed` message.
+//!
+//! An `Authorization: Bearer` token on any request authenticates it and the
+//! session it belongs to; later requests in the session reuse that identity.
+
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response as HttpResponse};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::task::AbortHandle;
+use tracing::{debug, info, warn};
+
+use crate::auth::Identity;
+use crate::mcp::McpServer;
+use crate::protocol::{ErrorResponse, Response};
+use crate::server_v2::process_request;
+use crate::shutdown::Shutdown;
+
+/// Header naming the session a request belongs to
+pub const SESSION_HEADER: &str = "mcp-session-id";
+
+/// Sessions idle for longer than this are dropped
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Messages queued for a stream before senders fall back to inline replies
+const STREAM_BUFFER: usize = 64;
+
+/// An HTTP client session
+struct Session {
+    /// Open SSE stream, if any
+    stream: Option<mpsc::Sender<Value>>,
+    /// Who the session authenticated as
+    caller: Identity,
+    last_seen: Instant,
+    /// Task pushing change notifications to the stream
+    changes: AbortHandle,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.changes.abort();
+    }
+}
+
+/// Shared state of the HTTP listener
+#[derive(Clone)]
+pub struct HttpState {
+    server: Arc<McpServer>,
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+}
+
+impl HttpState {
+    pub fn new(server: Arc<McpServer>) -> Self {
+        Self {
+            server,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Start a session for `caller`, dropping any that have gone idle
+    async fn create_session(&self, caller: Identity) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        let changes = self.forward_changes(id.clone());
+        let mut sessions = self.sessions.lock().await;
+        sessions.retain(|_, s| s.last_seen.elapsed() < SESSION_IDLE_TIMEOUT);
+        sessions.insert(
+            id.clone(),
+            Session {
+                stream: None,
+                caller,
+                last_seen: Instant::now(),
+                changes,
+            },
+        );
+        info!("HTTP session {} started", id);
+        id
+    }
+
+    /// Announce writes the session's current caller may see on its stream
+    fn forward_changes(&self, id: String) -> AbortHandle {
+        let state = self.clone();
+        let mut changes = self.server.subscribe_changes();
+        tokio::spawn(async move {
+            loop {
+                let event = match changes.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("HTTP session {} missed {} change events", id, missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let caller = match state.sessions.lock().await.get(&id) {
+                    Some(session) => session.caller.clone(),
+                    None => break,
+                };
+                if !caller.can_access(event.tenant.as_deref()) {
+                    continue;
+                }
+                // Sessions without a stream have nowhere to hear about it
+                state
+                    .send(
+                        &id,
+                        json!({
+                            "jsonrpc": "2.0",
+                            "method": "notifications/context_changed",
+                            "params": {
+                                "method": event.method,
+                                "project_name": event.project_name,
+                                "key": event.key,
+                            },
+                        }),
+                    )
+                    .await;
+            }
+        })
+        .abort_handle()
+    }
+
+    /// Mark a session as active and return its caller; `None` if it does not exist
+    async fn touch(&self, id: &str) -> Option<Identity> {
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions.get_mut(id)?;
+        session.last_seen = Instant::now();
+        Some(session.caller.clone())
+    }
+
+    /// Remember who a session is authenticated as
+    async fn set_caller(&self, id: &str, caller: Identity) {
+        if let Some(session) = self.sessions.lock().await.get_mut(id) {
+            session.caller = caller;
+        }
+    }
+
+    /// Queue a message on the session's stream; false if it has none
+    pub async fn send(&self, id: &str, message: Value) -> bool {
+        let sender = match self.sessions.lock().await.get_mut(id) {
+            Some(session) => session.stream.clone(),
+            None => None,
+        };
+        let Some(sender) = sender else {
+            return false;
+        };
+
+        if sender.send(message).await.is_ok() {
+            return true;
+        }
+        // The client went away; forget the stream so replies go inline
+        if let Some(session) = self.sessions.lock().await.get_mut(id) {
+            session.stream = None;
+        }
+        false
+    }
+}
+
+/// Routes of the HTTP transport
+pub fn router(state: HttpState) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/mcp", post(post_message).delete(delete_session))
+        .route("/mcp/sse", get(open_stream))
+        .route("/sse", get(open_stream))
+        .with_state(state)
+}
+
+/// Serve the HTTP transport on `addr`
+///
+/// On shutdown open SSE streams are ended and requests already received
+/// are answered before this returns.
+pub async fn run_http(addr: SocketAddr, server: Arc<McpServer>, shutdown: Shutdown) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("MPCM Server listening on http://{}", listener.local_addr()?);
+
+    let state = HttpState::new(server);
+    let sessions = state.sessions.clone();
+    let signal = shutdown.clone();
+    shutdown
+        .bounded(async move {
+            axum::serve(listener, router(state))
+                .with_graceful_shutdown(async move {
+                    signal.triggered().await;
+                    // Dropping the senders ends the streams
+                    sessions.lock().await.clear();
+                })
+                .await?;
+            Ok(())
+        })
+        .await
+}
+
+async fn health() -> Json<Value> {
+    Json(json!({
+        "status": "healthy",
+        "transport": "http/sse",
+        "version": env!("CARGO_PKG_VERSION"),
+    }))
+}
+
+/// Session query parameters, for clients that cannot set headers
+#[derive(Debug, Default, Deserialize)]
+struct SessionQuery {
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
+}
+
+fn session_id(headers: &HeaderMap, query: &SessionQuery) -> Option<String> {
+    headers
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| query.session_id.clone())
+}
+
+fn unknown_session() -> HttpResponse {
+    (StatusCode::NOT_FOUND, Json(json!({ "error": "Unknown session" }))).into_response()
+}
+
+/// Identity from the `Authorization` header, if one was sent
+async fn bearer_identity(
+    server: &McpServer,
+    headers: &HeaderMap,
+) -> std::result::Result<Option<Identity>, HttpResponse> {
+    let Some(header) = headers.get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+    match server.authenticate_bearer(header.to_str().ok()).await {
+        Ok(caller) => Ok(Some(caller)),
+        Err(e) => {
+            let response = Response::error(Value::Null, ErrorResponse::from_error(&e));
+            Err((StatusCode::UNAUTHORIZED, Json(response)).into_response())
+        }
+    }
+}
+
+async fn post_message(
+    State(state): State<HttpState>,
+    Query(query): Query<SessionQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> HttpResponse {
+    let Ok(body) = std::str::from_utf8(&body) else {
+        return (StatusCode::BAD_REQUEST, "Request body is not UTF-8").into_response();
+    };
+
+    let bearer = match bearer_identity(&state.server, &headers).await {
+        Ok(bearer) => bearer,
+        Err(response) => return response,
+    };
+
+    let mut session = session_id(&headers, &query);
+    let mut caller = match &session {
+        Some(id) => match state.touch(id).await {
+            Some(session_caller) => bearer.unwrap_or(session_caller),
+            None => return unknown_session(),
+        },
+        None => bearer.unwrap_or_else(Identity::anonymous),
+    };
+    if session.is_none() && serde_json::from_str::<Value>(body).is_ok_and(|v| v["method"] == "initialize") {
+        session = Some(state.create_session(caller.clone()).await);
+    }
+
+    let response = process_request(body, &state.server, &mut caller).await;
+    if let Some(id) = &session {
+        state.set_caller(id, caller).await;
+    }
+
+    let mut http_response = match response {
+        None => StatusCode::ACCEPTED.into_response(),
+        Some(response) => {
+            let message = serde_json::to_value(&response).unwrap_or(Value::Null);
+            match &session {
+                Some(id) if state.send(id, message.clone()).await => {
+                    debug!("Delivered response on stream for session {}", id);
+                    StatusCode::ACCEPTED.into_response()
+                }
+                _ => Json(message).into_response(),
+            }
+        }
+    };
+
+    if let Some(value) = session.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        http_response.headers_mut().insert(SESSION_HEADER, value);
+    }
+    http_response
+}
+
+async fn delete_session(
+    State(state): State<HttpState>,
+    Query(query): Query<SessionQuery>,
+    headers: HeaderMap,
+) -> HttpResponse {
+    let Some(id) = session_id(&headers, &query) else {
+        return (StatusCode::BAD_REQUEST, "Missing session id").into_response();
+    };
+    // Dropping the sender ends the session's event stream
+    match state.sessions.lock().await.remove(&id) {
+        Some(_) => {
+            info!("HTTP session {} ended", id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        None => unknown_session(),
+    }
+}
+
+async fn open_stream(
+    State(state): State<HttpState>,
+    Query(query): Query<SessionQuery>,
+    headers: HeaderMap,
+) -> HttpResponse {
+    let bearer = match bearer_identity(&state.server, &headers).await {
+        Ok(bearer) => bearer,
+        Err(response) => return response,
+    };
+    let id = match session_id(&headers, &query) {
+        Some(id) if state.touch(&id).await.is_some() => id,
+        Some(_) => return unknown_session(),
+        None => state.create_session(bearer.unwrap_or_else(Identity::anonymous)).await,
+    };
+
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+    if let Some(session) = state.sessions.lock().await.get_mut(&id) {
+        // A newer stream replaces an older one
+        session.stream = Some(sender);
+    }
+    info!("HTTP session {} opened an event stream", id);
+
+    let mut response = Sse::new(event_stream(&id, receiver))
+        .keep_alive(KeepAlive::default())
+        .into_response();
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(SESSION_HEADER, value);
+    }
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    response
+}
+
+/// `endpoint` event telling the client where to POST, then its messages
+fn event_stream(
+    session_id: &str,
+    receiver: mpsc::Receiver<Value>,
+) -> impl Stream<Item = std::result::Result<Event, Infallible>> {
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data(format!("/mcp?sessionId={}", session_id));
+
+    let messages = stream::unfold(receiver, |mut receiver| async move {
+        let message = receiver.recv().await?;
+        Some((Event::default().event("message").data(message.to_string()), receiver))
+    });
+
+    stream::once(async move { endpoint }).chain(messages).map(Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mpcm_core::registry::{RequestRouter, ServiceRegistry};
+    use mpcm_core::storage_v2::Storage;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpStream;
+
+    async fn start(temp_dir: &TempDir) -> SocketAddr {
+        start_with(temp_dir, false).await.0
+    }
+
+    async fn start_with(temp_dir: &TempDir, require_token: bool) -> (SocketAddr, Arc<Storage>) {
+        let storage = Arc::new(Storage::new(temp_dir.path().join("http.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let mut server = McpServer::new(storage.clone(), registry.clone(), RequestRouter::new(registry));
+        server.set_require_token(require_token);
+        let server = Arc::new(server);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router(HttpState::new(server))).await.unwrap();
+        });
+        (addr, storage)
+    }
+
+    /// Minimal HTTP/1.1 exchange; returns the raw response
+    async fn request(addr: SocketAddr, method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut head = format!("{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n", method, path);
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", body.len(), body));
+        stream.write_all(head.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    /// POST one JSON-RPC message with an optional token; returns the status and body
+    async fn rpc(addr: SocketAddr, token: Option<&str>, message: Value) -> (u16, Value) {
+        let authorization = token.map(|t| format!("Bearer {}", t));
+        let headers: Vec<(&str, &str)> = authorization.iter().map(|a| ("Authorization", a.as_str())).collect();
+        let response = request(addr, "POST", "/mcp", &headers, &message.to_string()).await;
+
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").map(|(_, b)| b).unwrap_or_default();
+        (status, serde_json::from_str(body).unwrap_or(Value::Null))
+    }
+
+    fn header_value<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+        response
+            .lines()
+            .take_while(|l| !l.is_empty())
+            .find_map(|l| l.split_once(": ").filter(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v))
+    }
+
+    #[tokio::test]
+    async fn test_post_returns_inline_response_and_session() {
+        let temp_dir = TempDir::new().unwrap();
+        let addr = start(&temp_dir).await;
+
+        let response = request(
+            addr,
+            "POST",
+            "/mcp",
+            &[],
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(r#""protocolVersion""#));
+        let session = header_value(&response, SESSION_HEADER).unwrap().to_string();
+
+        let response = request(
+            addr,
+            "POST",
+            "/mcp",
+            &[(SESSION_HEADER, &session)],
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 202"));
+
+        let response = request(addr, "POST", "/mcp", &[(SESSION_HEADER, "nope")], "{}").await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn test_responses_arrive_on_event_stream() {
+        let temp_dir = TempDir::new().unwrap();
+        let addr = start(&temp_dir).await;
+
+        let mut sse = TcpStream::connect(addr).await.unwrap();
+        sse.write_all(b"GET /mcp/sse HTTP/1.1\r\nHost: localhost\r\nAccept: text/event-stream\r\n\r\n")
+            .await
+            .unwrap();
+        let mut lines = BufReader::new(sse).lines();
+
+        let mut session = None;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if let Some(endpoint) = line.strip_prefix("data: /mcp?sessionId=") {
+                session = Some(endpoint.to_string());
+                break;
+            }
+        }
+        let session = session.unwrap();
+
+        let response = request(
+            addr,
+            "POST",
+            &format!("/mcp?sessionId={}", session),
+            &[],
+            r#"{"jsonrpc":"2.0","id":"a","method":"ping"}"#,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 202"));
+
+        let message = loop {
+            let line = lines.next_line().await.unwrap().unwrap();
+            if let Some(data) = line.strip_prefix("data: ") {
+                break serde_json::from_str::<Value>(data).unwrap();
+            }
+        };
+        assert_eq!(message["id"], "a");
+        assert_eq!(message["result"], json!({}));
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_announced_to_other_sessions() {
+        let temp_dir = TempDir::new().unwrap();
+        let addr = start(&temp_dir).await;
+
+        let mut sse = TcpStream::connect(addr).await.unwrap();
+        sse.write_all(b"GET /mcp/sse HTTP/1.1\r\nHost: localhost\r\nAccept: text/event-stream\r\n\r\n")
+            .await
+            .unwrap();
+        let mut lines = BufReader::new(sse).lines();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if line.starts_with("data: /mcp?sessionId=") {
+                break;
+            }
+        }
+
+        let initialize = json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} });
+        let response = request(addr, "POST", "/mcp", &[], &initialize.to_string()).await;
+        let session = header_value(&response, SESSION_HEADER).unwrap().to_string();
+        let store = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "store_context",
+            "params": { "project_name": "alpha", "key": "k", "type": "note", "value": "v" },
+        });
+        let response = request(addr, "POST", "/mcp", &[(SESSION_HEADER, &session)], &store.to_string()).await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        let message = loop {
+            let line = lines.next_line().await.unwrap().unwrap();
+            if let Some(data) = line.strip_prefix("data: ") {
+                break serde_json::from_str::<Value>(data).unwrap();
+            }
+        };
+        assert_eq!(message["method"], "notifications/context_changed");
+        assert_eq!(message["params"]["project_name"], "alpha");
+        assert_eq!(message["params"]["key"], "k");
+    }
+
+    #[tokio::test]
+    async fn test_tokens_authenticate_and_isolate_tenants() {
+        let temp_dir = TempDir::new().unwrap();
+        let (addr, storage) = start_with(&temp_dir, true).await;
+        let team_a = storage.issue_token("a", Some("team-a"), "read_write").await.unwrap().token;
+        let team_b = storage.issue_token("b", Some("team-b"), "read_write").await.unwrap().token;
+
+        let list = json!({ "jsonrpc": "2.0", "id": 1, "method": "list_projects", "params": {} });
+        let (status, body) = rpc(addr, None, list.clone()).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["error"]["code"], mpcm_core::codes::UNAUTHENTICATED);
+
+        let (status, _) = rpc(addr, Some("mpcm_wrong"), list.clone()).await;
+        assert_eq!(status, 401);
+
+        let store = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "store_context",
+            "params": { "project_name": "alpha", "key": "k", "type": "note", "value": "secret" },
+        });
+        let (_, body) = rpc(addr, Some(&team_a), store).await;
+        assert!(body["error"].is_null(), "{}", body);
+
+        // team-b can neither see nor touch team-a's project
+        let (_, body) = rpc(addr, Some(&team_b), list.clone()).await;
+        assert_eq!(body["result"], json!([]));
+        let get = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "get_project_context",
+            "params": { "project_name": "alpha" },
+        });
+        let (_, body) = rpc(addr, Some(&team_b), get.clone()).await;
+        assert_eq!(body["error"]["code"], mpcm_core::codes::PROJECT_NOT_FOUND);
+
+        let (_, body) = rpc(addr, Some(&team_a), list).await;
+        assert_eq!(body["result"][0]["name"], "alpha");
+        let (_, body) = rpc(addr, Some(&team_a), get).await;
+        assert!(body["error"].is_null(), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_callers_cannot_read_tenant_projects() {
+        let temp_dir = TempDir::new().unwrap();
+        let (addr, storage) = start_with(&temp_dir, false).await;
+
+        // Without tenants the server is open to anyone who can reach it
+        let store = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "store_context",
+            "params": { "project_name": "alpha", "key": "k", "type": "note", "value": "secret" },
+        });
+        let (_, body) = rpc(addr, None, store).await;
+        assert!(body["error"].is_null(), "{}", body);
+
+        let team_a = storage.issue_token("a", Some("team-a"), "read_write").await.unwrap().token;
+        let store = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "store_context",
+            "params": { "project_name": "beta", "key": "k", "type": "note", "value": "secret" },
+        });
+        let (_, body) = rpc(addr, Some(&team_a), store).await;
+        assert!(body["error"].is_null(), "{}", body);
+
+        for method in ["get_project_context", "list_projects", "search_context"] {
+            let read = json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": method,
+                "params": { "project_name": "beta", "query": "secret" },
+            });
+            let (_, body) = rpc(addr, None, read).await;
+            assert_eq!(body["error"]["code"], mpcm_core::codes::UNAUTHENTICATED, "{}: {}", method, body);
+        }
+    }
+}
@@ -1,21 +1,56 @@
-//! MPCM Server - Unix socket server for context management
+//! MPCM Server - context management over Unix sockets, stdio and the network
 //! 
-//! This server implements the JSON-RPC protocol over Unix sockets
-//! to provide context storage and retrieval services.
+//! The protocol dialect and the storage back end are chosen at startup;
+//! the defaults are MCP/JSON-RPC over the TypeScript-compatible schema.
+//! Settings come from a TOML config file, environment variables and flags,
+//! each overriding the one before.
 
 mod protocol;
+mod auth;
+mod config;
 mod handlers;
+mod handlers_v2;
+mod http;
+mod mcp;
+mod reload;
 mod server;
+mod server_v2;
+mod shutdown;
+mod tls;
+mod ws;
 
-use anyhow::Result;
-use clap::Parser;
-use std::path::PathBuf;
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
+use anyhow::{bail, Context as _, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, Level};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
+
+// Re-export storage from mpcm-core
+use mpcm_core::adapters::terminal::DEFAULT_ALLOWED_COMMANDS;
+use mpcm_core::registry::{RequestRouter, ServiceRegistry};
+use mpcm_core::storage;
+use mpcm_core::storage_v2::{ConflictPolicy, ProjectArchive, RetentionPolicy, Storage};
+
+use config::{
+    AuthConfig, BackupConfig, Config, MaintenanceConfig, RegistryConfig, ServerConfig, StorageConfig, TlsConfig,
+};
+use handlers::Backend;
+use server_v2::Handlers;
+use shutdown::Shutdown;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// TOML config file (defaults to ~/.mpcm-pro/config.toml if it exists)
+    #[arg(long, env = "MPCM_CONFIG", global = true)]
+    config: Option<PathBuf>,
+    
     /// Path to the SQLite database
     #[arg(long, env = "MPCM_DB_PATH", default_value = "~/.mpcm-pro/mpcm-pro.db")]
     db_path: PathBuf,
@@ -24,49 +59,544 @@ struct Args {
     #[arg(long, env = "MPCM_SOCKET_PATH", default_value = "/tmp/mpcm.sock")]
     socket_path: PathBuf,
     
+    /// How clients connect
+    #[arg(long, env = "MPCM_TRANSPORT", value_enum, default_value_t = Transport::Unix)]
+    transport: Transport,
+    
+    /// Protocol dialect requests are written in
+    #[arg(long, env = "MPCM_DIALECT", value_enum, default_value_t = Dialect::Jsonrpc)]
+    dialect: Dialect,
+    
+    /// Schema the database is kept in
+    #[arg(long, env = "MPCM_STORAGE", value_enum, default_value_t = StorageBackend::V2)]
+    storage: StorageBackend,
+    
+    /// Address the HTTP transport listens on
+    #[arg(long, env = "MPCM_HTTP_ADDR", default_value = "127.0.0.1:3000")]
+    http_addr: SocketAddr,
+    
+    /// Address the WebSocket transport listens on
+    #[arg(long, env = "MPCM_WS_ADDR", default_value = "127.0.0.1:3001")]
+    ws_addr: SocketAddr,
+    
+    /// Address the TLS transport listens on
+    #[arg(long, env = "MPCM_TLS_ADDR", default_value = "127.0.0.1:7443")]
+    tls_addr: SocketAddr,
+    
+    /// PEM certificate chain for the TLS transport
+    #[arg(long, env = "MPCM_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+    
+    /// PEM private key for the TLS transport
+    #[arg(long, env = "MPCM_TLS_KEY")]
+    tls_key: Option<PathBuf>,
+    
+    /// PEM CA bundle for client certificates; makes them mandatory
+    #[arg(long, env = "MPCM_TLS_CLIENT_CA")]
+    tls_client_ca: Option<PathBuf>,
+    
+    /// JSON file mapping client certificate common names to a tenant and role
+    #[arg(long, env = "MPCM_TLS_CLIENT_MAP")]
+    tls_client_map: Option<PathBuf>,
+    
     /// Log level
     #[arg(long, env = "MPCM_LOG_LEVEL", default_value = "info")]
     log_level: String,
+    
+    /// Maximum concurrent connections
+    #[arg(long, env = "MPCM_MAX_CONNECTIONS", default_value = "100")]
+    max_connections: usize,
+    
+    /// Seconds a request may run unless it sets `_meta.timeoutMs`
+    #[arg(long, env = "MPCM_REQUEST_TIMEOUT", default_value = "300")]
+    request_timeout: u64,
+    
+    /// Days deleted projects and context stay restorable before purge
+    #[arg(long, env = "MPCM_TOMBSTONE_RETENTION_DAYS", default_value = "30")]
+    tombstone_retention_days: i64,
+    
+    /// Directory for database backups
+    #[arg(long, env = "MPCM_BACKUP_DIR", default_value = "~/.mpcm-pro/backups")]
+    backup_dir: PathBuf,
+    
+    /// Minutes between scheduled backups (0 disables them)
+    #[arg(long, env = "MPCM_BACKUP_INTERVAL_MINUTES", default_value = "0")]
+    backup_interval_minutes: u64,
+    
+    /// Number of backups to keep
+    #[arg(long, env = "MPCM_BACKUP_KEEP", default_value = "7")]
+    backup_keep: usize,
+    
+    /// JSON file with retention rules for stale context
+    #[arg(long, env = "MPCM_RETENTION_CONFIG")]
+    retention_config: Option<PathBuf>,
+    
+    /// Minutes between maintenance runs (retention and tombstone purge, 0 disables them)
+    #[arg(long, env = "MPCM_MAINTENANCE_INTERVAL_MINUTES", default_value = "60")]
+    maintenance_interval_minutes: u64,
+    
+    /// Directory the filesystem, git and terminal tools work in (tools without one are disabled)
+    #[arg(long, env = "MPCM_ADAPTER_ROOT")]
+    adapter_root: Option<PathBuf>,
+    
+    /// Refuse network callers without a token or mapped client certificate;
+    /// always the case once a tenant exists
+    #[arg(long, env = "MPCM_REQUIRE_TOKEN")]
+    require_token: bool,
+    
+    /// Seconds between service health checks
+    #[arg(long, env = "MPCM_HEALTH_CHECK_INTERVAL", default_value = "60")]
+    health_check_interval: u64,
+    
+    /// Seconds in-flight requests get to finish after SIGINT or SIGTERM
+    #[arg(long, env = "MPCM_SHUTDOWN_GRACE", default_value = "30")]
+    shutdown_grace: u64,
+    
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Transport {
+    /// Listen on the Unix socket
+    Unix,
+    /// Serve a single client over stdin/stdout, as MCP hosts launch servers
+    Stdio,
+    /// Accept JSON-RPC POSTs and stream responses over Server-Sent Events
+    Http,
+    /// Exchange JSON-RPC messages and change notifications over WebSocket
+    Websocket,
+    /// Line-delimited JSON-RPC over TCP with TLS, optionally with client certificates
+    Tls,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Dialect {
+    /// MCP and the JSON-RPC storage, token and tool methods
+    Jsonrpc,
+    /// The TypeScript adapter's `ServiceRequest` methods (unix and stdio transports only)
+    Service,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum StorageBackend {
+    /// The flat `contexts` table of the original server (service dialect only)
+    V1,
+    /// The TypeScript-compatible schema; imports v1 data found in the same file
+    V2,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Export a project to a JSON archive
+    Export {
+        /// Project to export
+        project: String,
+        
+        /// Archive file to write (stdout if omitted)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Import a project from a JSON archive
+    Import {
+        /// Archive file to read
+        input: PathBuf,
+        
+        /// How to handle keys that already exist: skip, overwrite or keep-newest
+        #[arg(long, default_value = "skip")]
+        policy: ConflictPolicy,
+    },
+    
+    /// Write a backup of the database to the backup directory
+    Backup,
+    
+    /// Replace the database with a backup (stop the server first)
+    Restore {
+        /// Backup file to restore
+        backup: PathBuf,
+    },
+    
+    /// Issue an API token and print it (it cannot be shown again)
+    IssueToken {
+        /// Name to tell the token apart by
+        name: String,
+        
+        /// Tenant to scope the token to (unscoped if omitted)
+        #[arg(long)]
+        tenant: Option<String>,
+        
+        /// What the token may do
+        #[arg(long, value_enum, default_value_t = auth::Role::ReadWrite)]
+        role: auth::Role,
+    },
+    
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Validate the config file and print the settings in effect
+    Check,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let (config_path, file) = match Config::discover(args.config.as_deref())? {
+        Some((path, file)) => (Some(path), file),
+        None => (None, Config::default()),
+    };
+    layer_config(&mut args, &matches, &file);
+    let config = effective_config(&args, &file);
+    config.validate().context("Invalid settings")?;
     
-    // Initialize logging
+    // Initialize logging; the level follows config reloads
     let log_level = args.log_level.parse::<Level>().unwrap_or(Level::INFO);
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(log_level)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+    let writer = if args.command.is_some() || args.transport == Transport::Stdio {
+        // Keep stdout clean for command output and the stdio protocol stream
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let builder = FmtSubscriber::builder()
+        .with_env_filter(level_filter(log_level))
+        .with_writer(writer)
+        .with_filter_reloading();
+    let log_filter = builder.reload_handle();
+    tracing::subscriber::set_global_default(builder.finish())?;
     
-    info!("Starting MPCM Server");
+    info!("Starting MPCM Server ({:?} dialect, {:?} storage)", args.dialect, args.storage);
     info!("Database: {:?}", args.db_path);
-    info!("Socket: {:?}", args.socket_path);
+    if args.transport == Transport::Unix {
+        info!("Socket: {:?}", args.socket_path);
+    }
+    
+    if args.dialect == Dialect::Service && !matches!(args.transport, Transport::Unix | Transport::Stdio) {
+        bail!("The service dialect is only served over the unix and stdio transports");
+    }
+    if let Some(Command::Config { command: ConfigCommand::Check }) = &args.command {
+        match &config_path {
+            Some(path) => println!("# Config file {:?} is valid", path),
+            None => println!("# No config file found; using defaults"),
+        }
+        print!("{}", toml::to_string_pretty(&config)?);
+        return Ok(());
+    }
+    if args.storage == StorageBackend::V1 && (args.dialect != Dialect::Service || args.command.is_some()) {
+        bail!("The v1 storage back end only serves the service dialect; use --storage v2");
+    }
     
     // Expand home directory
     let db_path = expand_home_dir(&args.db_path);
+    let backup_dir = expand_home_dir(&args.backup_dir);
+    
+    if args.storage == StorageBackend::V1 {
+        let storage = Arc::new(storage::Storage::new(&db_path).await?);
+        let result = serve(&args, Handlers::Service(Backend::V1(storage.clone()))).await;
+        if let Err(e) = storage.checkpoint().await {
+            error!("WAL checkpoint failed: {}", e);
+        }
+        storage.close().await;
+        return result;
+    }
     
-    // Ensure database directory exists
-    if let Some(parent) = db_path.parent() {
-        std::fs::create_dir_all(parent)?;
+    // Restoring swaps the database file, so it must happen before it is opened
+    if let Some(Command::Restore { backup }) = &args.command {
+        let version = Storage::restore_backup(backup, &db_path).await?;
+        println!("Restored {:?} from {:?} (schema version {})", db_path, backup, version);
+        return Ok(());
     }
     
-    // Start the server
-    server::run_server(&args.socket_path, &db_path).await?;
+    // Initialize storage
+    let mut storage = Storage::new(&db_path).await?;
+    storage.set_tombstone_retention_days(args.tombstone_retention_days);
+    if let Some(path) = &args.retention_config {
+        let policy: RetentionPolicy = serde_json::from_str(&std::fs::read_to_string(expand_home_dir(path))?)?;
+        info!("Loaded {} retention rules from {:?}", policy.rules.len(), path);
+        storage.set_retention_policy(policy);
+    }
+    let storage = Arc::new(storage);
+    info!("Storage initialized successfully");
     
-    Ok(())
+    match args.command {
+        Some(Command::Export { project, output }) => {
+            let archive = storage.export_project(&project).await?;
+            let json = serde_json::to_string_pretty(&archive)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, json)?;
+                    info!("Exported project {} to {:?}", project, path);
+                }
+                None => println!("{}", json),
+            }
+            return Ok(());
+        }
+        Some(Command::Import { input, policy }) => {
+            let archive: ProjectArchive = serde_json::from_str(&std::fs::read_to_string(&input)?)?;
+            let result = storage.import_project(&archive, policy).await?;
+            println!("{}", serde_json::to_string_pretty(&result)?);
+            return Ok(());
+        }
+        Some(Command::Backup) => {
+            let backup = storage.backup(&backup_dir, args.backup_keep).await?;
+            println!("{}", serde_json::to_string_pretty(&backup)?);
+            return Ok(());
+        }
+        Some(Command::IssueToken { name, tenant, role }) => {
+            let issued = storage.issue_token(&name, tenant.as_deref(), role.as_str()).await?;
+            println!("{}", serde_json::to_string_pretty(&issued)?);
+            return Ok(());
+        }
+        Some(Command::Restore { .. }) => unreachable!("restore is handled before storage opens"),
+        Some(Command::Config { .. }) => unreachable!("config commands are handled before storage opens"),
+        None => {}
+    }
+    
+    if args.maintenance_interval_minutes > 0 {
+        spawn_maintenance(
+            storage.clone(),
+            Duration::from_secs(args.maintenance_interval_minutes * 60),
+        );
+    }
+    
+    if args.backup_interval_minutes > 0 {
+        spawn_backup_schedule(
+            storage.clone(),
+            backup_dir,
+            Duration::from_secs(args.backup_interval_minutes * 60),
+            args.backup_keep,
+        );
+    }
+    
+    if args.dialect == Dialect::Service {
+        let result = serve(&args, Handlers::Service(Backend::V2(storage.clone()))).await;
+        close_storage(&storage).await;
+        return result;
+    }
+    
+    // Adapter tools are reached through the registry
+    let registry = Arc::new(ServiceRegistry::new(args.health_check_interval));
+    registry.clone().start_health_check_task();
+    let mut server = mcp::McpServer::new(
+        storage.clone(),
+        registry.clone(),
+        RequestRouter::new(registry.clone()),
+    );
+    server.set_require_token(args.require_token);
+    server.set_request_timeout(Duration::from_secs(args.request_timeout));
+    let server = Arc::new(server);
+    
+    // Adapters, routing and the log level follow the config file while running
+    let resolve: reload::Resolver = Box::new(move |file| {
+        let mut args = Args::from_arg_matches(&matches).expect("arguments were parsed at startup");
+        layer_config(&mut args, &matches, file);
+        effective_config(&args, file)
+    });
+    let set_log_level: reload::LogLevelSetter =
+        Box::new(move |level| Ok(log_filter.reload(level_filter(level))?));
+    let reloader = Arc::new(reload::Reloader::new(
+        config_path.or_else(Config::default_path),
+        resolve,
+        registry.clone(),
+        server.clone(),
+        set_log_level,
+    ));
+    reloader.apply(config).await.context("Failed to start adapters")?;
+    reloader.clone().spawn();
+    
+    let result = serve(&args, Handlers::JsonRpc(server)).await;
+    registry.shutdown_all().await;
+    close_storage(&storage).await;
+    result
+}
+
+/// Serve `handlers` on the configured transport until it stops or a
+/// SIGINT/SIGTERM has been handled
+async fn serve(args: &Args, handlers: Handlers) -> Result<()> {
+    let shutdown = Shutdown::new(Duration::from_secs(args.shutdown_grace));
+    shutdown.trigger_on_signal();
+    
+    let server = match (args.transport, handlers) {
+        (Transport::Unix, handlers) => {
+            return server_v2::run_server(&args.socket_path, handlers, args.max_connections, shutdown).await;
+        }
+        (Transport::Stdio, handlers) => return server_v2::run_stdio(handlers, shutdown).await,
+        (_, Handlers::JsonRpc(server)) => server,
+        (_, Handlers::Service(_)) => unreachable!("the service dialect is checked against the transport"),
+    };
+    
+    match args.transport {
+        Transport::Http => http::run_http(args.http_addr, server, shutdown).await,
+        Transport::Websocket => ws::run_websocket(args.ws_addr, server, args.max_connections, shutdown).await,
+        Transport::Tls => {
+            let options = tls::TlsOptions {
+                cert: args.tls_cert.clone().context("--tls-cert is required for the TLS transport")?,
+                key: args.tls_key.clone().context("--tls-key is required for the TLS transport")?,
+                client_ca: args.tls_client_ca.clone(),
+                client_map: args.tls_client_map.clone(),
+            };
+            let listener = tls::TlsListener::load(&options)?;
+            tls::run_tls(args.tls_addr, listener, server, args.max_connections, shutdown).await
+        }
+        Transport::Unix | Transport::Stdio => unreachable!("served above"),
+    }
+}
+
+/// Log filter passing `level` and everything more severe
+fn level_filter(level: Level) -> EnvFilter {
+    EnvFilter::default().add_directive(LevelFilter::from_level(level).into())
+}
+
+/// Take settings that no flag or environment variable gave from the config file
+fn layer_config(args: &mut Args, matches: &ArgMatches, file: &Config) {
+    let explicit = |id: &str| {
+        matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+    };
+    macro_rules! layer {
+        ($field:ident, $value:expr) => {
+            if let Some(value) = $value {
+                if !explicit(stringify!($field)) {
+                    args.$field = value;
+                }
+            }
+        };
+    }
+    
+    let server = file.server.clone();
+    layer!(db_path, server.db_path);
+    layer!(socket_path, server.socket_path);
+    layer!(log_level, server.log_level);
+    layer!(max_connections, server.max_connections);
+    layer!(request_timeout, server.request_timeout);
+    layer!(shutdown_grace, server.shutdown_grace);
+    layer!(require_token, file.auth.require_token);
+    
+    let tls = file.tls.clone();
+    layer!(tls_addr, tls.addr);
+    layer!(tls_cert, tls.cert.map(Some));
+    layer!(tls_key, tls.key.map(Some));
+    layer!(tls_client_ca, tls.client_ca.map(Some));
+    layer!(tls_client_map, tls.client_map.map(Some));
+    
+    let storage = file.storage.clone();
+    layer!(tombstone_retention_days, storage.tombstone_retention_days);
+    layer!(retention_config, storage.retention_config.map(Some));
+    
+    let backup = file.backup.clone();
+    layer!(backup_dir, backup.dir);
+    layer!(backup_interval_minutes, backup.interval_minutes);
+    layer!(backup_keep, backup.keep);
+    layer!(maintenance_interval_minutes, file.maintenance.interval_minutes);
+    
+    layer!(health_check_interval, file.registry.health_check_interval);
+    layer!(adapter_root, file.adapters.root.clone().map(Some));
 }
 
-fn expand_home_dir(path: &PathBuf) -> PathBuf {
+/// The config file with every setting resolved against flags and defaults
+fn effective_config(args: &Args, file: &Config) -> Config {
+    let mut config = file.clone();
+    config.server = ServerConfig {
+        db_path: Some(args.db_path.clone()),
+        socket_path: Some(args.socket_path.clone()),
+        log_level: Some(args.log_level.clone()),
+        max_connections: Some(args.max_connections),
+        request_timeout: Some(args.request_timeout),
+        shutdown_grace: Some(args.shutdown_grace),
+    };
+    config.auth = AuthConfig {
+        require_token: Some(args.require_token),
+    };
+    config.tls = TlsConfig {
+        addr: Some(args.tls_addr),
+        cert: args.tls_cert.clone(),
+        key: args.tls_key.clone(),
+        client_ca: args.tls_client_ca.clone(),
+        client_map: args.tls_client_map.clone(),
+    };
+    config.storage = StorageConfig {
+        tombstone_retention_days: Some(args.tombstone_retention_days),
+        retention_config: args.retention_config.clone(),
+    };
+    config.backup = BackupConfig {
+        dir: Some(args.backup_dir.clone()),
+        interval_minutes: Some(args.backup_interval_minutes),
+        keep: Some(args.backup_keep),
+    };
+    config.maintenance = MaintenanceConfig {
+        interval_minutes: Some(args.maintenance_interval_minutes),
+    };
+    config.registry = RegistryConfig {
+        health_check_interval: Some(args.health_check_interval),
+    };
+    config.adapters.root = args.adapter_root.clone();
+    config.adapters.terminal.allowed_commands.get_or_insert_with(|| {
+        DEFAULT_ALLOWED_COMMANDS.iter().map(|c| c.to_string()).collect()
+    });
+    config
+}
+
+/// Fold the WAL into the database file and close it once serving has stopped
+async fn close_storage(storage: &Storage) {
+    if let Err(e) = storage.checkpoint().await {
+        error!("WAL checkpoint failed: {}", e);
+    }
+    storage.close().await;
+    info!("Storage closed");
+}
+
+/// Back up the database every `interval` until the process exits
+fn spawn_backup_schedule(storage: Arc<Storage>, dir: PathBuf, interval: Duration, keep: usize) {
+    info!("Scheduling backups to {:?} every {:?}", dir, interval);
+    
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick fires immediately; wait a full interval instead
+        ticker.tick().await;
+        
+        loop {
+            ticker.tick().await;
+            if let Err(e) = storage.backup(&dir, keep).await {
+                error!("Scheduled backup failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Apply retention rules and purge expired tombstones every `interval`
+fn spawn_maintenance(storage: Arc<Storage>, interval: Duration) {
+    info!("Scheduling maintenance every {:?}", interval);
+    
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        
+        loop {
+            ticker.tick().await;
+            if let Err(e) = storage.apply_retention(storage.retention_policy(), false).await {
+                error!("Retention run failed: {}", e);
+            }
+            if let Err(e) = storage.purge(None, None).await {
+                error!("Tombstone purge failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Expand ~ to home directory
+fn expand_home_dir(path: &Path) -> PathBuf {
     if let Some(path_str) = path.to_str() {
-        if path_str.starts_with("~/") {
-            if let Ok(home) = std::env::var("HOME") {
-                return PathBuf::from(path_str.replacen("~", &home, 1));
+        if let Some(rest) = path_str.strip_prefix("~/") {
+            if let Some(home) = dirs::home_dir() {
+                return home.join(rest);
             }
         }
     }
-    path.clone()
+    path.to_path_buf()
 }
 
 #[cfg(test)]
@@ -85,4 +615,53 @@ mod tests {
         let expanded = expand_home_dir(&absolute);
         assert_eq!(expanded, absolute);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_config_file_loses_to_flags() {
+        let file: Config = toml::from_str(
+            "[server]\nmax_connections = 8\nsocket_path = \"/tmp/file.sock\"\n[routing.tools]\nexecute = \"terminal\"",
+        )
+        .unwrap();
+        let matches = Args::command()
+            .try_get_matches_from(["mpcm-server", "--max-connections", "20"])
+            .unwrap();
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        layer_config(&mut args, &matches, &file);
+        assert_eq!(args.max_connections, 20);
+        assert_eq!(args.socket_path, PathBuf::from("/tmp/file.sock"));
+        assert_eq!(args.log_level, "info");
+        
+        let config = effective_config(&args, &file);
+        assert_eq!(config.server.max_connections, Some(20));
+        assert_eq!(config.routing.tools["execute"], "terminal");
+        assert!(config.validate().is_ok());
+        assert!(config.adapters.terminal.allowed_commands.unwrap().contains(&"cargo".to_string()));
+    }
+    
+    #[test]
+    fn test_flags_are_validated_with_the_file() {
+        let file: Config = toml::from_str(
+            "[auth]\nrequire_token = true\n[tls]\ncert = \"server.pem\"\nkey = \"server.key\"\n[backup]\nkeep = 3",
+        )
+        .unwrap();
+        let resolve = |flags: &[&str]| {
+            let matches = Args::command()
+                .try_get_matches_from(["mpcm-server"].iter().chain(flags))
+                .unwrap();
+            let mut args = Args::from_arg_matches(&matches).unwrap();
+            layer_config(&mut args, &matches, &file);
+            effective_config(&args, &file)
+        };
+        
+        let config = resolve(&[]);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.auth.require_token, Some(true));
+        assert_eq!(config.tls.key, Some(PathBuf::from("server.key")));
+        assert_eq!(config.backup.keep, Some(3));
+        
+        // A valid file does not vouch for the flags layered over it
+        for flags in [&["--log-level", "loud"][..], &["--max-connections", "0"], &["--backup-keep", "0"]] {
+            assert!(resolve(flags).validate().is_err(), "accepted {:?}", flags);
+        }
+    }
+}
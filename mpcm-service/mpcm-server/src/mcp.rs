@@ -0,0 +1,638 @@
+//! Model Context Protocol endpoint
+//!
+//! Speaks MCP (`initialize`, `tools/list`, `tools/call`) on top of the
+//! existing JSON-RPC methods. Tools are the storage methods plus every
+//! capability of the services in the registry; the bespoke methods stay
+//! callable directly for older clients.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, info};
+
+use crate::auth::Identity;
+use crate::handlers_v2::{self, parse_params, target_project};
+use mpcm_core::registry::{RequestRouter, ServiceRegistry, ServiceResult, ToolRequest};
+use mpcm_core::storage_v2::Storage;
+use mpcm_core::MpcmError;
+
+/// Protocol revisions this server can speak, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Server name reported during `initialize`
+pub const SERVER_NAME: &str = "mpcm-pro";
+
+/// Initialize parameters
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeParams {
+    protocol_version: Option<String>,
+    #[serde(default)]
+    client_info: Value,
+}
+
+/// Tool call parameters
+#[derive(Debug, Deserialize)]
+pub struct CallToolParams {
+    name: String,
+    #[serde(default)]
+    arguments: Map<String, Value>,
+}
+
+/// A successful write through one of the storage methods
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    pub method: String,
+    pub project_name: Option<String>,
+    pub key: Option<String>,
+    /// Tenant owning the changed project
+    pub tenant: Option<String>,
+}
+
+/// Storage methods that change data and so produce a `ChangeEvent`
+pub(crate) const MUTATING_METHODS: [&str; 11] = [
+    "store_context",
+    "update_project_status",
+    "store_project_context",
+    "restore_context_revision",
+    "delete_context",
+    "delete_project",
+    "restore",
+    "purge",
+    "import_project",
+    "register_context_type",
+    "unregister_context_type",
+];
+
+/// Whether a request only reads, so a batch may run it alongside others
+///
+/// Adapter tools write files and run commands, so they never overlap.
+pub(crate) fn is_read_only(method: &str, params: Option<&Value>) -> bool {
+    match method {
+        "tools/call" => params
+            .and_then(|params| params["name"].as_str())
+            .is_some_and(|tool| STORAGE_TOOLS.iter().any(|(name, _)| *name == tool) && is_read_only(tool, None)),
+        "authenticate" | "issue_token" | "revoke_token" => false,
+        _ => !MUTATING_METHODS.contains(&method),
+    }
+}
+
+/// Change events buffered per subscriber before the slowest ones lag
+const CHANGE_BUFFER: usize = 256;
+
+/// How long a request may run unless the client asks for another deadline
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Methods anonymous callers may use when a token is required
+const OPEN_METHODS: [&str; 2] = ["initialize", "ping"];
+
+/// Authenticate parameters
+#[derive(Debug, Deserialize)]
+pub struct AuthenticateParams {
+    token: String,
+}
+
+/// Dispatches MCP and bespoke JSON-RPC methods for every transport
+pub struct McpServer {
+    storage: Arc<Storage>,
+    registry: Arc<ServiceRegistry>,
+    router: RequestRouter,
+    changes: broadcast::Sender<ChangeEvent>,
+    /// Refuse anonymous network callers even while there are no tenants
+    require_token: bool,
+    /// Deadline for requests that do not set their own
+    request_timeout: Duration,
+}
+
+impl McpServer {
+    /// Create a server routing adapter tools through `router`
+    pub fn new(storage: Arc<Storage>, registry: Arc<ServiceRegistry>, router: RequestRouter) -> Self {
+        Self {
+            storage,
+            registry,
+            router,
+            changes: broadcast::channel(CHANGE_BUFFER).0,
+            require_token: false,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+
+    /// Make anonymous network callers authenticate before anything but the handshake
+    ///
+    /// Once a tenant exists they have to regardless, or they could read its projects.
+    pub fn set_require_token(&mut self, required: bool) {
+        self.require_token = required;
+    }
+
+    /// Set the deadline for requests that do not carry their own
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.request_timeout = timeout;
+    }
+
+    /// Deadline for requests that do not carry their own
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    /// Router adapter tools are sent through
+    pub fn router(&self) -> &RequestRouter {
+        &self.router
+    }
+
+    /// Identity of the holder of `token`
+    pub async fn authenticate(&self, token: &str) -> Result<Identity> {
+        let token = self
+            .storage
+            .authenticate_token(token)
+            .await?
+            .ok_or_else(|| MpcmError::Unauthenticated("Invalid or revoked token".to_string()))?;
+        Ok(Identity::from_token(&token)?)
+    }
+
+    /// Switch a connection to the identity of the token in `params`
+    pub async fn handle_authenticate(&self, caller: &mut Identity, params: Value) -> Result<Value> {
+        let params: AuthenticateParams = parse_params(params)?;
+        *caller = self.authenticate(&params.token).await?;
+        info!("Caller authenticated as {:?} (tenant {:?})", caller.name, caller.tenant);
+
+        Ok(json!({
+            "name": caller.name,
+            "tenant": caller.tenant,
+            "role": caller.role.as_str(),
+        }))
+    }
+
+    /// Identity for an `Authorization` header value; anonymous without one
+    pub async fn authenticate_bearer(&self, header: Option<&str>) -> Result<Identity> {
+        match header {
+            Some(header) => {
+                let token = header
+                    .strip_prefix("Bearer ")
+                    .ok_or_else(|| MpcmError::Unauthenticated("Expected a Bearer token".to_string()))?;
+                self.authenticate(token.trim()).await
+            }
+            None => Ok(Identity::anonymous()),
+        }
+    }
+
+    /// Receive an event for every successful write from now on
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.changes.subscribe()
+    }
+
+    /// Fail unless `caller` may get past the token requirement for `method`
+    async fn admit(&self, caller: &Identity, method: &str) -> Result<()> {
+        if caller.authenticated || OPEN_METHODS.contains(&method) || method.starts_with("notifications/") {
+            return Ok(());
+        }
+        if self.require_token || self.storage.has_tenants().await? {
+            return Err(MpcmError::Unauthenticated("A token is required".to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// Fail unless `caller` may run a method a transport answers itself
+    ///
+    /// Such methods, like `subscribe` on WebSockets, never reach
+    /// `handle_request` but are held to the same checks.
+    pub async fn authorize_local(&self, caller: &Identity, method: &str, project_name: Option<&str>) -> Result<()> {
+        self.admit(caller, method).await?;
+        caller.authorize(method)?;
+        if let (Some(tenant), Some(project)) = (caller.tenant.as_deref(), project_name) {
+            self.storage.check_project_access(project, tenant).await?;
+        }
+        Ok(())
+    }
+
+    /// Handle one request from `caller`; notifications yield `Value::Null`
+    pub async fn handle_request(&self, caller: &Identity, method: &str, params: Value) -> Result<Value> {
+        self.admit(caller, method).await?;
+
+        match method {
+            "initialize" => {
+                let params: InitializeParams = parse_params(params)?;
+                Ok(self.initialize(params))
+            }
+            "ping" => Ok(json!({})),
+            "tools/list" => self.list_tools().await,
+            "tools/call" => {
+                let params: CallToolParams = parse_params(params)?;
+                self.call_tool(caller, params).await
+            }
+            m if m.starts_with("notifications/") => {
+                debug!("Received notification: {}", m);
+                Ok(Value::Null)
+            }
+            _ => self.call_storage(caller, method, params).await,
+        }
+    }
+
+    /// Run a storage method, announcing it if it changed anything
+    async fn call_storage(&self, caller: &Identity, method: &str, params: Value) -> Result<Value> {
+        caller.authorize(method)?;
+        let event = if MUTATING_METHODS.contains(&method) {
+            let project_name = target_project(&params);
+            // Looked up first, as the write may purge the project; tenant callers own what they write
+            let tenant = match (&caller.tenant, &project_name) {
+                (Some(tenant), _) => Some(tenant.clone()),
+                (None, Some(project)) => self.storage.project_tenant(project).await?,
+                (None, None) => None,
+            };
+            Some(ChangeEvent {
+                method: method.to_string(),
+                project_name,
+                key: params.get("key").and_then(Value::as_str).map(str::to_string),
+                tenant,
+            })
+        } else {
+            None
+        };
+
+        let result =
+            handlers_v2::handle_request(method, params, self.storage.clone(), caller.tenant.as_deref()).await?;
+        if let Some(event) = event {
+            // Nobody listening is fine
+            let _ = self.changes.send(event);
+        }
+        Ok(result)
+    }
+
+    /// Answer the handshake, agreeing on a protocol version
+    fn initialize(&self, params: InitializeParams) -> Value {
+        let requested = params.protocol_version.as_deref();
+        let version = requested
+            .filter(|v| SUPPORTED_PROTOCOL_VERSIONS.contains(v))
+            .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0]);
+        info!("MCP client {} initialized with protocol {}", params.client_info, version);
+
+        json!({
+            "protocolVersion": version,
+            "capabilities": {
+                "tools": { "listChanged": false },
+            },
+            "serverInfo": {
+                "name": SERVER_NAME,
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })
+    }
+
+    /// Storage tools followed by the capabilities of registered services
+    async fn list_tools(&self) -> Result<Value> {
+        let mut tools = storage_tools();
+
+        let mut services = self.registry.list_services().await;
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+        for service in services {
+            for capability in service.capabilities {
+                // Storage tools and earlier services win on name clashes
+                if tools.iter().any(|t| t["name"] == capability.name.as_str()) {
+                    continue;
+                }
+                tools.push(json!({
+                    "name": capability.name,
+                    "description": capability.description,
+                    "inputSchema": capability.input_schema.unwrap_or_else(|| json!({ "type": "object" })),
+                }));
+            }
+        }
+
+        Ok(json!({ "tools": tools }))
+    }
+
+    /// Run a tool, reporting failures inside the result as MCP expects
+    async fn call_tool(&self, caller: &Identity, params: CallToolParams) -> Result<Value> {
+        debug!("Calling tool: {}", params.name);
+
+        if STORAGE_TOOLS.iter().any(|(name, _)| *name == params.name) {
+            // Denials are protocol errors rather than failed tool runs
+            caller.authorize(&params.name)?;
+            let result = self
+                .call_storage(caller, &params.name, Value::Object(params.arguments))
+                .await;
+            return Ok(match result {
+                Ok(value) => tool_result(&value, false),
+                Err(e) => tool_result(&Value::String(e.to_string()), true),
+            });
+        }
+
+        let string_arg = |key: &str| params.arguments.get(key).and_then(Value::as_str).map(str::to_string);
+        let project_name = string_arg("project_name");
+        let role_id = string_arg("role_id");
+        caller.authorize_tool(&params.name, project_name.as_deref())?;
+        if let (Some(tenant), Some(project)) = (caller.tenant.as_deref(), project_name.as_deref()) {
+            self.storage.check_project_access(project, tenant).await?;
+        }
+        let request = ToolRequest {
+            tool: params.name,
+            args: Value::Object(params.arguments),
+        };
+
+        match self.router.route_request(request, project_name, role_id, None).await {
+            Ok(result) => Ok(service_tool_result(result)),
+            Err(e) if matches!(e.downcast_ref::<MpcmError>(), Some(MpcmError::NoServiceForTool(_))) => Err(e),
+            Err(e) => Ok(tool_result(&Value::String(e.to_string()), true)),
+        }
+    }
+}
+
+/// Wrap a value as MCP tool output
+fn tool_result(value: &Value, is_error: bool) -> Value {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        other => serde_json::to_string_pretty(other).unwrap_or_default(),
+    };
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": is_error,
+    })
+}
+
+fn service_tool_result(result: ServiceResult) -> Value {
+    if result.success {
+        tool_result(&result.data.unwrap_or(Value::Null), false)
+    } else {
+        let message = result.error.unwrap_or_else(|| "Tool failed".to_string());
+        tool_result(&Value::String(message), true)
+    }
+}
+
+/// Storage methods exposed as tools, with their descriptions
+const STORAGE_TOOLS: [(&str, &str); 24] = [
+    ("store_context", "Store a context entry for a project"),
+    ("search_context", "Search context entries across projects"),
+    ("get_project_context", "Get a project and all of its context"),
+    ("list_projects", "List projects"),
+    ("update_project_status", "Change a project's status"),
+    ("store_project_context", "Create a project or update its details"),
+    ("get_recent_updates", "List recent changes to projects and context"),
+    ("list_context_revisions", "List earlier values of a context entry"),
+    ("diff_context_revisions", "Diff two revisions of a context entry"),
+    ("restore_context_revision", "Restore a context entry to an earlier revision"),
+    ("delete_context", "Delete a context entry"),
+    ("delete_project", "Delete a project and its context"),
+    ("restore", "Restore a deleted project or context entry"),
+    ("purge", "Permanently remove deleted data"),
+    ("export_project", "Export a project as an archive"),
+    ("import_project", "Import a project archive"),
+    ("retention_dry_run", "Show what the retention policy would remove"),
+    ("list_systems", "List the machines that have written context"),
+    ("register_context_type", "Register a custom context type"),
+    ("list_context_types", "List built-in and custom context types"),
+    ("unregister_context_type", "Remove a custom context type"),
+    ("issue_token", "Issue an API token for a tenant and role"),
+    ("list_tokens", "List API tokens"),
+    ("revoke_token", "Revoke an API token"),
+];
+
+/// Tool definitions for the storage methods
+fn storage_tools() -> Vec<Value> {
+    STORAGE_TOOLS
+        .iter()
+        .map(|(name, description)| {
+            json!({
+                "name": name,
+                "description": description,
+                "inputSchema": storage_input_schema(name),
+            })
+        })
+        .collect()
+}
+
+fn storage_input_schema(tool: &str) -> Value {
+    let string = json!({ "type": "string" });
+    let boolean = json!({ "type": "boolean" });
+    let integer = json!({ "type": "integer" });
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    let object = json!({ "type": "object" });
+
+    let (properties, required): (Value, &[&str]) = match tool {
+        "store_context" => (
+            json!({
+                "project_name": string, "key": string, "type": string, "value": string,
+                "tags": strings, "metadata": object, "is_system_specific": boolean, "role_id": string,
+            }),
+            &["project_name", "key", "type", "value"],
+        ),
+        "search_context" => (
+            json!({
+                "project_name": string, "query": string, "type": string, "types": strings,
+                "tags": strings, "tag_match": { "type": "string", "enum": ["any", "all"] },
+                "role_id": string, "since": string, "until": string, "is_system_specific": boolean,
+                "offset": integer, "limit": integer,
+                "sort": {
+                    "type": "string",
+                    "enum": ["relevance", "updated_desc", "updated_asc", "created_desc", "created_asc", "key"],
+                },
+            }),
+            &[],
+        ),
+        "get_project_context" => (json!({ "project_name": string, "system_specific": boolean }), &["project_name"]),
+        "list_projects" => (json!({ "include_archived": boolean }), &[]),
+        "update_project_status" => (
+            json!({ "project_name": string, "status": string, "note": string }),
+            &["project_name", "status"],
+        ),
+        "store_project_context" => (
+            json!({
+                "project_name": string, "description": string, "status": string,
+                "repository_url": string, "local_directory": string, "tags": strings,
+                "metadata": object, "primary_system_id": integer,
+            }),
+            &["project_name"],
+        ),
+        "get_recent_updates" => (
+            json!({
+                "project_name": string, "entity_type": string, "action": string,
+                "since": string, "until": string, "limit": integer,
+            }),
+            &[],
+        ),
+        "list_context_revisions" => (json!({ "project_name": string, "key": string }), &["project_name", "key"]),
+        "diff_context_revisions" => (
+            json!({ "project_name": string, "key": string, "from": integer, "to": integer }),
+            &["project_name", "key", "from", "to"],
+        ),
+        "restore_context_revision" => (
+            json!({ "project_name": string, "key": string, "revision": integer, "role_id": string }),
+            &["project_name", "key", "revision"],
+        ),
+        "delete_context" => (
+            json!({ "project_name": string, "key": string, "hard": boolean }),
+            &["project_name", "key"],
+        ),
+        "delete_project" => (json!({ "project_name": string, "hard": boolean }), &["project_name"]),
+        "restore" => (json!({ "project_name": string, "key": string }), &["project_name"]),
+        "purge" => (json!({ "project_name": string, "older_than_days": integer }), &[]),
+        "export_project" => (json!({ "project_name": string }), &["project_name"]),
+        "import_project" => (
+            json!({
+                "archive": object,
+                "policy": { "type": "string", "enum": ["skip", "overwrite", "keep_newest"] },
+            }),
+            &["archive"],
+        ),
+        "retention_dry_run" => (json!({ "policy": object }), &[]),
+        "register_context_type" => (
+            json!({ "name": string, "description": string, "value_schema": object, "metadata_schema": object }),
+            &["name"],
+        ),
+        "unregister_context_type" => (json!({ "name": string }), &["name"]),
+        "issue_token" => (
+            json!({
+                "name": string, "tenant": string,
+                "role": { "type": "string", "enum": ["admin", "read_write", "read_only"] },
+            }),
+            &["name", "role"],
+        ),
+        "list_tokens" => (json!({ "tenant": string }), &[]),
+        "revoke_token" => (json!({ "id": integer }), &["id"]),
+        _ => (json!({}), &[]),
+    };
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mpcm_core::adapters::FileSystemAdapter;
+    use tempfile::TempDir;
+
+    async fn server(temp_dir: &TempDir) -> McpServer {
+        let storage = Arc::new(Storage::new(temp_dir.path().join("mcp.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        registry
+            .register(Box::new(FileSystemAdapter::new(temp_dir.path())))
+            .await
+            .unwrap();
+        let router = RequestRouter::new(registry.clone());
+        McpServer::new(storage, registry, router)
+    }
+
+    #[tokio::test]
+    async fn test_initialize_negotiates_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let server = server(&temp_dir).await;
+
+        let result = server
+            .handle_request(&Identity::local(), "initialize", json!({ "protocolVersion": "2024-11-05", "capabilities": {} }))
+            .await
+            .unwrap();
+        assert_eq!(result["protocolVersion"], "2024-11-05");
+        assert_eq!(result["serverInfo"]["name"], SERVER_NAME);
+        assert!(result["capabilities"]["tools"].is_object());
+
+        let result = server
+            .handle_request(&Identity::local(), "initialize", json!({ "protocolVersion": "1999-01-01" }))
+            .await
+            .unwrap();
+        assert_eq!(result["protocolVersion"], SUPPORTED_PROTOCOL_VERSIONS[0]);
+    }
+
+    #[tokio::test]
+    async fn test_tools_cover_storage_and_services() {
+        let temp_dir = TempDir::new().unwrap();
+        let server = server(&temp_dir).await;
+
+        let result = server.handle_request(&Identity::local(), "tools/list", Value::Null).await.unwrap();
+        let names: Vec<_> = result["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"store_context"));
+        assert!(names.contains(&"writeFile"));
+
+        let stored = server
+            .handle_request(&Identity::local(), "tools/call", json!({
+                "name": "store_context",
+                "arguments": { "project_name": "alpha", "key": "k", "type": "note", "value": "v" },
+            }))
+            .await
+            .unwrap();
+        assert_eq!(stored["isError"], false);
+
+        let written = server
+            .handle_request(&Identity::local(), "tools/call", json!({
+                "name": "writeFile",
+                "arguments": { "path": "notes.txt", "content": "hello" },
+            }))
+            .await
+            .unwrap();
+        assert_eq!(written["isError"], false);
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("notes.txt")).unwrap(), "hello");
+
+        let failed = server
+            .handle_request(&Identity::local(), "tools/call", json!({
+                "name": "get_project_context",
+                "arguments": { "project_name": "missing" },
+            }))
+            .await
+            .unwrap();
+        assert_eq!(failed["isError"], true);
+
+        let err = server
+            .handle_request(&Identity::local(), "tools/call", json!({ "name": "no_such_tool" }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<MpcmError>(), Some(MpcmError::NoServiceForTool(_))));
+    }
+
+    #[tokio::test]
+    async fn test_adapter_tools_need_a_token_and_a_project_of_the_tenant() {
+        let temp_dir = TempDir::new().unwrap();
+        let server = server(&temp_dir).await;
+        let write = |arguments: Value| json!({ "name": "writeFile", "arguments": arguments });
+        let code = |err: anyhow::Error| err.downcast_ref::<MpcmError>().map(MpcmError::code);
+
+        // Anonymous callers are refused even while no tenant exists
+        let err = server
+            .handle_request(&Identity::anonymous(), "tools/call", write(json!({ "path": "a.txt", "content": "x" })))
+            .await
+            .unwrap_err();
+        assert_eq!(code(err), Some(mpcm_core::error::codes::UNAUTHENTICATED));
+        assert!(!temp_dir.path().join("a.txt").exists());
+
+        let caller = |tenant: &str| Identity {
+            name: Some(tenant.to_string()),
+            tenant: Some(tenant.to_string()),
+            role: crate::auth::Role::ReadWrite,
+            authenticated: true,
+        };
+        let (team_a, team_b) = (caller("team-a"), caller("team-b"));
+        server
+            .handle_request(&team_a, "store_context", json!({
+                "project_name": "alpha", "key": "k", "type": "note", "value": "v",
+            }))
+            .await
+            .unwrap();
+
+        // Tenant callers only run tools scoped to a project of their own
+        let err = server
+            .handle_request(&team_b, "tools/call", write(json!({ "path": "b.txt", "content": "x" })))
+            .await
+            .unwrap_err();
+        assert_eq!(code(err), Some(mpcm_core::error::codes::PERMISSION_DENIED));
+        let err = server
+            .handle_request(&team_b, "tools/call", write(json!({ "project_name": "alpha", "path": "b.txt", "content": "x" })))
+            .await
+            .unwrap_err();
+        assert_eq!(code(err), Some(mpcm_core::error::codes::PROJECT_NOT_FOUND));
+        assert!(!temp_dir.path().join("b.txt").exists());
+
+        let written = server
+            .handle_request(&team_a, "tools/call", write(json!({ "project_name": "alpha", "path": "a.txt", "content": "x" })))
+            .await
+            .unwrap();
+        assert_eq!(written["isError"], false);
+    }
+}
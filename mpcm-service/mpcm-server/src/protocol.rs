@@ -1,54 +1,215 @@
 //! JSON-RPC protocol types and serialization
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
+pub const JSONRPC_VERSION: &str = "2.0";
+
 // For TypeScript adapter compatibility
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ServiceRequest {
-    pub id: String,
+    /// `None` when absent, which makes the request a notification
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
     pub method: String,
+    #[serde(default)]
     pub params: Value,
 }
 
-// For v2 server compatibility  
+// For v2 server compatibility; ids may be strings, numbers or null
 #[derive(Debug, Clone, Deserialize)]
 pub struct Request {
-    pub id: Option<String>,
+    #[serde(default)]
+    pub jsonrpc: Option<String>,
+    /// `None` when absent, which makes the request a notification;
+    /// `"id": null` is `Some(Value::Null)` and still gets a response
+    #[serde(default, deserialize_with = "present")]
+    pub id: Option<Value>,
     pub method: String,
+    #[serde(default)]
     pub params: Option<Value>,
 }
 
+/// Keep an explicit `null` distinct from a missing field
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+impl Request {
+    /// Validate one message of a single request or batch
+    ///
+    /// `jsonrpc` may be left out by older clients but must otherwise be "2.0".
+    /// Invalid messages yield the error response to send back instead.
+    pub fn from_value(message: Value) -> Result<Self, Box<Response>> {
+        let id = match message.get("id") {
+            None | Some(Value::Null | Value::String(_) | Value::Number(_)) => message.get("id").cloned(),
+            Some(_) => return Err(Box::new(Response::error(Value::Null, ErrorResponse::invalid_request()))),
+        };
+        let invalid = || {
+            let id = id.clone().unwrap_or(Value::Null);
+            Box::new(Response::error(id, ErrorResponse::invalid_request()))
+        };
+
+        let request: Self = serde_json::from_value(message).map_err(|_| invalid())?;
+        let version_ok = request.jsonrpc.as_deref().is_none_or(|v| v == JSONRPC_VERSION);
+        let params_ok = matches!(request.params, None | Some(Value::Object(_) | Value::Array(_)));
+        if !version_ok || !params_ok {
+            return Err(invalid());
+        }
+        Ok(request)
+    }
+
+    /// The response to send for `result`; notifications get none
+    pub fn respond(self, result: anyhow::Result<Value>) -> Option<Response> {
+        let id = self.id?;
+        Some(match result {
+            Ok(result) => Response::success(id, result),
+            Err(e) => Response::error(id, ErrorResponse::from_error(&e)),
+        })
+    }
+}
+
+/// A line off the wire: one request or a batch of them
+#[derive(Debug)]
+pub enum Incoming {
+    Single(Value),
+    Batch(Vec<Value>),
+}
+
+impl Incoming {
+    /// Parse a message, or the error response for malformed JSON or an empty batch
+    pub fn parse(text: &str) -> Result<Self, Box<Response>> {
+        let error = match serde_json::from_str(text) {
+            Ok(Value::Array(batch)) if batch.is_empty() => ErrorResponse::invalid_request(),
+            Ok(Value::Array(batch)) => return Ok(Incoming::Batch(batch)),
+            Ok(message) => return Ok(Incoming::Single(message)),
+            Err(e) => ErrorResponse::parse_error(&e.to_string()),
+        };
+        Err(Box::new(Response::error(Value::Null, error)))
+    }
+}
+
+/// What goes back for one line: a response, or an array for a batch
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Reply<R = Response> {
+    Single(R),
+    Batch(Vec<R>),
+}
+
+impl<R> Reply<R> {
+    /// Reply to a batch; nothing at all if it held only notifications
+    pub fn batch(responses: Vec<R>) -> Option<Self> {
+        (!responses.is_empty()).then_some(Reply::Batch(responses))
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Response {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub id: Option<String>,
+    pub jsonrpc: &'static str,
+    /// Always present; null when the request's id could not be read
+    pub id: Value,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<ErrorResponse>,
 }
 
+impl Response {
+    pub fn success(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+    
+    pub fn error(id: Value, error: ErrorResponse) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct ServiceResponse {
-    pub id: String,
+    pub jsonrpc: &'static str,
+    pub id: Value,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<ErrorResponse>,
 }
 
+impl From<Request> for ServiceRequest {
+    fn from(request: Request) -> Self {
+        Self {
+            id: request.id,
+            method: request.method,
+            params: request.params.unwrap_or(Value::Null),
+        }
+    }
+}
+
+impl From<Response> for ServiceResponse {
+    fn from(response: Response) -> Self {
+        Self {
+            jsonrpc: response.jsonrpc,
+            id: response.id,
+            result: response.result,
+            error: response.error,
+        }
+    }
+}
+
+impl ServiceResponse {
+    pub fn success(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+    
+    pub fn error(id: Value, error: ErrorResponse) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct ErrorResponse {
     pub code: i32,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
 }
 
 impl ErrorResponse {
+    /// Error response carrying the code and details of a handler error
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        let (code, data) = mpcm_core::error_details(error);
+        Self {
+            code,
+            message: error.to_string(),
+            data,
+        }
+    }
+    
     pub fn parse_error(msg: &str) -> Self {
         Self {
             code: -32700,
             message: format!("Parse error: {}", msg),
+            data: None,
         }
     }
     
@@ -56,20 +217,7 @@ impl ErrorResponse {
         Self {
             code: -32600,
             message: "Invalid request".to_string(),
-        }
-    }
-    
-    pub fn method_not_found(method: &str) -> Self {
-        Self {
-            code: -32601,
-            message: format!("Method not found: {}", method),
-        }
-    }
-    
-    pub fn invalid_params(msg: &str) -> Self {
-        Self {
-            code: -32602,
-            message: format!("Invalid params: {}", msg),
+            data: None,
         }
     }
     
@@ -77,15 +225,11 @@ impl ErrorResponse {
         Self {
             code: -32603,
             message: format!("Internal error: {}", msg),
+            data: None,
         }
     }
 }
 
-// Custom error codes
-pub const ERROR_CONTEXT_NOT_FOUND: i32 = 1001;
-pub const ERROR_PROJECT_NOT_FOUND: i32 = 1002;
-pub const ERROR_DATABASE: i32 = 1003;
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,20 +249,17 @@ mod tests {
         });
         
         let request: ServiceRequest = serde_json::from_value(json).unwrap();
-        assert_eq!(request.id, "test123");
+        assert_eq!(request.id, Some(json!("test123")));
         assert_eq!(request.method, "store_context");
         assert_eq!(request.params["project_name"], "test");
     }
     
     #[test]
     fn test_response_serialization() {
-        let response = ServiceResponse {
-            id: "test123".to_string(),
-            result: Some(json!({"success": true})),
-            error: None,
-        };
+        let response = ServiceResponse::success(json!("test123"), json!({"success": true}));
         
         let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["jsonrpc"], "2.0");
         assert_eq!(json["id"], "test123");
         assert_eq!(json["result"]["success"], true);
         assert_eq!(json.get("error"), None);
@@ -126,14 +267,59 @@ mod tests {
     
     #[test]
     fn test_error_response() {
-        let response = ServiceResponse {
-            id: "test123".to_string(),
-            result: None,
-            error: Some(ErrorResponse::method_not_found("unknown")),
-        };
+        let response = ServiceResponse::error(json!("test123"), ErrorResponse::internal_error("unknown"));
         
         let json = serde_json::to_value(&response).unwrap();
-        assert_eq!(json["error"]["code"], -32601);
+        assert_eq!(json["error"]["code"], -32603);
         assert!(json["error"]["message"].as_str().unwrap().contains("unknown"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_error_response_from_typed_error() {
+        let err: anyhow::Error = mpcm_core::MpcmError::ProjectNotFound {
+            project: "alpha".to_string(),
+        }
+        .into();
+        
+        let json = serde_json::to_value(ErrorResponse::from_error(&err)).unwrap();
+        assert_eq!(json["code"], 1002);
+        assert_eq!(json["data"], json!({"kind": "project_not_found", "project": "alpha"}));
+        
+        let untyped = ErrorResponse::from_error(&anyhow::anyhow!("Project not found: beta"));
+        assert_eq!(untyped.code, -32603);
+        assert!(untyped.data.is_none());
+        
+        // Values we stored but cannot read back are our fault, not the caller's
+        let corrupt: anyhow::Error = mpcm_core::MpcmError::from(serde_json::from_str::<Value>("{").unwrap_err()).into();
+        assert_eq!(ErrorResponse::from_error(&corrupt).code, -32603);
+    }
+    
+    #[test]
+    fn test_request_ids_and_validation() {
+        let request = Request::from_value(json!({"jsonrpc": "2.0", "id": 7, "method": "ping"})).unwrap();
+        assert_eq!(request.id, Some(json!(7)));
+        
+        // A null id is a request; a missing one is a notification
+        let request = Request::from_value(json!({"jsonrpc": "2.0", "id": null, "method": "ping"})).unwrap();
+        assert_eq!(request.id, Some(Value::Null));
+        let notification = Request::from_value(json!({"jsonrpc": "2.0", "method": "ping"})).unwrap();
+        assert!(notification.respond(Ok(json!({}))).is_none());
+        
+        for message in [
+            json!({"jsonrpc": "2.0", "id": [1], "method": "ping"}),
+            json!({"jsonrpc": "1.0", "id": 1, "method": "ping"}),
+            json!({"jsonrpc": "2.0", "id": 1, "method": 5}),
+            json!({"jsonrpc": "2.0", "id": 1, "method": "ping", "params": "x"}),
+            json!(1),
+        ] {
+            let response = *Request::from_value(message).unwrap_err();
+            assert_eq!(response.error.unwrap().code, -32600);
+        }
+        
+        assert!(matches!(Incoming::parse("[]"), Err(r) if r.id.is_null()));
+        assert!(matches!(Incoming::parse("[1, 2]"), Ok(Incoming::Batch(b)) if b.len() == 2));
+        let parse_error = serde_json::to_value(Incoming::parse("{").unwrap_err()).unwrap();
+        assert_eq!(parse_error["id"], Value::Null);
+        assert_eq!(parse_error["error"]["code"], -32700);
+    }
+}
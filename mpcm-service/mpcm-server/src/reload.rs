@@ -0,0 +1,303 @@
+//! Live configuration reload
+//!
+//! The config file is read again on SIGHUP and whenever its modification
+//! time changes. A file that fails to parse or validate, or whose adapters
+//! fail to start, is rejected whole and the server keeps running as it was.
+//! Otherwise tool routing, the adapters registered with the
+//! `ServiceRegistry`, the terminal whitelist and the log level are brought
+//! in line with it; every other setting takes effect on the next restart.
+
+use anyhow::{anyhow, Context as _, Result};
+use mpcm_core::adapters::terminal::{AllowedCommands, DEFAULT_ALLOWED_COMMANDS};
+use mpcm_core::adapters::{FileSystemAdapter, GitAdapter};
+use mpcm_core::registry::{ServiceProvider, ServiceRegistry};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn, Level};
+
+use crate::config::{Config, ADAPTER_NAMES};
+use crate::mcp::McpServer;
+
+/// How often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Changes the level of the global log subscriber
+pub type LogLevelSetter = Box<dyn Fn(Level) -> Result<()> + Send + Sync>;
+
+/// Layers flags and environment variables over a freshly read file
+pub type Resolver = Box<dyn Fn(&Config) -> Config + Send + Sync>;
+
+/// Applies configuration to a running server
+pub struct Reloader {
+    /// File to read again; `None` if there is no home directory to look in
+    path: Option<PathBuf>,
+    resolve: Resolver,
+    registry: Arc<ServiceRegistry>,
+    server: Arc<McpServer>,
+    set_log_level: LogLevelSetter,
+    applied: Mutex<Applied>,
+}
+
+/// What the running server was last configured with
+#[derive(Default)]
+struct Applied {
+    /// `None` until the first `apply`
+    config: Option<Config>,
+    /// Registered adapters and the directory each works in
+    adapters: HashMap<&'static str, PathBuf>,
+    /// Whitelist of the registered terminal adapter
+    whitelist: Option<AllowedCommands>,
+}
+
+impl Reloader {
+    pub fn new(
+        path: Option<PathBuf>,
+        resolve: Resolver,
+        registry: Arc<ServiceRegistry>,
+        server: Arc<McpServer>,
+        set_log_level: LogLevelSetter,
+    ) -> Self {
+        Self {
+            path,
+            resolve,
+            registry,
+            server,
+            set_log_level,
+            applied: Mutex::new(Applied::default()),
+        }
+    }
+
+    /// Read the file again and apply it; nothing changes if it is invalid
+    ///
+    /// The file is checked on its own and again once flags are layered over it.
+    pub async fn reload(&self) -> Result<()> {
+        let path = self.path.as_ref().ok_or_else(|| anyhow!("No config file to reload"))?;
+        let file = Config::load(path)?;
+        let config = (self.resolve)(&file);
+        config.validate().context("Invalid settings")?;
+        self.apply(config).await
+    }
+
+    /// Bring the server in line with `config`, which has been validated
+    ///
+    /// The changed adapters are started before anything else changes and
+    /// swapped in together; if one fails to start, the previous config
+    /// stays in effect.
+    pub async fn apply(&self, config: Config) -> Result<()> {
+        let mut applied = self.applied.lock().await;
+
+        let mut adapters = applied.adapters.clone();
+        let mut whitelist = applied.whitelist.clone();
+        let mut remove = Vec::new();
+        let mut add: Vec<Box<dyn ServiceProvider>> = Vec::new();
+        for &name in ADAPTER_NAMES {
+            let root = config.adapters.root_of(name);
+            if adapters.get(name) == root.as_ref() {
+                continue;
+            }
+
+            if adapters.remove(name).is_some() {
+                remove.push(name);
+                if name == "terminal" {
+                    whitelist = None;
+                }
+            }
+            let Some(root) = root else { continue };
+
+            add.push(match name {
+                "filesystem" => Box::new(FileSystemAdapter::new(&root)),
+                "git" => Box::new(GitAdapter::new(&root)),
+                _ => {
+                    let terminal = config.adapters.terminal_adapter(&root);
+                    whitelist = Some(terminal.allowed_commands());
+                    Box::new(terminal)
+                }
+            });
+            adapters.insert(name, root);
+        }
+        if !remove.is_empty() || !add.is_empty() {
+            self.registry.replace_services(&remove, add).await?;
+            for (name, root) in &adapters {
+                if applied.adapters.get(name) != Some(root) {
+                    info!("{} adapter works in {:?}", name, root);
+                }
+            }
+        }
+        applied.adapters = adapters;
+        applied.whitelist = whitelist;
+
+        if let Some(previous) = &applied.config {
+            if restart_settings(previous) != restart_settings(&config) {
+                warn!("Server, storage and registry settings other than log_level take effect on restart");
+            }
+        }
+
+        let level = config.server.log_level.clone();
+        if level != applied.config.as_ref().and_then(|c| c.server.log_level.clone()) {
+            match level.as_deref().unwrap_or("info").parse::<Level>() {
+                Ok(level) => match (self.set_log_level)(level) {
+                    Ok(()) => info!("Log level is {}", level),
+                    Err(e) => error!("Failed to change the log level: {}", e),
+                },
+                Err(_) => warn!("Ignoring unknown log level {:?}", level),
+            }
+        }
+
+        // A terminal adapter that stayed registered keeps running with the new list
+        if let Some(whitelist) = &applied.whitelist {
+            let commands = config.adapters.terminal.allowed_commands.clone().unwrap_or_else(|| {
+                DEFAULT_ALLOWED_COMMANDS.iter().map(|c| c.to_string()).collect()
+            });
+            if whitelist.get() != commands {
+                info!("Terminal adapter allows {:?}", commands);
+                whitelist.set(commands);
+            }
+        }
+
+        if applied.config.as_ref().map(|c| &c.routing) != Some(&config.routing) {
+            config.routing.apply(self.server.router()).await;
+        }
+
+        applied.config = Some(config);
+        Ok(())
+    }
+
+    /// Reload on SIGHUP and whenever the file changes
+    pub fn spawn(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    error!("Failed to listen for SIGHUP: {}", e);
+                    return;
+                }
+            };
+            let mut ticker = tokio::time::interval(WATCH_INTERVAL);
+            let mut modified = self.modified();
+
+            loop {
+                tokio::select! {
+                    _ = hangup.recv() => info!("Received SIGHUP, reloading config"),
+                    _ = ticker.tick() => {
+                        if self.modified() == modified {
+                            continue;
+                        }
+                        info!("Config file changed, reloading");
+                    }
+                }
+                modified = self.modified();
+                if let Err(e) = self.reload().await {
+                    error!("Config rejected, keeping current settings: {:#}", e);
+                }
+            }
+        });
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        let path = self.path.as_ref()?;
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+}
+
+/// The part of `config` that is only read at startup
+fn restart_settings(config: &Config) -> Config {
+    let mut config = config.clone();
+    config.server.log_level = None;
+    config.adapters = Default::default();
+    config.routing = Default::default();
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mpcm_core::registry::{RequestRouter, ServiceCommand, ToolRequest};
+    use mpcm_core::storage_v2::Storage;
+    use serde_json::json;
+    use std::sync::Mutex as StdMutex;
+    use tempfile::TempDir;
+
+    async fn names(registry: &ServiceRegistry) -> Vec<String> {
+        let mut names: Vec<String> = registry.list_services().await.into_iter().map(|s| s.name).collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_valid_configs_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        let root = temp_dir.path().display().to_string();
+        let storage = Arc::new(Storage::new(temp_dir.path().join("reload.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let server = Arc::new(McpServer::new(storage, registry.clone(), RequestRouter::new(registry.clone())));
+
+        let levels = Arc::new(StdMutex::new(Vec::new()));
+        let recorded = levels.clone();
+        let reloader = Reloader::new(
+            Some(path.clone()),
+            Box::new(|file| file.clone()),
+            registry.clone(),
+            server.clone(),
+            Box::new(move |level| {
+                recorded.lock().unwrap().push(level);
+                Ok(())
+            }),
+        );
+        let run = |command: &str| ServiceCommand {
+            tool: "execute".to_string(),
+            args: json!({ "command": command }),
+            project_name: None,
+            role_id: None,
+            context: None,
+            store_result: None,
+        };
+
+        std::fs::write(&path, format!("[adapters]\nroot = {:?}\n", root)).unwrap();
+        reloader.reload().await.unwrap();
+        assert_eq!(names(&registry).await, ["filesystem", "git", "terminal"]);
+        assert!(registry.execute("terminal", run("echo hi")).await.is_ok());
+
+        std::fs::write(
+            &path,
+            format!(
+                "[server]\nlog_level = \"debug\"\n[adapters]\nroot = {:?}\n[adapters.git]\nenabled = false\n\
+                 [adapters.terminal]\nallowed_commands = [\"ls\"]\n[routing]\nstrategy = \"direct\"\n\
+                 [routing.tools]\nlistDirectory = \"filesystem\"\n",
+                root
+            ),
+        )
+        .unwrap();
+        reloader.reload().await.unwrap();
+        assert_eq!(names(&registry).await, ["filesystem", "terminal"]);
+        assert!(registry.execute("terminal", run("echo hi")).await.is_err());
+        assert!(registry.execute("terminal", run("ls")).await.is_ok());
+        assert_eq!(*levels.lock().unwrap(), [Level::DEBUG]);
+        let unmapped = ToolRequest { tool: "execute".to_string(), args: json!({ "command": "ls" }) };
+        assert!(server.router().route_request(unmapped, None, None, None).await.is_err());
+
+        // A broken file changes nothing, even the parts of it that are valid
+        std::fs::write(
+            &path,
+            format!("[server]\nlog_level = \"trace\"\n[adapters]\nroot = {:?}\n[routing.tools]\nexecute = \"docker\"\n", root),
+        )
+        .unwrap();
+        assert!(reloader.reload().await.is_err());
+        assert_eq!(names(&registry).await, ["filesystem", "terminal"]);
+        assert_eq!(levels.lock().unwrap().len(), 1);
+        assert!(registry.execute("terminal", run("echo hi")).await.is_err());
+
+        // Adapters that cannot start leave the running ones and the old settings alone
+        let mut config = Config::default();
+        config.server.log_level = Some("trace".to_string());
+        config.adapters.root = Some(path.join("not-a-directory"));
+        assert!(reloader.apply(config).await.is_err());
+        assert_eq!(names(&registry).await, ["filesystem", "terminal"]);
+        assert!(registry.execute("terminal", run("ls")).await.is_ok());
+        assert_eq!(levels.lock().unwrap().len(), 1);
+    }
+}
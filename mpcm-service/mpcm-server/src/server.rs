@@ -1,120 +1,121 @@
-//! Unix socket server implementation for MPCM
+//! Line server for the TypeScript-compatible service dialect
 //! 
-//! This module handles the Unix socket server lifecycle, connection management,
-//! and request/response processing. It's designed for high performance with
-//! async I/O and connection pooling.
+//! This module reads `ServiceRequest`s off a connection, runs them through
+//! the service handlers and writes back `ServiceResponse`s. The transports
+//! in `server_v2` hand it connections when the server runs this dialect.
 
-use anyhow::{Context, Result};
-use mpcm_core::storage::Storage;
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::RwLock;
+use anyhow::Result;
+use futures::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::time::{timeout, Duration};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, warn};
 
-use crate::handlers::handle_request;
-use crate::protocol::{ServiceRequest, ServiceResponse, ErrorResponse};
+use crate::handlers::{handle_request, Backend};
+use crate::protocol::{ErrorResponse, Incoming, Reply, Request, ServiceRequest, ServiceResponse};
+use crate::shutdown::Shutdown;
 
 /// Default timeout for client operations (30 seconds)
-const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// Maximum message size (10MB) to prevent memory exhaustion
 const MAX_MESSAGE_SIZE: usize = 10 * 1024 * 1024;
 
-/// Server configuration
-pub struct ServerConfig {
-    pub socket_path: PathBuf,
-    pub db_path: PathBuf,
-    pub max_connections: usize,
-    pub request_timeout: Duration,
-}
-
-impl Default for ServerConfig {
-    fn default() -> Self {
-        Self {
-            socket_path: PathBuf::from("/tmp/mpcm.sock"),
-            db_path: PathBuf::from("~/.mpcm-pro/mpcm-pro.db"),
-            max_connections: 100,
-            request_timeout: DEFAULT_TIMEOUT,
-        }
-    }
-}
-
-/// Parse a JSON message into a ServiceRequest
-pub fn parse_message(message: &str) -> Result<ServiceRequest> {
-    serde_json::from_str(message)
-        .context("Failed to parse JSON message")
-}
+/// Methods that write, and so are never run concurrently within a batch
+const WRITE_METHODS: [&str; 2] = ["store_context", "store_project_context"];
 
-/// Format a ServiceResponse as a JSON string with newline
-pub fn format_response(response: &ServiceResponse) -> String {
+/// Format a ServiceResponse, or a reply holding them, as a JSON string with newline
+pub fn format_response<T: Serialize>(response: &T) -> String {
     match serde_json::to_string(response) {
         Ok(json) => format!("{}\n", json),
         Err(e) => {
             // Fallback error response
-            format!(r#"{{"id":"","error":{{"code":-32603,"message":"Failed to serialize response: {}"}}}}"#, e)
+            format!(r#"{{"jsonrpc":"2.0","id":null,"error":{{"code":-32603,"message":"Failed to serialize response: {}"}}}}"#, e)
         }
     }
 }
 
-/// Run the Unix socket server
-pub async fn run_server(socket_path: &Path, db_path: &Path) -> Result<()> {
-    // Clean up any existing socket
-    if socket_path.exists() {
-        std::fs::remove_file(socket_path)
-            .context("Failed to remove existing socket")?;
-    }
-    
-    // Initialize storage
-    let storage = Storage::new(db_path).await
-        .context("Failed to initialize storage")?;
-    let storage = Arc::new(RwLock::new(storage));
-    
-    // Create Unix socket listener
-    let listener = UnixListener::bind(socket_path)
-        .context("Failed to bind Unix socket")?;
+/// Process one line: a request or a batch of them
+///
+/// Returns the formatted reply, or `None` when there is nothing to send
+/// because the line held only notifications. A batch runs concurrently
+/// unless it writes, in which case it runs in order.
+pub async fn process_message(message: &str, backend: &Backend) -> Option<String> {
+    let reply = match Incoming::parse(message) {
+        Ok(Incoming::Single(message)) => process_one(message, backend).await.map(Reply::Single),
+        Ok(Incoming::Batch(batch)) => {
+            let writes = batch
+                .iter()
+                .any(|m| m["method"].as_str().is_some_and(|method| WRITE_METHODS.contains(&method)));
+            let mut responses = Vec::new();
+            if writes {
+                for message in batch {
+                    responses.extend(process_one(message, backend).await);
+                }
+            } else {
+                let pending = batch.into_iter().map(|message| process_one(message, backend));
+                responses.extend(join_all(pending).await.into_iter().flatten());
+            }
+            Reply::batch(responses)
+        }
+        Err(response) => Some(Reply::Single(ServiceResponse::from(*response))),
+    };
+    reply.map(|reply| format_response(&reply))
+}
+
+/// Process a single request; notifications get no response
+async fn process_one(message: Value, backend: &Backend) -> Option<ServiceResponse> {
+    let request = match Request::from_value(message) {
+        Ok(request) => ServiceRequest::from(request),
+        Err(response) => return Some((*response).into()),
+    };
     
-    info!("MPCM Server listening on {:?}", socket_path);
+    // Convert ServiceRequest to JSON-RPC format for handlers
+    let json_rpc_request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": request.method,
+        "params": request.params,
+        "id": request.id
+    });
     
-    // Set socket permissions (readable/writable by owner only)
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))
-            .context("Failed to set socket permissions")?;
-    }
+    let json_rpc_response = handle_request(json_rpc_request, backend).await;
+    let id = request.id?;
     
-    // Accept connections
-    loop {
-        match listener.accept().await {
-            Ok((stream, _addr)) => {
-                let storage = storage.clone();
-                
-                // Spawn handler for each connection
-                tokio::spawn(async move {
-                    if let Err(e) = handle_connection(stream, storage).await {
-                        error!("Connection handler error: {}", e);
-                    }
-                });
-            }
-            Err(e) => {
-                error!("Failed to accept connection: {}", e);
-                // Continue accepting other connections
-            }
-        }
-    }
+    // Convert JSON-RPC response back to ServiceResponse
+    Some(if let Some(error) = json_rpc_response.get("error") {
+        ServiceResponse::error(
+            id,
+            ErrorResponse {
+                code: error["code"].as_i64().unwrap_or(-32603) as i32,
+                message: error["message"].as_str().unwrap_or("Unknown error").to_string(),
+                data: error.get("data").cloned(),
+            },
+        )
+    } else {
+        ServiceResponse::success(id, json_rpc_response.get("result").cloned().unwrap_or(Value::Null))
+    })
 }
 
-/// Handle a single client connection
-async fn handle_connection(
-    stream: UnixStream,
-    storage: Arc<RwLock<Storage>>,
-) -> Result<()> {
+/// Answer service requests from `reader` on `writer` until it closes
+///
+/// A client that sends nothing for `idle_timeout` is told so and dropped;
+/// `None` waits forever, as a host driving stdio expects. Requests run one
+/// at a time, so once `shutdown` is triggered the connection closes after
+/// answering the current one.
+pub async fn serve_service_lines<R, W>(
+    reader: R,
+    mut writer: W,
+    backend: Backend,
+    idle_timeout: Option<Duration>,
+    shutdown: Shutdown,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
     debug!("New client connected");
     
-    let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
     let mut buffer = String::new();
     
@@ -122,10 +123,19 @@ async fn handle_connection(
         buffer.clear();
         
         // Read request with timeout
-        let read_result = timeout(
-            DEFAULT_TIMEOUT,
-            reader.read_line(&mut buffer)
-        ).await;
+        let read = async {
+            match idle_timeout {
+                Some(idle_timeout) => timeout(idle_timeout, reader.read_line(&mut buffer)).await,
+                None => Ok(reader.read_line(&mut buffer).await),
+            }
+        };
+        let read_result = tokio::select! {
+            read_result = read => read_result,
+            _ = shutdown.triggered() => {
+                debug!("Closing connection for shutdown");
+                break;
+            }
+        };
         
         match read_result {
             Ok(Ok(0)) => {
@@ -135,62 +145,18 @@ async fn handle_connection(
             }
             Ok(Ok(n)) if n > MAX_MESSAGE_SIZE => {
                 // Message too large
-                let error_response = ServiceResponse {
-                    id: String::new(),
-                    result: None,
-                    error: Some(ErrorResponse::invalid_request()),
-                };
+                let error_response = ServiceResponse::error(Value::Null, ErrorResponse::invalid_request());
                 
                 writer.write_all(format_response(&error_response).as_bytes()).await?;
                 writer.flush().await?;
                 continue;
             }
+            Ok(Ok(_)) if buffer.trim().is_empty() => continue,
             Ok(Ok(_)) => {
-                // Process request
-                match parse_message(&buffer) {
-                    Ok(request) => {
-                        // Convert ServiceRequest to JSON-RPC format for handlers
-                        let json_rpc_request = serde_json::json!({
-                            "jsonrpc": "2.0",
-                            "method": request.method,
-                            "params": request.params,
-                            "id": request.id
-                        });
-                        
-                        let json_rpc_response = handle_request(json_rpc_request, storage.clone()).await;
-                        
-                        // Convert JSON-RPC response back to ServiceResponse
-                        let response = if let Some(error) = json_rpc_response.get("error") {
-                            ServiceResponse {
-                                id: request.id,
-                                result: None,
-                                error: Some(ErrorResponse {
-                                    code: error["code"].as_i64().unwrap_or(-32603) as i32,
-                                    message: error["message"].as_str().unwrap_or("Unknown error").to_string(),
-                                }),
-                            }
-                        } else {
-                            ServiceResponse {
-                                id: request.id,
-                                result: json_rpc_response.get("result").cloned(),
-                                error: None,
-                            }
-                        };
-                        
-                        writer.write_all(format_response(&response).as_bytes()).await?;
-                        writer.flush().await?;
-                    }
-                    Err(e) => {
-                        // Invalid JSON
-                        let error_response = ServiceResponse {
-                            id: String::new(),
-                            result: None,
-                            error: Some(ErrorResponse::parse_error()),
-                        };
-                        
-                        writer.write_all(format_response(&error_response).as_bytes()).await?;
-                        writer.flush().await?;
-                    }
+                // Process request; notifications get no response
+                if let Some(reply) = process_message(&buffer, &backend).await {
+                    writer.write_all(reply.as_bytes()).await?;
+                    writer.flush().await?;
                 }
             }
             Ok(Err(e)) => {
@@ -201,11 +167,7 @@ async fn handle_connection(
             Err(_) => {
                 // Timeout
                 warn!("Client request timeout");
-                let error_response = ServiceResponse {
-                    id: String::new(),
-                    result: None,
-                    error: Some(ErrorResponse::internal_error("Request timeout")),
-                };
+                let error_response = ServiceResponse::error(Value::Null, ErrorResponse::internal_error("Request timeout"));
                 
                 writer.write_all(format_response(&error_response).as_bytes()).await?;
                 writer.flush().await?;
@@ -217,64 +179,80 @@ async fn handle_connection(
     Ok(())
 }
 
-/// Graceful shutdown handler
-pub async fn shutdown_server(socket_path: &Path) -> Result<()> {
-    info!("Shutting down MPCM Server");
-    
-    // Remove socket file
-    if socket_path.exists() {
-        std::fs::remove_file(socket_path)
-            .context("Failed to remove socket during shutdown")?;
-    }
-    
-    Ok(())
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use mpcm_core::storage::Storage;
+    use serde_json::json;
+    use std::sync::Arc;
     use tempfile::TempDir;
     
-    #[test]
-    fn test_server_config_default() {
-        let config = ServerConfig::default();
-        assert_eq!(config.socket_path, PathBuf::from("/tmp/mpcm.sock"));
-        assert_eq!(config.max_connections, 100);
-        assert_eq!(config.request_timeout, Duration::from_secs(30));
-    }
-    
     #[tokio::test]
-    async fn test_socket_cleanup() {
+    async fn test_parse_error_handling() {
         let temp_dir = TempDir::new().unwrap();
-        let socket_path = temp_dir.path().join("test.sock");
+        let db_path = temp_dir.path().join("v1.db");
+        std::fs::File::create(&db_path).unwrap();
+        let storage = Storage::new(&db_path).await.unwrap();
+        let backend = Backend::V1(Arc::new(storage));
         
-        // Create a dummy file
-        std::fs::write(&socket_path, "dummy").unwrap();
-        assert!(socket_path.exists());
+        let reply = process_message("{ invalid json }", &backend).await.unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["jsonrpc"], "2.0");
+        assert_eq!(reply["id"], Value::Null);
+        assert_eq!(reply["error"]["code"], -32700);
         
-        // Shutdown should remove it
-        shutdown_server(&socket_path).await.unwrap();
-        assert!(!socket_path.exists());
-    }
-    
-    #[test]
-    fn test_parse_error_handling() {
-        let invalid_json = "{ invalid json }";
-        let result = parse_message(invalid_json);
-        assert!(result.is_err());
+        // Numeric ids round-trip, notifications are silent, batches answer as arrays
+        let batch = r#"[{"id":1,"method":"list_projects"},{"method":"list_projects"},{"id":"b","method":"nope"}]"#;
+        let reply: Value = serde_json::from_str(&process_message(batch, &backend).await.unwrap()).unwrap();
+        let replies = reply.as_array().unwrap();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(replies[1]["id"], "b");
+        assert_eq!(replies[1]["error"]["code"], -32601);
+        assert!(process_message(r#"{"method":"list_projects"}"#, &backend).await.is_none());
     }
     
     #[test]
     fn test_format_response() {
-        let response = ServiceResponse {
-            id: "test123".to_string(),
-            result: Some(serde_json::json!({"success": true})),
-            error: None,
-        };
+        let response = ServiceResponse::success(serde_json::json!("test123"), serde_json::json!({"success": true}));
         
         let formatted = format_response(&response);
         assert!(formatted.ends_with('\n'));
         assert!(formatted.contains("test123"));
         assert!(formatted.contains("success"));
     }
+    
+    #[tokio::test]
+    async fn test_service_lines_over_v2_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = mpcm_core::storage_v2::Storage::new(temp_dir.path().join("v2.db")).await.unwrap();
+        let backend = Backend::V2(Arc::new(storage));
+        
+        let requests = [
+            json!({"id": "1", "method": "store_context",
+                   "params": {"project_name": "alpha", "key": "db", "type": "decision", "value": "Use SQLite"}}),
+            json!({"id": "2", "method": "store_project_context",
+                   "params": {"project_name": "alpha", "description": "Demo"}}),
+            json!({"id": "3", "method": "search_context", "params": {"query": "SQLite"}}),
+            json!({"id": "4", "method": "get_project_context", "params": {"project_name": "alpha"}}),
+            json!({"id": "5", "method": "list_projects"}),
+        ];
+        let input: String = requests.iter().map(|r| r.to_string() + "\n").collect();
+        let mut output = Vec::new();
+        serve_service_lines(input.as_bytes(), &mut output, backend, None, Shutdown::default()).await.unwrap();
+        
+        let replies: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(replies.len(), 5);
+        assert_eq!(replies[0]["result"]["success"], true);
+        assert_eq!(replies[1]["result"]["success"], true);
+        assert_eq!(replies[2]["result"]["count"], 1);
+        assert_eq!(replies[2]["result"]["entries"][0]["key"], "db");
+        assert_eq!(replies[3]["result"]["project"]["description"], "Demo");
+        assert_eq!(replies[3]["result"]["context_count"], 1);
+        assert_eq!(replies[4]["result"]["projects"][0]["name"], "alpha");
+    }
 }
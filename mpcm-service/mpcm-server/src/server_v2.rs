@@ -1,22 +1,49 @@
-//! Unix socket server implementation v2
+//! Unix socket and stdio servers
 
-use anyhow::{anyhow, Result};
-use serde_json::{json, Value};
+use anyhow::{Context as _, Result};
+use futures::future::join_all;
+use serde_json::Value;
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::{UnixListener, UnixStream};
+use tokio::task::{self, AbortHandle, JoinSet};
 use tracing::{debug, error, info, warn};
 
-use crate::handlers_v2;
-use crate::protocol::{Request, Response, ErrorResponse};
-use mpcm_core::storage_v2::Storage;
+use crate::auth::Identity;
+use crate::handlers::Backend;
+use crate::mcp::{is_read_only, McpServer};
+use crate::protocol::{ErrorResponse, Incoming, Reply, Request, Response};
+use crate::server::{serve_service_lines, DEFAULT_TIMEOUT};
+use crate::shutdown::Shutdown;
+use mpcm_core::MpcmError;
 
-/// Run the Unix socket server
+/// Notification that cancels a running request by id
+pub const CANCEL_REQUEST: &str = "$/cancelRequest";
+
+/// Requests one connection may have running at once
+pub const MAX_IN_FLIGHT: usize = 64;
+
+/// Handler set a connection is served with, picked by `--dialect`
+#[derive(Clone)]
+pub enum Handlers {
+    /// MCP plus the JSON-RPC storage methods, as `Request`s
+    JsonRpc(Arc<McpServer>),
+    /// The TypeScript adapter's `ServiceRequest` methods
+    Service(Backend),
+}
+
+/// Run the Unix socket server until `shutdown` is triggered
+///
+/// Open connections then get the grace period to finish what they are
+/// running, and the socket file is removed.
 pub async fn run_server(
     socket_path: &Path,
-    storage: Arc<Storage>,
+    handlers: Handlers,
     max_connections: usize,
+    shutdown: Shutdown,
 ) -> Result<()> {
     // Remove existing socket if it exists
     if socket_path.exists() {
@@ -27,114 +54,354 @@ pub async fn run_server(
     let listener = UnixListener::bind(socket_path)?;
     info!("MPCM Server listening on {:?}", socket_path);
     
+    // Set socket permissions (readable/writable by owner only)
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))
+            .context("Failed to set socket permissions")?;
+    }
+    
     // Connection semaphore to limit concurrent connections
     let semaphore = Arc::new(tokio::sync::Semaphore::new(max_connections));
+    let mut connections = JoinSet::new();
     
     loop {
-        // Accept new connection
-        let (stream, _) = listener.accept().await?;
-        let storage = storage.clone();
-        let permit = semaphore.clone().acquire_owned().await?;
+        // Wait for a free slot, then accept new connection
+        let permit = tokio::select! {
+            permit = semaphore.clone().acquire_owned() => permit?,
+            _ = shutdown.triggered() => break,
+        };
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.triggered() => break,
+        };
+        let handlers = handlers.clone();
+        let shutdown = shutdown.clone();
         
         // Spawn handler task
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, storage).await {
+        connections.spawn(async move {
+            if let Err(e) = handle_connection(stream, handlers, shutdown).await {
                 error!("Connection error: {}", e);
             }
             drop(permit); // Release semaphore permit
         });
+        
+        // Forget connections that have finished
+        while connections.try_join_next().is_some() {}
     }
+    
+    // Stop accepting before draining
+    drop(listener);
+    shutdown
+        .bounded(async {
+            while connections.join_next().await.is_some() {}
+            Ok(())
+        })
+        .await?;
+    shutdown_server(socket_path).await
 }
 
 /// Handle a single client connection
 async fn handle_connection(
     stream: UnixStream,
-    storage: Arc<Storage>,
+    handlers: Handlers,
+    shutdown: Shutdown,
 ) -> Result<()> {
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-    let mut line = String::new();
+    let (reader, writer) = stream.into_split();
     
     debug!("New client connected");
+    match handlers {
+        Handlers::JsonRpc(server) => serve_lines(reader, writer, server, Identity::local(), shutdown).await,
+        Handlers::Service(backend) => {
+            serve_service_lines(reader, writer, backend, Some(DEFAULT_TIMEOUT), shutdown).await
+        }
+    }
+}
+
+/// Serve requests over stdin/stdout until stdin closes or `shutdown` is triggered
+///
+/// Stdout carries only responses, so logging must go to stderr.
+pub async fn run_stdio(handlers: Handlers, shutdown: Shutdown) -> Result<()> {
+    info!("MPCM Server listening on stdio");
+    let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+    let serving = async {
+        match handlers {
+            Handlers::JsonRpc(server) => serve_lines(stdin, stdout, server, Identity::local(), shutdown.clone()).await,
+            Handlers::Service(backend) => serve_service_lines(stdin, stdout, backend, None, shutdown.clone()).await,
+        }
+    };
+    shutdown.bounded(serving).await
+}
+
+/// Answer line-delimited JSON-RPC requests from `reader` on `writer`
+///
+/// Requests run concurrently and are answered as they finish. After the
+/// reader closes or `shutdown` is triggered, requests still running are
+/// finished and answered.
+pub(crate) async fn serve_lines<R, W>(
+    reader: R,
+    mut writer: W,
+    server: Arc<McpServer>,
+    mut caller: Identity,
+    shutdown: Shutdown,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+    let mut in_flight = InFlight::new(server);
+    let mut reading = true;
     
-    loop {
-        line.clear();
-        
-        // Read next line
-        match reader.read_line(&mut line).await {
-            Ok(0) => {
-                // EOF - client disconnected
-                debug!("Client disconnected");
-                break;
+    while reading || !in_flight.is_empty() {
+        let reply = tokio::select! {
+            line = lines.next_line(), if reading => match line {
+                Ok(Some(line)) if line.trim().is_empty() => None,
+                Ok(Some(line)) => in_flight.submit(line, &mut caller).await,
+                Ok(None) => {
+                    // EOF - client disconnected
+                    debug!("Client disconnected");
+                    reading = false;
+                    None
+                }
+                Err(e) => {
+                    error!("Read error: {}", e);
+                    reading = false;
+                    None
+                }
+            },
+            reply = in_flight.next(), if !in_flight.is_empty() => reply,
+            _ = shutdown.triggered(), if reading => {
+                debug!("Draining {} requests before closing", in_flight.len());
+                reading = false;
+                None
             }
-            Ok(_) => {
-                // Process request
-                let response = process_request(&line, storage.clone()).await;
-                
-                // Send response
-                let response_str = serde_json::to_string(&response)? + "\n";
-                writer.write_all(response_str.as_bytes()).await?;
-                writer.flush().await?;
+        };
+        
+        // Send response; notifications get none
+        if let Some(reply) = reply {
+            let response_str = serde_json::to_string(&reply)? + "\n";
+            writer.write_all(response_str.as_bytes()).await?;
+            writer.flush().await?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Requests running on one connection
+///
+/// A request with an id can be cancelled with `$/cancelRequest` (or MCP's
+/// `notifications/cancelled`) while it runs: its task is dropped, which
+/// kills any command it started, and it is answered with a cancellation
+/// error. Batches run as one task and cannot be cancelled.
+///
+/// An id may only be used by one running request at a time, and at most
+/// `MAX_IN_FLIGHT` requests run at once; anything past that is refused.
+pub(crate) struct InFlight {
+    server: Arc<McpServer>,
+    limit: usize,
+    tasks: JoinSet<Option<Reply>>,
+    /// Request id of each cancellable task
+    ids: HashMap<task::Id, Value>,
+    /// Cancellable tasks by the JSON text of their request id
+    handles: HashMap<String, AbortHandle>,
+}
+
+impl InFlight {
+    pub(crate) fn new(server: Arc<McpServer>) -> Self {
+        Self {
+            server,
+            limit: MAX_IN_FLIGHT,
+            tasks: JoinSet::new(),
+            ids: HashMap::new(),
+            handles: HashMap::new(),
+        }
+    }
+    
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+    
+    pub(crate) fn len(&self) -> usize {
+        self.tasks.len()
+    }
+    
+    /// Take one message off the wire
+    ///
+    /// Most requests start running in the background. Cancellations apply
+    /// at once, and `authenticate` is answered before the next message is
+    /// read, since it changes `caller` for the requests that follow it.
+    pub(crate) async fn submit(&mut self, line: String, caller: &mut Identity) -> Option<Reply> {
+        let message: Value = serde_json::from_str(&line).unwrap_or(Value::Null);
+        if matches!(message["method"].as_str(), Some(CANCEL_REQUEST | "notifications/cancelled")) {
+            self.cancel(&message["params"]);
+            return None;
+        }
+        if authenticates(&message) {
+            return process_request(&line, &self.server, caller).await;
+        }
+        if let Some(id) = message.get("id") {
+            if self.handles.contains_key(&id.to_string()) {
+                return refuse(&message, MpcmError::InvalidArgument(format!("Request id {} is already in use", id)));
             }
+        }
+        if self.tasks.len() >= self.limit {
+            return refuse(&message, MpcmError::TooManyRequests { limit: self.limit });
+        }
+        
+        let server = self.server.clone();
+        let mut caller = caller.clone();
+        let handle = self
+            .tasks
+            .spawn(async move { process_request(&line, &server, &mut caller).await });
+        if let Some(id) = message.get("id") {
+            self.ids.insert(handle.id(), id.clone());
+            self.handles.insert(id.to_string(), handle);
+        }
+        None
+    }
+    
+    /// Reply of the next request to finish; `None` if it needs none
+    pub(crate) async fn next(&mut self) -> Option<Reply> {
+        let finished = self.tasks.join_next_with_id().await?;
+        let task = match &finished {
+            Ok((task, _)) => *task,
+            Err(e) => e.id(),
+        };
+        let id = self.ids.remove(&task);
+        if let Some(id) = &id {
+            self.handles.remove(&id.to_string());
+        }
+        
+        let error = match finished {
+            Ok((_, reply)) => return reply,
+            Err(e) if e.is_cancelled() => ErrorResponse::from_error(&MpcmError::RequestCancelled.into()),
             Err(e) => {
-                error!("Read error: {}", e);
-                break;
+                error!("Request task failed: {}", e);
+                ErrorResponse::internal_error(&e.to_string())
             }
-        }
+        };
+        id.map(|id| Reply::Single(Response::error(id, error)))
     }
     
-    Ok(())
+    fn cancel(&mut self, params: &Value) {
+        let id = params.get("id").or_else(|| params.get("requestId"));
+        match id.and_then(|id| self.handles.get(&id.to_string())) {
+            Some(handle) => {
+                debug!("Cancelling request {:?}", id);
+                handle.abort();
+            }
+            None => debug!("No running request {:?} to cancel", id),
+        }
+    }
 }
 
-/// Process a single JSON-RPC request
-async fn process_request(
+/// Answer every request in `message` with `error` without running it
+fn refuse(message: &Value, error: MpcmError) -> Option<Reply> {
+    warn!("Refusing request: {}", error);
+    let error = ErrorResponse::from_error(&error.into());
+    let respond = |message: &Value| message.get("id").map(|id| Response::error(id.clone(), error.clone()));
+    match message {
+        Value::Array(batch) => Reply::batch(batch.iter().filter_map(respond).collect()),
+        message => respond(message).map(Reply::Single),
+    }
+}
+
+/// Whether a message holds an `authenticate` request
+fn authenticates(message: &Value) -> bool {
+    match message {
+        Value::Array(batch) => batch.iter().any(authenticates),
+        message => message["method"] == "authenticate",
+    }
+}
+
+/// Process one line: a JSON-RPC request or a batch of them
+///
+/// Requests without an `id` are notifications and get no response; a batch
+/// of only notifications gets nothing back at all. `authenticate` changes
+/// who `caller` is for the rest of the connection.
+pub(crate) async fn process_request(
     line: &str,
-    storage: Arc<Storage>,
-) -> Response {
-    // Parse request
-    let request: Request = match serde_json::from_str(line) {
-        Ok(req) => req,
-        Err(e) => {
-            return Response {
-                id: None,
-                result: None,
-                error: Some(ErrorResponse::parse_error(&e.to_string())),
-            };
+    server: &McpServer,
+    caller: &mut Identity,
+) -> Option<Reply> {
+    match Incoming::parse(line) {
+        Ok(Incoming::Single(message)) => match Request::from_value(message) {
+            Ok(request) => process_one(request, server, caller).await.map(Reply::Single),
+            Err(response) => Some(Reply::Single(*response)),
+        },
+        Ok(Incoming::Batch(batch)) => Reply::batch(process_batch(batch, server, caller).await),
+        Err(response) => Some(Reply::Single(*response)),
+    }
+}
+
+/// Run a batch, overlapping consecutive reads
+///
+/// Anything that writes waits for the reads before it and runs alone, so
+/// a batch observes its own writes in order. Responses keep batch order.
+async fn process_batch(batch: Vec<Value>, server: &McpServer, caller: &mut Identity) -> Vec<Response> {
+    let mut responses = Vec::new();
+    let mut reads = Vec::new();
+    for message in batch {
+        match Request::from_value(message) {
+            Ok(request) if !is_read_only(&request.method, request.params.as_ref()) => {
+                responses.extend(process_reads(std::mem::take(&mut reads), server, caller).await);
+                responses.extend(process_one(request, server, caller).await);
+            }
+            parsed => reads.push(parsed),
         }
+    }
+    responses.extend(process_reads(reads, server, caller).await);
+    responses
+}
+
+async fn process_reads(
+    reads: Vec<std::result::Result<Request, Box<Response>>>,
+    server: &McpServer,
+    caller: &Identity,
+) -> Vec<Response> {
+    let pending = reads.into_iter().map(|parsed| async move {
+        match parsed {
+            Ok(request) => {
+                let result = execute(&request, server, caller).await;
+                request.respond(result)
+            }
+            Err(response) => Some(*response),
+        }
+    });
+    join_all(pending).await.into_iter().flatten().collect()
+}
+
+async fn process_one(request: Request, server: &McpServer, caller: &mut Identity) -> Option<Response> {
+    let result = if request.method == "authenticate" {
+        let params = request.params.clone().unwrap_or(Value::Null);
+        server.handle_authenticate(caller, params).await
+    } else {
+        execute(&request, server, caller).await
     };
+    request.respond(result)
+}
+
+/// Run a request, failing it once its deadline passes
+///
+/// Clients set a deadline with `params._meta.timeoutMs`; other requests get
+/// the server's. Work still running at the deadline is dropped.
+async fn execute(request: &Request, server: &McpServer, caller: &Identity) -> Result<Value> {
+    let params = request.params.clone().unwrap_or(Value::Null);
+    let deadline = params
+        .pointer("/_meta/timeoutMs")
+        .and_then(Value::as_u64)
+        .map(Duration::from_millis)
+        .unwrap_or_else(|| server.request_timeout());
     
-    let request_id = request.id.clone();
-    
-    // Handle request
-    match handlers_v2::handle_request(
-        &request.method,
-        request.params.unwrap_or(Value::Null),
-        storage,
-    ).await {
-        Ok(result) => Response {
-            id: request_id,
-            result: Some(result),
-            error: None,
-        },
-        Err(e) => {
-            let error_response = if e.to_string().contains("not found") {
-                ErrorResponse {
-                    code: handlers_v2::error_codes::METHOD_NOT_FOUND,
-                    message: e.to_string(),
-                }
-            } else {
-                ErrorResponse {
-                    code: handlers_v2::error_codes::INTERNAL_ERROR,
-                    message: e.to_string(),
-                }
-            };
-            
-            Response {
-                id: request_id,
-                result: None,
-                error: Some(error_response),
-            }
+    match tokio::time::timeout(deadline, server.handle_request(caller, &request.method, params)).await {
+        Ok(result) => result,
+        Err(_) => Err(MpcmError::DeadlineExceeded {
+            timeout_ms: deadline.as_millis() as u64,
         }
+        .into()),
     }
 }
 
@@ -149,3 +416,233 @@ pub async fn shutdown_server(socket_path: &Path) -> Result<()> {
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mpcm_core::adapters::TerminalAdapter;
+    use mpcm_core::registry::{RequestRouter, ServiceRegistry};
+    use mpcm_core::storage_v2::Storage;
+    use serde_json::json;
+    use tempfile::TempDir;
+    
+    #[tokio::test]
+    async fn test_socket_cleanup() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("test.sock");
+        
+        // Create a dummy file
+        std::fs::write(&socket_path, "dummy").unwrap();
+        assert!(socket_path.exists());
+        
+        // Shutdown should remove it
+        shutdown_server(&socket_path).await.unwrap();
+        assert!(!socket_path.exists());
+    }
+    
+    #[tokio::test]
+    async fn test_serve_lines_answers_requests_but_not_notifications() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Arc::new(Storage::new(temp_dir.path().join("lines.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let server = McpServer::new(storage, registry.clone(), RequestRouter::new(registry));
+        
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-03-26"}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            "",
+            r#"{"jsonrpc":"2.0","id":"two","method":"list_projects","params":{}}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        serve_lines(input.as_bytes(), &mut output, Arc::new(server), Identity::local(), Shutdown::default()).await.unwrap();
+        
+        // Requests run concurrently, so answers may come in any order
+        let mut responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        responses.sort_by_key(|r| r["id"].is_string());
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["jsonrpc"], "2.0");
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2025-03-26");
+        assert_eq!(responses[1]["id"], "two");
+        assert!(responses[1]["result"].is_array());
+    }
+    
+    #[tokio::test]
+    async fn test_batches_and_null_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Arc::new(Storage::new(temp_dir.path().join("batch.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let server = McpServer::new(storage, registry.clone(), RequestRouter::new(registry));
+        let mut caller = Identity::local();
+        
+        // Reads after a write in the same batch see it
+        let batch = json!([
+            {"jsonrpc": "2.0", "id": 1, "method": "store_context",
+             "params": {"project_name": "alpha", "key": "k", "type": "note", "value": "batched"}},
+            {"jsonrpc": "2.0", "id": 2, "method": "list_projects"},
+            {"jsonrpc": "2.0", "method": "ping"},
+            {"jsonrpc": "2.0", "id": 3, "method": "search_context", "params": {"query": "batched"}},
+            {"foo": "bar"},
+        ]);
+        let reply = process_request(&batch.to_string(), &server, &mut caller).await.unwrap();
+        let replies = serde_json::to_value(reply).unwrap();
+        let replies = replies.as_array().unwrap();
+        assert_eq!(replies.len(), 4);
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(replies[1]["result"][0]["name"], "alpha");
+        assert_eq!(replies[2]["result"].as_array().unwrap().len(), 1);
+        assert_eq!(replies[3]["id"], Value::Null);
+        assert_eq!(replies[3]["error"]["code"], -32600);
+        
+        let notifications = r#"[{"jsonrpc":"2.0","method":"ping"},{"jsonrpc":"2.0","method":"list_projects"}]"#;
+        assert!(process_request(notifications, &server, &mut caller).await.is_none());
+        
+        let reply = process_request(r#"{"jsonrpc":"2.0","id":null,"method":"ping"}"#, &server, &mut caller)
+            .await
+            .unwrap();
+        let reply = serde_json::to_value(reply).unwrap();
+        assert_eq!(reply["id"], Value::Null);
+        assert_eq!(reply["result"], json!({}));
+    }
+    
+    async fn send<W: AsyncWrite + Unpin>(writer: &mut W, message: Value) {
+        let line = message.to_string() + "\n";
+        writer.write_all(line.as_bytes()).await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_requests_can_be_cancelled_or_time_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Arc::new(Storage::new(temp_dir.path().join("cancel.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let mut terminal = TerminalAdapter::new(temp_dir.path());
+        terminal.allow_command("sleep");
+        registry.register(Box::new(terminal)).await.unwrap();
+        let server = McpServer::new(storage, registry.clone(), RequestRouter::new(registry));
+        
+        let (client, connection) = tokio::io::duplex(4096);
+        let (reader, writer) = tokio::io::split(connection);
+        let serving = tokio::spawn(serve_lines(reader, writer, Arc::new(server), Identity::local(), Shutdown::default()));
+        let (client_reader, mut client_writer) = tokio::io::split(client);
+        let mut replies = BufReader::new(client_reader).lines();
+        let sleep = |command: &str| json!({ "name": "execute", "arguments": { "command": command } });
+        send(&mut client_writer, json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": sleep("sleep 1; touch ran") })).await;
+        send(&mut client_writer, json!({ "jsonrpc": "2.0", "id": 2, "method": "ping" })).await;
+        
+        // The ping overtakes the running command
+        let reply: Value = serde_json::from_str(&replies.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["id"], 2);
+        
+        send(&mut client_writer, json!({ "jsonrpc": "2.0", "method": CANCEL_REQUEST, "params": { "id": 1 } })).await;
+        let reply: Value = serde_json::from_str(&replies.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["error"]["code"], -32800);
+        
+        let mut params = sleep("sleep 5");
+        params["_meta"] = json!({ "timeoutMs": 100 });
+        send(&mut client_writer, json!({ "jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": params })).await;
+        let reply: Value = serde_json::from_str(&replies.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["id"], 3);
+        assert_eq!(reply["error"]["code"], 1301);
+        assert_eq!(reply["error"]["data"]["timeout_ms"], 100);
+        
+        client_writer.shutdown().await.unwrap();
+        serving.await.unwrap().unwrap();
+        
+        // Cancelling killed the command before it got to `touch`
+        tokio::time::sleep(Duration::from_millis(1200)).await;
+        assert!(!temp_dir.path().join("ran").exists());
+    }
+    
+    #[tokio::test]
+    async fn test_duplicate_ids_and_excess_requests_are_refused() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Arc::new(Storage::new(temp_dir.path().join("limits.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let mut terminal = TerminalAdapter::new(temp_dir.path());
+        terminal.allow_command("sleep");
+        registry.register(Box::new(terminal)).await.unwrap();
+        let server = McpServer::new(storage, registry.clone(), RequestRouter::new(registry));
+        
+        let mut in_flight = InFlight::new(Arc::new(server));
+        in_flight.limit = 2;
+        let mut caller = Identity::local();
+        let sleep = |id: Value| {
+            json!({ "jsonrpc": "2.0", "id": id, "method": "tools/call",
+                    "params": { "name": "execute", "arguments": { "command": "sleep 0.3" } } })
+            .to_string()
+        };
+        assert!(in_flight.submit(sleep(json!(1)), &mut caller).await.is_none());
+        
+        // The first request keeps its id and keeps running
+        let reply = serde_json::to_value(in_flight.submit(sleep(json!(1)), &mut caller).await.unwrap()).unwrap();
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["error"]["code"], -32602);
+        assert_eq!(in_flight.len(), 1);
+        
+        assert!(in_flight.submit(sleep(json!(2)), &mut caller).await.is_none());
+        let reply = serde_json::to_value(in_flight.submit(sleep(json!(3)), &mut caller).await.unwrap()).unwrap();
+        assert_eq!(reply["id"], 3);
+        assert_eq!(reply["error"]["code"], 1302);
+        assert_eq!(reply["error"]["data"]["limit"], 2);
+        let batch = format!("[{}]", sleep(json!(4)));
+        let reply = serde_json::to_value(in_flight.submit(batch, &mut caller).await.unwrap()).unwrap();
+        assert_eq!(reply[0]["error"]["code"], 1302);
+        
+        // Both running requests finish, and their ids are free again
+        let mut ids = Vec::new();
+        while !in_flight.is_empty() {
+            let reply = serde_json::to_value(in_flight.next().await.unwrap()).unwrap();
+            assert!(reply["result"].is_object());
+            ids.push(reply["id"].clone());
+        }
+        ids.sort_by_key(|id| id.as_i64());
+        assert_eq!(ids, [json!(1), json!(2)]);
+        assert!(in_flight.submit(sleep(json!(1)), &mut caller).await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_shutdown_drains_requests_and_removes_socket() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("drain.sock");
+        let storage = Arc::new(Storage::new(temp_dir.path().join("drain.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let mut terminal = TerminalAdapter::new(temp_dir.path());
+        terminal.allow_command("sleep");
+        registry.register(Box::new(terminal)).await.unwrap();
+        let server = McpServer::new(storage, registry.clone(), RequestRouter::new(registry));
+        
+        let shutdown = Shutdown::new(Duration::from_secs(5));
+        let serving = tokio::spawn({
+            let socket_path = socket_path.clone();
+            let shutdown = shutdown.clone();
+            async move { run_server(&socket_path, Handlers::JsonRpc(Arc::new(server)), 4, shutdown).await }
+        });
+        while !socket_path.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        
+        let stream = tokio::net::UnixStream::connect(&socket_path).await.unwrap();
+        let (client_reader, mut client_writer) = stream.into_split();
+        let mut replies = BufReader::new(client_reader).lines();
+        let params = json!({ "name": "execute", "arguments": { "command": "sleep 0.3" } });
+        send(&mut client_writer, json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": params })).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.trigger();
+        
+        // The running request is still answered, then the connection closes
+        let reply: Value = serde_json::from_str(&replies.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["id"], 1);
+        assert!(reply["result"].is_object());
+        assert!(replies.next_line().await.unwrap().is_none());
+        
+        serving.await.unwrap().unwrap();
+        assert!(!socket_path.exists());
+        assert!(tokio::net::UnixStream::connect(&socket_path).await.is_err());
+    }
+}
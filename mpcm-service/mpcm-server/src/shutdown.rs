@@ -0,0 +1,114 @@
+//! Graceful shutdown
+//!
+//! Every transport is handed the same `Shutdown`. Once it is triggered,
+//! listeners stop accepting, connections stop reading new requests and
+//! finish the ones already running, and whatever is still busy when the
+//! grace period runs out is dropped.
+
+use anyhow::Result;
+use std::future::Future;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// How long in-flight requests may take to finish unless configured
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Shared shutdown signal with the grace period for draining
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    token: CancellationToken,
+    grace: Duration,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new(DEFAULT_GRACE_PERIOD)
+    }
+}
+
+impl Shutdown {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            token: CancellationToken::new(),
+            grace,
+        }
+    }
+
+    /// Start shutting down; later calls do nothing
+    pub fn trigger(&self) {
+        if !self.token.is_cancelled() {
+            info!("Shutting down, draining for up to {:?}", self.grace);
+            self.token.cancel();
+        }
+    }
+
+    /// Resolves once shutdown has been triggered
+    pub async fn triggered(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Resolves once the grace period after the trigger has passed
+    pub async fn expired(&self) {
+        self.triggered().await;
+        tokio::time::sleep(self.grace).await
+    }
+
+    /// Run `serving` until it finishes or the grace period runs out
+    ///
+    /// Dropping `serving` on expiry drops the connections it holds.
+    pub async fn bounded<F>(&self, serving: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        tokio::select! {
+            result = serving => result,
+            _ = self.expired() => {
+                warn!("Connections still busy {:?} after shutdown, closing them", self.grace);
+                Ok(())
+            }
+        }
+    }
+
+    /// Trigger on the first SIGINT or SIGTERM
+    pub fn trigger_on_signal(&self) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            let mut terminate = match signal(SignalKind::terminate()) {
+                Ok(terminate) => terminate,
+                Err(e) => {
+                    error!("Failed to listen for SIGTERM: {}", e);
+                    return;
+                }
+            };
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => info!("Received SIGINT"),
+                _ = terminate.recv() => info!("Received SIGTERM"),
+            }
+            shutdown.trigger();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bounded_gives_up_after_grace_period() {
+        let shutdown = Shutdown::new(Duration::from_millis(50));
+
+        // Finishing work is waited for
+        shutdown.trigger();
+        let finished = shutdown.bounded(async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok(())
+        });
+        assert!(tokio::time::timeout(Duration::from_secs(1), finished).await.is_ok());
+
+        // Stuck work is dropped once the grace period is over
+        let stuck = shutdown.bounded(std::future::pending());
+        assert!(tokio::time::timeout(Duration::from_secs(1), stuck).await.unwrap().is_ok());
+    }
+}
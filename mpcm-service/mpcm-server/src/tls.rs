@@ -0,0 +1,364 @@
+//! TCP listener secured with TLS
+//!
+//! Speaks the same line-delimited JSON-RPC as the Unix socket. With a client
+//! CA configured every client must present a certificate signed by it, and
+//! the certificate's common name decides the tenant and role of the
+//! connection.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, error, info, warn};
+
+use crate::auth::{Identity, Role};
+use crate::mcp::McpServer;
+use crate::server_v2::serve_lines;
+use crate::shutdown::Shutdown;
+
+/// Clients that have not finished the handshake by then are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Files the TLS listener is built from
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    /// PEM certificate chain presented to clients
+    pub cert: PathBuf,
+    /// PEM private key of the certificate
+    pub key: PathBuf,
+    /// PEM CA bundle client certificates must chain to; enables mutual TLS
+    pub client_ca: Option<PathBuf>,
+    /// JSON object mapping client certificate common names to grants
+    pub client_map: Option<PathBuf>,
+}
+
+/// Tenant and role given to a client certificate
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClientGrant {
+    #[serde(default)]
+    pub tenant: Option<String>,
+    pub role: Role,
+}
+
+/// Accepts TLS connections and works out who is calling
+pub struct TlsListener {
+    acceptor: TlsAcceptor,
+    mutual: bool,
+    /// Only these common names may connect, if set; otherwise any
+    /// certificate from the CA connects as an anonymous caller
+    clients: Option<HashMap<String, ClientGrant>>,
+}
+
+impl TlsListener {
+    /// Load the certificate, key and client settings
+    pub fn load(options: &TlsOptions) -> Result<Self> {
+        let certs = CertificateDer::pem_file_iter(&options.cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .with_context(|| format!("Failed to read certificates from {:?}", options.cert))?;
+        let key = PrivateKeyDer::from_pem_file(&options.key)
+            .with_context(|| format!("Failed to read private key from {:?}", options.key))?;
+
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone()).with_safe_default_protocol_versions()?;
+        let builder = match &options.client_ca {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in CertificateDer::pem_file_iter(path)
+                    .with_context(|| format!("Failed to read client CA from {:?}", path))?
+                {
+                    roots.add(cert?)?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder.with_single_cert(certs, key)?;
+
+        let clients = match &options.client_map {
+            Some(_) if options.client_ca.is_none() => bail!("A client map needs a client CA to verify certificates"),
+            Some(path) => Some(load_client_map(path)?),
+            None => None,
+        };
+
+        Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            mutual: options.client_ca.is_some(),
+            clients,
+        })
+    }
+
+    /// Identity of a client that completed the handshake
+    fn identify(&self, certs: Option<&[CertificateDer<'_>]>) -> Result<Identity> {
+        if !self.mutual {
+            // Anyone who can reach the port, until they authenticate with a token
+            return Ok(Identity::anonymous());
+        }
+
+        let cert = certs
+            .and_then(|certs| certs.first())
+            .ok_or_else(|| anyhow!("Client sent no certificate"))?;
+        let name = common_name(cert)?;
+        let Some(clients) = &self.clients else {
+            // The CA vouches for who they are, not for what they may do
+            return Ok(Identity {
+                name: Some(name),
+                ..Identity::anonymous()
+            });
+        };
+        let grant = clients
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| anyhow!("No grant for client certificate '{}'", name))?;
+
+        Ok(Identity {
+            name: Some(name),
+            tenant: grant.tenant,
+            role: grant.role,
+            authenticated: true,
+        })
+    }
+}
+
+fn load_client_map(path: &Path) -> Result<HashMap<String, ClientGrant>> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read client map {:?}", path))?;
+    serde_json::from_str(&raw).with_context(|| format!("Invalid client map {:?}", path))
+}
+
+/// Subject common name of a DER certificate
+fn common_name(cert: &CertificateDer<'_>) -> Result<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert.as_ref())
+        .map_err(|e| anyhow!("Invalid client certificate: {}", e))?;
+    let name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .ok_or_else(|| anyhow!("Client certificate has no common name"))?;
+    Ok(name.to_string())
+}
+
+/// Serve TLS clients on `addr`
+pub async fn run_tls(
+    addr: SocketAddr,
+    tls: TlsListener,
+    server: Arc<McpServer>,
+    max_connections: usize,
+    shutdown: Shutdown,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!(
+        "MPCM Server listening on {} (TLS{})",
+        listener.local_addr()?,
+        if tls.mutual { ", client certificates required" } else { "" }
+    );
+    serve(listener, Arc::new(tls), server, max_connections, shutdown).await
+}
+
+async fn serve(
+    listener: TcpListener,
+    tls: Arc<TlsListener>,
+    server: Arc<McpServer>,
+    max_connections: usize,
+    shutdown: Shutdown,
+) -> Result<()> {
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(max_connections));
+    let mut connections = JoinSet::new();
+
+    loop {
+        // Wait for a free slot, then accept; clients past the limit stay in the backlog
+        let permit = tokio::select! {
+            permit = semaphore.clone().acquire_owned() => permit?,
+            _ = shutdown.triggered() => break,
+        };
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.triggered() => break,
+        };
+        let tls = tls.clone();
+        let server = server.clone();
+        let shutdown = shutdown.clone();
+
+        connections.spawn(async move {
+            if let Err(e) = handle_connection(stream, peer, &tls, server, shutdown).await {
+                error!("TLS connection error from {}: {}", peer, e);
+            }
+            drop(permit);
+        });
+        while connections.try_join_next().is_some() {}
+    }
+
+    drop(listener);
+    shutdown
+        .bounded(async {
+            while connections.join_next().await.is_some() {}
+            Ok(())
+        })
+        .await
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    tls: &TlsListener,
+    server: Arc<McpServer>,
+    shutdown: Shutdown,
+) -> Result<()> {
+    let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.acceptor.accept(stream))
+        .await
+        .map_err(|_| anyhow!("TLS handshake timed out"))??;
+
+    let identity = match tls.identify(stream.get_ref().1.peer_certificates()) {
+        Ok(identity) => identity,
+        Err(e) => {
+            warn!("Rejecting TLS client {}: {}", peer, e);
+            return Ok(());
+        }
+    };
+    debug!(
+        "TLS client {} connected as {:?} (tenant {:?}, role {})",
+        peer,
+        identity.name,
+        identity.tenant,
+        identity.role.as_str()
+    );
+
+    let (reader, writer) = tokio::io::split(stream);
+    serve_lines(reader, writer, server, identity, shutdown).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mpcm_core::registry::{RequestRouter, ServiceRegistry};
+    use mpcm_core::storage_v2::Storage;
+    use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair};
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::TlsConnector;
+
+    /// A CA, a server certificate for localhost and client certificates per name
+    struct Pki {
+        ca: CertifiedIssuer<'static, KeyPair>,
+    }
+
+    impl Pki {
+        fn new() -> Self {
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params.distinguished_name.push(DnType::CommonName, "test ca");
+            Self {
+                ca: CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap(),
+            }
+        }
+
+        /// Certificate and key PEM for `common_name`
+        fn issue(&self, common_name: &str) -> (String, String) {
+            let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+            params.distinguished_name.push(DnType::CommonName, common_name);
+            let key = KeyPair::generate().unwrap();
+            let cert = params.signed_by(&key, &self.ca).unwrap();
+            (cert.pem(), key.serialize_pem())
+        }
+    }
+
+    async fn start(temp_dir: &TempDir, pki: &Pki) -> SocketAddr {
+        let write = |name: &str, contents: &str| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        };
+        let (cert, key) = pki.issue("localhost");
+        let options = TlsOptions {
+            cert: write("server.pem", &cert),
+            key: write("server.key", &key),
+            client_ca: Some(write("ca.pem", &pki.ca.pem())),
+            client_map: Some(write(
+                "clients.json",
+                &json!({ "alice": { "tenant": "team-a", "role": "read_only" } }).to_string(),
+            )),
+        };
+        let tls = Arc::new(TlsListener::load(&options).unwrap());
+
+        let storage = Arc::new(Storage::new(temp_dir.path().join("tls.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let server = Arc::new(McpServer::new(storage, registry.clone(), RequestRouter::new(registry)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, tls, server, 4, Shutdown::default()));
+        addr
+    }
+
+    /// Connect with a certificate for `client` issued by `issuer` and send one
+    /// request; `None` if the server hung up
+    async fn call(addr: SocketAddr, pki: &Pki, issuer: &Pki, client: &str, request: Value) -> Option<Value> {
+        let mut roots = RootCertStore::empty();
+        roots.add(pki.ca.der().clone()).unwrap();
+        let (cert, key) = issuer.issue(client);
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(
+                vec![CertificateDer::from_pem_slice(cert.as_bytes()).unwrap()],
+                PrivateKeyDer::from_pem_slice(key.as_bytes()).unwrap(),
+            )
+            .unwrap();
+
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .ok()?;
+        stream.write_all(format!("{}\n", request).as_bytes()).await.ok()?;
+
+        let mut line = String::new();
+        match BufReader::new(stream).read_line(&mut line).await {
+            Ok(n) if n > 0 => Some(serde_json::from_str(&line).unwrap()),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_certificates_map_to_roles() {
+        let temp_dir = TempDir::new().unwrap();
+        let pki = Pki::new();
+        let addr = start(&temp_dir, &pki).await;
+
+        let list = json!({ "jsonrpc": "2.0", "id": 1, "method": "list_projects", "params": {} });
+        let response = call(addr, &pki, &pki, "alice", list.clone()).await.unwrap();
+        assert!(response["result"].is_array());
+
+        // alice is read-only
+        let store = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "store_context",
+            "params": { "project_name": "p", "key": "k", "type": "note", "value": "v" },
+        });
+        let response = call(addr, &pki, &pki, "alice", store).await.unwrap();
+        assert_eq!(response["error"]["code"], mpcm_core::codes::PERMISSION_DENIED);
+
+        // Signed by the CA but not in the client map
+        assert!(call(addr, &pki, &pki, "mallory", list.clone()).await.is_none());
+
+        // Signed by a different CA
+        assert!(call(addr, &pki, &Pki::new(), "alice", list).await.is_none());
+    }
+}
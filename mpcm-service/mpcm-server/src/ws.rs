@@ -0,0 +1,448 @@
+//! WebSocket transport
+//!
+//! Each text frame carries one JSON-RPC message, exactly as a line does on
+//! the Unix socket. On top of that a connection can `subscribe` to changes,
+//! optionally for one project, and receives `notifications/context_changed`
+//! for every matching write until it unsubscribes or disconnects.
+//!
+//! Clients authenticate with an `Authorization: Bearer` header on the
+//! upgrade request, a `token` query parameter (browsers cannot set headers
+//! on WebSockets), or an `authenticate` call once connected.
+
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response as HttpResponse};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info, warn};
+
+use crate::auth::Identity;
+use crate::mcp::{ChangeEvent, McpServer};
+use crate::handlers_v2::parse_params;
+use crate::protocol::{ErrorResponse, Reply, Request, Response};
+use crate::server_v2::InFlight;
+use crate::shutdown::Shutdown;
+use mpcm_core::MpcmError;
+
+/// How often the server pings each client
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Connections silent for this long are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Shared state of the WebSocket listener
+#[derive(Clone)]
+pub struct WsState {
+    server: Arc<McpServer>,
+    connections: Arc<Semaphore>,
+    shutdown: Shutdown,
+}
+
+impl WsState {
+    pub fn new(server: Arc<McpServer>, max_connections: usize, shutdown: Shutdown) -> Self {
+        Self {
+            server,
+            connections: Arc::new(Semaphore::new(max_connections)),
+            shutdown,
+        }
+    }
+}
+
+/// Routes of the WebSocket transport
+pub fn router(state: WsState) -> Router {
+    Router::new().route("/ws", get(upgrade)).with_state(state)
+}
+
+/// Serve the WebSocket transport on `addr`
+///
+/// Returns once shutdown has been triggered and every connection has
+/// finished its requests and closed.
+pub async fn run_websocket(
+    addr: SocketAddr,
+    server: Arc<McpServer>,
+    max_connections: usize,
+    shutdown: Shutdown,
+) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("MPCM Server listening on ws://{}/ws", listener.local_addr()?);
+
+    let state = WsState::new(server, max_connections, shutdown.clone());
+    let connections = state.connections.clone();
+    let signal = shutdown.clone();
+    shutdown
+        .bounded(async move {
+            axum::serve(listener, router(state))
+                .with_graceful_shutdown(async move { signal.triggered().await })
+                .await?;
+            // Upgraded sockets outlive the listener; each holds a slot until it closes
+            let _all = connections.acquire_many(max_connections as u32).await?;
+            Ok(())
+        })
+        .await
+}
+
+/// Upgrade query parameters
+#[derive(Debug, Default, Deserialize)]
+struct UpgradeQuery {
+    token: Option<String>,
+}
+
+async fn upgrade(
+    State(state): State<WsState>,
+    Query(query): Query<UpgradeQuery>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> HttpResponse {
+    let bearer = query
+        .token
+        .map(|token| format!("Bearer {}", token))
+        .or_else(|| headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()).map(str::to_string));
+    let caller = match state.server.authenticate_bearer(bearer.as_deref()).await {
+        Ok(caller) => caller,
+        Err(e) => {
+            let response = Response::error(Value::Null, ErrorResponse::from_error(&e));
+            return (StatusCode::UNAUTHORIZED, Json(response)).into_response();
+        }
+    };
+
+    let Ok(permit) = state.connections.clone().try_acquire_owned() else {
+        warn!("Rejecting WebSocket connection: connection limit reached");
+        return (StatusCode::SERVICE_UNAVAILABLE, "Too many connections").into_response();
+    };
+
+    ws.on_upgrade(move |socket| async move {
+        if let Err(e) = handle_socket(socket, state.server, caller, permit, state.shutdown).await {
+            debug!("WebSocket connection ended with error: {}", e);
+        }
+    })
+}
+
+/// Subscribe parameters
+#[derive(Debug, Default, Deserialize)]
+struct SubscribeParams {
+    /// Only changes to this project; every project if unset
+    project_name: Option<String>,
+}
+
+/// Unsubscribe parameters
+#[derive(Debug, Deserialize)]
+struct UnsubscribeParams {
+    subscription_id: u64,
+}
+
+/// Change subscriptions held by one connection
+#[derive(Default)]
+struct Subscriptions {
+    next_id: u64,
+    filters: HashMap<u64, Option<String>>,
+}
+
+impl Subscriptions {
+    fn add(&mut self, project_name: Option<String>) -> u64 {
+        self.next_id += 1;
+        self.filters.insert(self.next_id, project_name);
+        self.next_id
+    }
+
+    /// Notifications for every subscription matching `event`
+    ///
+    /// Callers only hear about changes to projects they can access.
+    fn notifications(&self, event: &ChangeEvent, caller: &Identity) -> Vec<Value> {
+        if !caller.can_access(event.tenant.as_deref()) {
+            return Vec::new();
+        }
+        self.filters
+            .iter()
+            .filter(|(_, project)| project.is_none() || *project == &event.project_name)
+            .map(|(id, _)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "notifications/context_changed",
+                    "params": {
+                        "subscription_id": id,
+                        "method": event.method,
+                        "project_name": event.project_name,
+                        "key": event.key,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+async fn handle_socket(
+    mut socket: WebSocket,
+    server: Arc<McpServer>,
+    mut caller: Identity,
+    _permit: OwnedSemaphorePermit,
+    shutdown: Shutdown,
+) -> Result<()> {
+    debug!("WebSocket client connected");
+
+    let mut subscriptions = Subscriptions::default();
+    let mut changes = server.subscribe_changes();
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    let mut last_heard = Instant::now();
+    let mut in_flight = InFlight::new(server.clone());
+    let mut reading = true;
+
+    loop {
+        if !reading && in_flight.is_empty() {
+            socket.send(Message::Close(None)).await?;
+            break;
+        }
+
+        tokio::select! {
+            _ = shutdown.triggered(), if reading => {
+                debug!("Draining {} WebSocket requests before closing", in_flight.len());
+                reading = false;
+            }
+            message = socket.recv(), if reading => {
+                let Some(message) = message else { break };
+                last_heard = Instant::now();
+
+                match message? {
+                    Message::Text(text) => {
+                        let reply = match local_request(&text) {
+                            Some(request) => {
+                                handle_local(request, &server, &caller, &mut subscriptions).await.map(Reply::Single)
+                            }
+                            None => in_flight.submit(text.to_string(), &mut caller).await,
+                        };
+                        if let Some(reply) = reply {
+                            socket.send(Message::text(serde_json::to_string(&reply)?)).await?;
+                        }
+                    }
+                    Message::Ping(payload) => socket.send(Message::Pong(payload)).await?,
+                    Message::Close(_) => break,
+                    Message::Pong(_) | Message::Binary(_) => {}
+                }
+            }
+            reply = in_flight.next(), if !in_flight.is_empty() => {
+                if let Some(reply) = reply {
+                    socket.send(Message::text(serde_json::to_string(&reply)?)).await?;
+                }
+            }
+            change = changes.recv() => {
+                let event = match change {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("WebSocket client missed {} change events", missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                for notification in subscriptions.notifications(&event, &caller) {
+                    socket.send(Message::text(notification.to_string())).await?;
+                }
+            }
+            _ = ping.tick() => {
+                if last_heard.elapsed() > IDLE_TIMEOUT {
+                    info!("Closing idle WebSocket connection");
+                    break;
+                }
+                socket.send(Message::Ping(Default::default())).await?;
+            }
+        }
+    }
+
+    debug!("WebSocket client disconnected");
+    Ok(())
+}
+
+/// The request if it is one of the connection-level methods
+///
+/// Anything else, including malformed messages and batches, goes to the
+/// shared handler.
+fn local_request(text: &str) -> Option<Request> {
+    let request = Request::from_value(serde_json::from_str(text).ok()?).ok()?;
+    matches!(request.method.as_str(), "subscribe" | "unsubscribe").then_some(request)
+}
+
+/// Answer `subscribe` or `unsubscribe`
+async fn handle_local(
+    request: Request,
+    server: &McpServer,
+    caller: &Identity,
+    subscriptions: &mut Subscriptions,
+) -> Option<Response> {
+    let params = request.params.clone().unwrap_or(Value::Null);
+    let result: Result<Value> = async {
+        if request.method == "subscribe" {
+            let params: SubscribeParams = parse_params(params)?;
+            server.authorize_local(caller, &request.method, params.project_name.as_deref()).await?;
+            Ok(json!({ "subscription_id": subscriptions.add(params.project_name) }))
+        } else {
+            server.authorize_local(caller, &request.method, None).await?;
+            let params: UnsubscribeParams = parse_params(params)?;
+            if subscriptions.filters.remove(&params.subscription_id).is_none() {
+                return Err(MpcmError::InvalidArgument(format!("Unknown subscription: {}", params.subscription_id)).into());
+            }
+            Ok(json!({ "success": true }))
+        }
+    }
+    .await;
+    request.respond(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use mpcm_core::registry::{RequestRouter, ServiceRegistry};
+    use mpcm_core::storage_v2::Storage;
+    use tempfile::TempDir;
+    use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+    async fn start(temp_dir: &TempDir, max_connections: usize) -> SocketAddr {
+        start_with(temp_dir, max_connections, false).await.0
+    }
+
+    async fn start_with(temp_dir: &TempDir, max_connections: usize, require_token: bool) -> (SocketAddr, Arc<Storage>) {
+        let storage = Arc::new(Storage::new(temp_dir.path().join("ws.db")).await.unwrap());
+        let registry = Arc::new(ServiceRegistry::new(60));
+        let mut server = McpServer::new(storage.clone(), registry.clone(), RequestRouter::new(registry));
+        server.set_require_token(require_token);
+        let server = Arc::new(server);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router(WsState::new(server, max_connections, Shutdown::default()))).await.unwrap();
+        });
+        (addr, storage)
+    }
+
+    async fn next_json<S>(client: &mut S) -> Value
+    where
+        S: futures::Stream<Item = Result<ClientMessage, tokio_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        loop {
+            if let ClientMessage::Text(text) = client.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_and_subscriptions() {
+        let temp_dir = TempDir::new().unwrap();
+        let addr = start(&temp_dir, 4).await;
+        let url = format!("ws://{}/ws", addr);
+
+        let (mut watcher, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let (mut writer, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+
+        watcher
+            .send(ClientMessage::text(r#"{"jsonrpc":"2.0","id":1,"method":"subscribe","params":{"project_name":"alpha"}}"#))
+            .await
+            .unwrap();
+        let subscribed = next_json(&mut watcher).await;
+        let subscription_id = subscribed["result"]["subscription_id"].clone();
+
+        for project in ["beta", "alpha"] {
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": project,
+                "method": "store_context",
+                "params": { "project_name": project, "key": "k", "type": "note", "value": "v" },
+            });
+            writer.send(ClientMessage::text(request.to_string())).await.unwrap();
+            let response = next_json(&mut writer).await;
+            assert_eq!(response["id"], project);
+            assert!(response["error"].is_null());
+        }
+
+        // Only the alpha write matches the subscription
+        let notification = next_json(&mut watcher).await;
+        assert_eq!(notification["method"], "notifications/context_changed");
+        assert_eq!(notification["params"]["subscription_id"], subscription_id);
+        assert_eq!(notification["params"]["project_name"], "alpha");
+
+        watcher
+            .send(ClientMessage::Ping(b"hi".to_vec().into()))
+            .await
+            .unwrap();
+        match watcher.next().await.unwrap().unwrap() {
+            ClientMessage::Pong(payload) => assert_eq!(&payload[..], b"hi"),
+            other => panic!("expected pong, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_are_authorized_and_scoped() {
+        let temp_dir = TempDir::new().unwrap();
+        let (addr, storage) = start_with(&temp_dir, 4, true).await;
+        let team_a = storage.issue_token("a", Some("team-a"), "read_write").await.unwrap().token;
+        let unscoped = storage.issue_token("ops", None, "read_write").await.unwrap().token;
+        let connect = |token: Option<&str>| {
+            let url = match token {
+                Some(token) => format!("ws://{}/ws?token={}", addr, token),
+                None => format!("ws://{}/ws", addr),
+            };
+            async move { tokio_tungstenite::connect_async(url).await.unwrap().0 }
+        };
+        let subscribe = |id: u64, project: Option<&str>| {
+            let request = json!({ "jsonrpc": "2.0", "id": id, "method": "subscribe", "params": { "project_name": project } });
+            ClientMessage::text(request.to_string())
+        };
+
+        // Anonymous sockets must authenticate before subscribing
+        let mut anonymous = connect(None).await;
+        anonymous.send(subscribe(1, None)).await.unwrap();
+        let refused = next_json(&mut anonymous).await;
+        assert_eq!(refused["error"]["code"], mpcm_core::error::codes::UNAUTHENTICATED);
+
+        let mut writer = connect(Some(&unscoped)).await;
+        let mut tenant = connect(Some(&team_a)).await;
+        for (id, project, client) in [(1, "beta", &mut writer), (2, "alpha", &mut tenant)] {
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "store_context",
+                "params": { "project_name": project, "key": "k", "type": "note", "value": "v" },
+            });
+            client.send(ClientMessage::text(request.to_string())).await.unwrap();
+            assert!(next_json(client).await["error"].is_null());
+        }
+
+        // Projects outside the tenant cannot be watched, by name or otherwise
+        tenant.send(subscribe(3, Some("beta"))).await.unwrap();
+        let hidden = next_json(&mut tenant).await;
+        assert_eq!(hidden["error"]["code"], mpcm_core::error::codes::PROJECT_NOT_FOUND);
+        tenant.send(subscribe(4, None)).await.unwrap();
+        assert!(next_json(&mut tenant).await["error"].is_null());
+
+        for project in ["beta", "alpha"] {
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": project,
+                "method": "store_context",
+                "params": { "project_name": project, "key": "k2", "type": "note", "value": "v" },
+            });
+            writer.send(ClientMessage::text(request.to_string())).await.unwrap();
+            assert!(next_json(&mut writer).await["error"].is_null());
+        }
+        let notification = next_json(&mut tenant).await;
+        assert_eq!(notification["params"]["project_name"], "alpha");
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let addr = start(&temp_dir, 1).await;
+        let url = format!("ws://{}/ws", addr);
+
+        let (_first, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        assert!(tokio_tungstenite::connect_async(&url).await.is_err());
+    }
+}